@@ -0,0 +1,258 @@
+//! CONNECT-UDP proxy, and a client relaying a local UDP socket through it
+//!
+//! Everything runs on loopback, the client forwarding what it receives on `--local` to the
+//! `--target` through the proxy:
+//!
+//! ```sh
+//! cargo run --example h3_udp_proxy -- echo &
+//! cargo run --example h3_udp_proxy -- proxy &
+//! cargo run --example h3_udp_proxy -- client &
+//! nc -u 127.0.0.1 5000
+//! ```
+
+use std::{fs, io, net::SocketAddr};
+
+use anyhow::{anyhow, Context, Result};
+use futures::StreamExt;
+use http::{Method, Response, StatusCode};
+use quinn::{Certificate, CertificateChain, PrivateKey};
+use structopt::{self, StructOpt};
+use tokio::net::{lookup_host, UdpSocket};
+use tracing::{error, info};
+use tracing_subscriber::filter::LevelFilter;
+
+use quinn_h3::{
+    client,
+    connect_udp::{Target, UdpTunnel, UriTemplate},
+    server::{self, RecvRequest},
+    Body, Protocol, Settings,
+};
+
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(name = "h3_udp_proxy")]
+enum Opt {
+    /// Run the proxy
+    Proxy {
+        /// Address to listen on
+        #[structopt(long = "listen", default_value = "[::1]:4433")]
+        listen: SocketAddr,
+    },
+    /// Relay datagrams received on a local socket to the target, through the proxy
+    Client {
+        /// Address of the proxy
+        #[structopt(long = "proxy", default_value = "[::1]:4433")]
+        proxy: SocketAddr,
+        /// Local address to receive datagrams on
+        #[structopt(long = "local", default_value = "127.0.0.1:5000")]
+        local: SocketAddr,
+        /// Address the proxy relays datagrams to
+        #[structopt(long = "target", default_value = "127.0.0.1:6000")]
+        target: SocketAddr,
+    },
+    /// Send datagrams back to their sender, to be used as a target
+    Echo {
+        /// Address to listen on
+        #[structopt(long = "listen", default_value = "127.0.0.1:6000")]
+        listen: SocketAddr,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_env_filter(
+                tracing_subscriber::EnvFilter::from_default_env()
+                    .add_directive(LevelFilter::INFO.into()),
+            )
+            .finish(),
+    )?;
+
+    match Opt::from_args() {
+        Opt::Proxy { listen } => proxy(listen).await,
+        Opt::Client {
+            proxy,
+            local,
+            target,
+        } => relay(proxy, local, target).await,
+        Opt::Echo { listen } => echo(listen).await,
+    }
+}
+
+async fn proxy(listen: SocketAddr) -> Result<()> {
+    let (cert, key) = build_certs()?;
+    let mut settings = Settings::new();
+    settings.set_enable_connect_protocol(true).set_h3_datagram(true);
+
+    let mut server = server::Builder::default();
+    server
+        .listen(listen)
+        .settings(settings)
+        .certificate(CertificateChain::from_certs(vec![cert]), key)
+        .expect("failed to add cert");
+    let mut incoming = server.build().expect("bind failed");
+    info!("proxy listening on {}", listen);
+
+    while let Some(connecting) = incoming.next().await {
+        tokio::spawn(async move {
+            let mut incoming_request = match connecting.await {
+                Ok(incoming_request) => incoming_request,
+                Err(e) => {
+                    error!("handshake failed: {:?}", e);
+                    return;
+                }
+            };
+            while let Some(request) = incoming_request.next().await {
+                tokio::spawn(async move {
+                    if let Err(e) = handle_request(request).await {
+                        error!("request failed: {:?}", e);
+                    }
+                });
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn handle_request(recv_request: RecvRequest) -> Result<()> {
+    let (request, mut sender) = recv_request.await?;
+    let template = UriTemplate::well_known("localhost")?;
+
+    // Only the path is matched, the proxy answers on any authority
+    let target = match template.target(request.uri()) {
+        Some(target)
+            if request.method() == Method::CONNECT
+                && request.extensions().get::<Protocol>() == Some(&Protocol::CONNECT_UDP) =>
+        {
+            target
+        }
+        _ => {
+            let response = Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(()))?;
+            sender.send_response(response).await?;
+            return Ok(());
+        }
+    };
+
+    let (socket, addr) = match bind_target(&target).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!("failed to reach {}: {}", target, e);
+            let response = Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::from(()))?;
+            sender.send_response(response).await?;
+            return Ok(());
+        }
+    };
+
+    info!("proxying UDP to {}", target);
+    let tunnel = sender.accept_connect_udp(request).await?;
+    forward(tunnel, socket, Some(addr)).await
+}
+
+async fn bind_target(target: &Target) -> Result<(UdpSocket, SocketAddr)> {
+    let addr = lookup_host(target.to_string())
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("no address found"))?;
+    let bind: SocketAddr = if addr.is_ipv4() {
+        "0.0.0.0:0".parse()?
+    } else {
+        "[::]:0".parse()?
+    };
+    Ok((UdpSocket::bind(bind).await?, addr))
+}
+
+async fn relay(proxy: SocketAddr, local: SocketAddr, target: SocketAddr) -> Result<()> {
+    let (cert, _) = build_certs()?;
+    let mut settings = Settings::new();
+    settings.set_h3_datagram(true);
+
+    let mut client_builder = client::Builder::default();
+    client_builder.settings(settings);
+    client_builder.add_certificate_authority(cert)?;
+    let client = client_builder.build()?;
+    let conn = client.connect(&proxy, "localhost")?.await?;
+
+    let template = UriTemplate::well_known(&format!("localhost:{}", proxy.port()))?;
+    let target = Target::new(target.ip().to_string(), target.port());
+    let tunnel = conn.connect_udp(&template, &target).await?;
+
+    let socket = UdpSocket::bind(local).await?;
+    info!("relaying datagrams from {} to {}", local, target);
+    forward(tunnel, socket, None).await
+}
+
+// Forward datagrams between a tunnel and a socket until the peer closes the tunnel, exchanging
+// them with `target`, or with the last address the socket received one from
+async fn forward(
+    mut tunnel: UdpTunnel,
+    mut socket: UdpSocket,
+    target: Option<SocketAddr>,
+) -> Result<()> {
+    enum Event {
+        Socket(usize, SocketAddr),
+        Tunnel(Option<Result<bytes::Bytes, quinn_h3::Error>>),
+    }
+
+    let mut buf = vec![0; 65_535];
+    let mut peer = target;
+    loop {
+        let event = tokio::select! {
+            res = socket.recv_from(&mut buf) => {
+                let (len, from) = res?;
+                Event::Socket(len, from)
+            }
+            payload = tunnel.recv() => Event::Tunnel(payload),
+        };
+        match event {
+            Event::Socket(_, from) if target.map_or(false, |t| t != from) => (),
+            Event::Socket(len, from) => {
+                peer = Some(from);
+                tunnel.send(&buf[..len]).await?;
+            }
+            Event::Tunnel(Some(payload)) => {
+                let payload = payload?;
+                if let Some(peer) = peer {
+                    socket.send_to(&payload, &peer).await?;
+                }
+            }
+            Event::Tunnel(None) => return Ok(()),
+        }
+    }
+}
+
+async fn echo(listen: SocketAddr) -> Result<()> {
+    let mut socket = UdpSocket::bind(listen).await?;
+    info!("echoing datagrams on {}", listen);
+    let mut buf = vec![0; 65_535];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await?;
+        socket.send_to(&buf[..len], &from).await?;
+    }
+}
+
+fn build_certs() -> Result<(Certificate, PrivateKey)> {
+    let dirs = directories::ProjectDirs::from("org", "quinn", "quinn-examples").unwrap();
+    let path = dirs.data_local_dir();
+    let cert_path = path.join("cert.der");
+    let key_path = path.join("key.der");
+    let (cert, key) = match fs::read(&cert_path).and_then(|x| Ok((x, fs::read(&key_path)?))) {
+        Ok(x) => x,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("generating self-signed certificate");
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+            let key = cert.serialize_private_key_der();
+            let cert = cert.serialize_der().unwrap();
+            fs::create_dir_all(&path).context("failed to create certificate directory")?;
+            fs::write(&cert_path, &cert).context("failed to write certificate")?;
+            fs::write(&key_path, &key).context("failed to write private key")?;
+            (cert, key)
+        }
+        Err(e) => return Err(e).context("failed to read certificate"),
+    };
+    Ok((Certificate::from_der(&cert)?, PrivateKey::from_der(&key)?))
+}
@@ -11,14 +11,14 @@ use http_body::Body as HttpBody;
 use quinn_proto::StreamId;
 
 use crate::{
-    connection::ConnectionRef,
+    connection::{ConnectionRef, RequestGuard},
     frame::FrameStream,
     proto::{
         frame::{HeadersFrame, HttpFrame},
         ErrorCode,
     },
     streams::Reset,
-    Error,
+    Error, Priority,
 };
 
 /// Simple body representation
@@ -29,6 +29,7 @@ use crate::{
 ///
 /// [`http::Request<B>`]: https://docs.rs/http/*/http/request/index.html
 /// [`http::Response<B>`]: https://docs.rs/http/*/http/response/index.html
+#[derive(Clone)]
 pub struct Body(pub(crate) Option<Bytes>);
 
 impl From<()> for Body {
@@ -82,6 +83,8 @@ pub struct RecvBody {
     stream_id: StreamId,
     recv: FrameStream,
     trailers: Option<HeadersFrame>,
+    // Keeps the client's request in flight until the body is dropped
+    guard: Option<RequestGuard>,
 }
 
 impl RecvBody {
@@ -91,9 +94,18 @@ impl RecvBody {
             stream_id,
             recv,
             trailers: None,
+            guard: None,
         }
     }
 
+    pub(crate) fn hold(&mut self, guard: RequestGuard) {
+        self.guard = Some(guard);
+    }
+
+    pub(crate) fn into_inner(self) -> FrameStream {
+        self.recv
+    }
+
     /// Convenience method to read the entire body in one call
     pub async fn read_to_end(&mut self) -> Result<Bytes, Error> {
         let mut body = BytesMut::with_capacity(10_240);
@@ -133,6 +145,17 @@ impl RecvBody {
     pub fn cancel(&mut self) {
         self.recv.reset(ErrorCode::REQUEST_CANCELLED);
     }
+
+    /// Change the priority of the request this response body belongs to
+    ///
+    /// Only clients can do this, it has no effect on the server side.
+    pub fn update_priority(&mut self, priority: Priority) {
+        self.conn
+            .h3
+            .lock()
+            .unwrap()
+            .update_priority(self.stream_id, priority);
+    }
 }
 
 impl HttpBody for RecvBody {
@@ -147,6 +170,12 @@ impl HttpBody for RecvBody {
             return match ready!(Pin::new(&mut self.recv).poll_next(cx)) {
                 None => Poll::Ready(None),
                 Some(Ok(HttpFrame::Reserved)) => continue,
+                Some(Ok(HttpFrame::PushPromise(p))) => {
+                    match self.conn.on_push_promise(self.stream_id, p) {
+                        Ok(()) => continue,
+                        Err(e) => Poll::Ready(Some(Err(e))),
+                    }
+                }
                 Some(Ok(HttpFrame::Data(d))) => Poll::Ready(Some(Ok(d.payload))),
                 Some(Ok(HttpFrame::Headers(t))) => {
                     self.trailers = Some(t);
@@ -70,26 +70,37 @@
 use std::{
     error::Error as StdError,
     future::Future,
+    io,
     net::SocketAddr,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use futures::{channel::oneshot, ready, FutureExt};
-use http::{request, Request, Response};
+use futures::{channel::oneshot, ready, FutureExt, Stream};
+use http::{
+    header::EXPECT, request, uri::Authority, HeaderMap, HeaderValue, Method, Request, Response,
+    StatusCode, Uri,
+};
 use http_body::Body as HttpBody;
 use pin_project::{pin_project, project};
-use quinn::{Certificate, Endpoint, OpenBi, RecvStream};
+use quinn::{Certificate, Endpoint, OpenBi, RecvStream, SendStream};
 use quinn_proto::{Side, StreamId};
+use tokio::net::lookup_host;
 use tracing::trace;
 
 use crate::{
     body::RecvBody,
-    connection::{ConnectionDriver, ConnectionRef},
+    connect_udp::{Target, UdpTunnel, UriTemplate},
+    connection::{ConnectionDriver, ConnectionRef, RequestGuard},
     data::RecvData,
+    datagram::{RequestDatagrams, CAPSULE_PROTOCOL},
     frame::FrameDecoder,
-    proto::{headers::Header, settings::Settings, ErrorCode},
-    Error, SendData, ZeroRttAccepted,
+    headers::SendHeaders,
+    pool::Pool,
+    proto::{frame::HeadersFrame, headers::Header, settings::Settings, ErrorCode},
+    tunnel::Tunnel,
+    webtransport::WebTransportSession,
+    Error, HttpError, Priority, Protocol, SendData, ZeroRttAccepted,
 };
 use futures_util::future;
 
@@ -124,6 +135,7 @@ use futures_util::future;
 pub struct Builder {
     settings: Settings,
     client_config: quinn::ClientConfigBuilder,
+    max_pushes: u64,
 }
 
 impl Default for Builder {
@@ -134,6 +146,7 @@ impl Default for Builder {
         Self {
             client_config,
             settings: Settings::new(),
+            max_pushes: 0,
         }
     }
 }
@@ -145,6 +158,17 @@ impl Builder {
         self
     }
 
+    /// Maximum number of responses the server may push on each connection
+    ///
+    /// Server push is disabled when this is 0, which is the default. Promised requests are
+    /// received with [`Connection::incoming_pushes()`].
+    ///
+    /// [`Connection::incoming_pushes()`]: struct.Connection.html#method.incoming_pushes
+    pub fn max_pushes(&mut self, count: u64) -> &mut Self {
+        self.max_pushes = count;
+        self
+    }
+
     /// Create a new client
     ///
     /// This method spawns a task for the QUIC endpoint's IO management, therefore it must
@@ -157,6 +181,8 @@ impl Builder {
         Ok(Client {
             endpoint,
             settings: self.settings,
+            max_pushes: self.max_pushes,
+            pool: Pool::new(),
         })
     }
 
@@ -181,6 +207,7 @@ impl Builder {
         Self {
             client_config,
             settings: Settings::new(),
+            max_pushes: 0,
         }
     }
 
@@ -192,13 +219,16 @@ impl Builder {
         Client {
             endpoint,
             settings: self.settings,
+            max_pushes: self.max_pushes,
+            pool: Pool::new(),
         }
     }
 }
 
 /// Client endpoint for the HTTP/3 protocol
 ///
-/// This lets you connect to HTTP/3 servers with given settings.
+/// This lets you connect to HTTP/3 servers with given settings, or send requests through
+/// connections it manages with [`request()`].
 ///
 /// It also contains saved crypto sessions, so a new connection can accept [`0-RTT`] exchanges when
 /// a prior connection to the same server as already suceeded.
@@ -213,9 +243,12 @@ impl Builder {
 /// # }
 /// ```
 /// [`0-RTT`]: struct.Connecting.html#method.into_0rtt
+/// [`request()`]: #method.request
 pub struct Client {
     endpoint: Endpoint,
     settings: Settings,
+    max_pushes: u64,
+    pool: Pool,
 }
 impl Default for Client {
     /// Create a new HTTP/3 client endpoint with crate's recomended settings
@@ -244,6 +277,7 @@ impl Client {
     ) -> Result<Connecting, quinn::ConnectError> {
         Ok(Connecting {
             settings: self.settings.clone(),
+            max_pushes: self.max_pushes,
             connecting: self.endpoint.connect(addr, server_name)?,
         })
     }
@@ -262,12 +296,115 @@ impl Client {
     ) -> Result<Connecting, quinn::ConnectError> {
         Ok(Connecting {
             settings: self.settings.clone(),
+            max_pushes: self.max_pushes,
             connecting: self
                 .endpoint
                 .connect_with(client_config, addr, server_name)?,
         })
     }
 
+    /// Send a request through a pooled connection
+    ///
+    /// Connections are kept for each authority requests are sent to, and reused by later
+    /// requests. A new one is established when none can open a request stream without exceeding
+    /// the server's limit, or when the server sent a `GOAWAY` or closed them, after an idle
+    /// timeout for example. Concurrent requests needing a new connection to the same authority
+    /// wait for a single one to be established. The authority is resolved, and the server
+    /// authenticated, with the host of the request's URI.
+    ///
+    /// Requests the server did not process, because it rejected them or sent a `GOAWAY` excluding
+    /// them, are sent again, up to 3 times, which is why their body must be cloneable.
+    ///
+    /// ```
+    /// # use anyhow::Result;
+    /// use http::Request;
+    /// use quinn_h3::{client::Client, Body};
+    ///
+    /// async fn get_things(client: &Client) -> Result<()> {
+    ///     for path in &["/things/1", "/things/2"] {
+    ///         let request = Request::get(format!("https://example.com{}", path))
+    ///             .body(Body::from(()))?;
+    ///         let mut response = client.request(request).await?;
+    ///         let body = response.body_mut().read_to_end().await?;
+    ///         println!("{}: {:?}", path, body);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn request<B>(&self, request: Request<B>) -> Result<Response<RecvBody>, Error>
+    where
+        B: HttpBody + Clone + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
+    {
+        let (parts, body) = request.into_parts();
+        let mut retries = 0;
+        loop {
+            let conn = self.pooled_connection(&parts.uri).await?;
+
+            let mut request = Request::new(body.clone());
+            *request.method_mut() = parts.method.clone();
+            *request.uri_mut() = parts.uri.clone();
+            *request.version_mut() = parts.version;
+            *request.headers_mut() = parts.headers.clone();
+            if let Some(protocol) = parts.extensions.get::<Protocol>() {
+                request.extensions_mut().insert(protocol.clone());
+            }
+
+            let (send, recv) = request_pair(conn.clone(), request);
+            futures::pin_mut!(send);
+            match future::try_join(send.as_mut(), recv).await {
+                Ok((_, response)) => return Ok(response),
+                Err(e) if retries < MAX_REQUEST_RETRIES && is_unprocessed(&conn, &send, &e) => {
+                    retries += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Get a connection to the authority of `uri` from the pool, or establish one
+    async fn pooled_connection(&self, uri: &Uri) -> Result<ConnectionRef, Error> {
+        let host = uri
+            .host()
+            .ok_or_else(|| Error::internal("request URI without host"))?;
+        let authority = format!("{}:{}", host, uri.port_u16().unwrap_or(DEFAULT_PORT));
+        if let Some(conn) = self.pool.get(&authority) {
+            return Ok(conn);
+        }
+
+        // Requests racing for a new connection wait for the first one to establish it
+        let lock = self.pool.connect_lock(&authority);
+        let guard = lock.lock().await;
+        let result = match self.pool.get(&authority) {
+            Some(conn) => Ok(conn),
+            None => {
+                let result = self.establish(host, &authority).await;
+                if let Ok(conn) = &result {
+                    self.pool.insert(authority.clone(), conn.clone());
+                }
+                result
+            }
+        };
+        drop(guard);
+        self.pool.release(&authority, lock);
+        result
+    }
+
+    async fn establish(&self, host: &str, authority: &str) -> Result<ConnectionRef, Error> {
+        let addr = lookup_host(authority)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for host"))?;
+        let server_name = host.trim_start_matches('[').trim_end_matches(']');
+        let mut connecting = self.connect(&addr, server_name)?;
+        future::poll_fn(|cx| connecting.poll_ref(cx)).await
+    }
+
+    #[cfg(test)]
+    pub(crate) fn pooled(&self, authority: &str) -> usize {
+        self.pool.len(authority)
+    }
+
     /// Wait for all connections on the endpoint to be cleanly shut down
     ///
     /// Waiting for this condition before exiting ensures that a good-faith effort is made to notify
@@ -292,6 +429,7 @@ impl Client {
 pub struct Connecting {
     connecting: quinn::Connecting,
     settings: Settings,
+    max_pushes: u64,
 }
 
 impl Connecting {
@@ -354,36 +492,44 @@ impl Connecting {
         let Self {
             connecting,
             settings,
+            max_pushes,
         } = self;
         match connecting.into_0rtt() {
             Err(connecting) => Err(Self {
                 connecting,
                 settings,
+                max_pushes,
             }),
             Ok((new_conn, zero_rtt)) => {
                 let quinn::NewConnection {
                     connection,
                     uni_streams,
                     bi_streams,
+                    datagrams,
                     ..
                 } = new_conn;
-                let conn_ref =
-                    ConnectionRef::new(connection, Side::Client, uni_streams, bi_streams, settings);
+                let conn_ref = ConnectionRef::new(
+                    connection,
+                    Side::Client,
+                    uni_streams,
+                    bi_streams,
+                    datagrams,
+                    settings,
+                );
+                conn_ref.h3.lock().unwrap().inner.allow_pushes(max_pushes);
                 tokio::spawn(ConnectionDriver(conn_ref.clone()));
                 Ok((Connection(conn_ref), zero_rtt))
             }
         }
     }
-}
-
-impl Future for Connecting {
-    type Output = Result<Connection, Error>;
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    // Complete the handshake, without the `Connection` closing it when dropped
+    fn poll_ref(&mut self, cx: &mut Context<'_>) -> Poll<Result<ConnectionRef, Error>> {
         let quinn::NewConnection {
             connection,
             uni_streams,
             bi_streams,
+            datagrams,
             ..
         } = ready!(Pin::new(&mut self.connecting).poll(cx))?;
         let conn_ref = ConnectionRef::new(
@@ -391,10 +537,25 @@ impl Future for Connecting {
             Side::Client,
             uni_streams,
             bi_streams,
+            datagrams,
             self.settings.clone(),
         );
+        conn_ref
+            .h3
+            .lock()
+            .unwrap()
+            .inner
+            .allow_pushes(self.max_pushes);
         tokio::spawn(ConnectionDriver(conn_ref.clone()));
-        Poll::Ready(Ok(Connection(conn_ref)))
+        Poll::Ready(Ok(conn_ref))
+    }
+}
+
+impl Future for Connecting {
+    type Output = Result<Connection, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_ref(cx).map(|r| r.map(Connection))
     }
 }
 
@@ -435,7 +596,10 @@ impl Connection {
     /// have been received and decoded.
     ///
     /// Note that both of those futures can be polled concurrently, but the reception will hang
-    /// indefinitely if transmission is not polled.
+    /// indefinitely if transmission is not polled. A request with an `expect: 100-continue` header
+    /// holds its body back until the server answers, so both need to be polled concurrently: the
+    /// body is sent once a `100 Continue` is received, or after a second without any answer, and
+    /// not at all if the final response comes first.
     ///
     /// # Example: GET request
     /// ```
@@ -467,11 +631,184 @@ impl Connection {
         B: HttpBody + 'static,
         B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
     {
-        let (open_send, open_recv) = oneshot::channel();
-        let recv = RecvResponse::new(open_recv, self.0.clone());
-        let send = SendRequest::new(open_send, self.0.clone(), request);
+        request_pair(self.0.clone(), request)
+    }
 
-        (send, recv)
+    /// Receive requests promised by the server
+    ///
+    /// Returns a `Stream` yielding a [`PushPromise`] for each request the server promised to push
+    /// a response for. Server push needs to be enabled with [`Builder::max_pushes()`].
+    ///
+    /// ```
+    /// # use anyhow::Result;
+    /// use futures::StreamExt;
+    /// use quinn_h3::client::Connection;
+    ///
+    /// async fn receive_pushes(connection: &Connection) -> Result<()> {
+    ///     let mut incoming_pushes = connection.incoming_pushes();
+    ///     while let Some(promise) = incoming_pushes.next().await {
+    ///         let (request, recv_push) = promise.await?;
+    ///         let mut response = recv_push.await?;
+    ///         let body = response.body_mut().read_to_end().await?;
+    ///         println!("pushed {}: {:?}", request.uri(), body);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// [`PushPromise`]: struct.PushPromise.html
+    /// [`Builder::max_pushes()`]: struct.Builder.html#method.max_pushes
+    pub fn incoming_pushes(&self) -> IncomingPush {
+        IncomingPush(self.0.clone())
+    }
+
+    /// Open a WebTransport session
+    ///
+    /// Sends an extended `CONNECT` request to `uri`, resolving once the server accepted the
+    /// session. WebTransport must be enabled in the [`Settings`] of both peers, this waits for
+    /// the server's to be received.
+    ///
+    /// ```
+    /// # use anyhow::Result;
+    /// use futures::AsyncWriteExt;
+    /// use quinn_h3::client::Connection;
+    ///
+    /// async fn hello(connection: &Connection) -> Result<()> {
+    ///     let session = connection.webtransport("https://example.com/echo".parse()?).await?;
+    ///     let mut send = session.open_uni().await?;
+    ///     send.write_all(b"hello").await?;
+    ///     send.finish().await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// [`Settings`]: ../struct.Settings.html
+    pub async fn webtransport(&self, uri: Uri) -> Result<WebTransportSession, Error> {
+        if !self.0.h3.lock().unwrap().webtransport_enabled() {
+            return Err(Error::internal(
+                "WebTransport is disabled in local settings",
+            ));
+        }
+        if !self.remote_supports(Settings::enable_webtransport).await? {
+            return Err(Error::Unsupported("WebTransport".into()));
+        }
+
+        let (send, recv) = self.0.quic.open_bi().await?;
+        let stream_id = send.id();
+        let mut header = Header::request(Method::CONNECT, uri, HeaderMap::new());
+        header.set_protocol(Protocol::WEB_TRANSPORT);
+
+        // Streams the server opens for the session can arrive before its response
+        self.0.h3.lock().unwrap().register_session(stream_id);
+        match self.send_connect(header, send, recv).await {
+            Ok((send, body)) => Ok(WebTransportSession::new(
+                self.0.clone(),
+                send,
+                body.into_inner(),
+            )),
+            Err(e) => {
+                self.0.h3.lock().unwrap().close_session(stream_id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Proxy UDP through the server
+    ///
+    /// Sends a CONNECT-UDP request for `target`, built from the proxy's `template`, resolving
+    /// once the proxy accepted it. The server must have enabled the extended `CONNECT` in its
+    /// [`Settings`], this waits for them to be received.
+    ///
+    /// [`Settings`]: ../struct.Settings.html
+    pub async fn connect_udp(
+        &self,
+        template: &UriTemplate,
+        target: &Target,
+    ) -> Result<UdpTunnel, Error> {
+        if !self
+            .remote_supports(Settings::enable_connect_protocol)
+            .await?
+        {
+            return Err(Error::Unsupported("extended CONNECT".into()));
+        }
+
+        let (send, recv) = self.0.quic.open_bi().await?;
+        let stream_id = send.id();
+        let mut headers = HeaderMap::new();
+        headers.insert(CAPSULE_PROTOCOL, HeaderValue::from_static("?1"));
+        let mut header = Header::request(Method::CONNECT, template.expand(target), headers);
+        header.set_protocol(Protocol::CONNECT_UDP);
+
+        // Registered before the request is sent, so the proxy's first datagrams are not dropped
+        let datagrams = match self.0.h3.lock().unwrap().register_datagrams(stream_id) {
+            Ok(()) => Some(RequestDatagrams::new(self.0.clone(), stream_id)),
+            Err(_) => None,
+        };
+        let (send, body) = self.send_connect(header, send, recv).await?;
+        Ok(UdpTunnel::new(self.0.clone(), send, body, datagrams))
+    }
+
+    /// Open a tunnel to `authority` through the server
+    ///
+    /// Sends a `CONNECT` request, resolving once the server connected to `authority` and accepted
+    /// it. Fails with [`Error::ConnectRefused`] otherwise.
+    ///
+    /// [`Error::ConnectRefused`]: ../enum.Error.html#variant.ConnectRefused
+    pub async fn connect_tunnel(&self, authority: Authority) -> Result<Tunnel, Error> {
+        let (send, recv) = self.0.quic.open_bi().await?;
+        let header = Header::request(Method::CONNECT, Uri::from(authority), HeaderMap::new());
+        let (send, body) = self.send_connect(header, send, recv).await?;
+        Ok(Tunnel::new(self.0.clone(), send, body))
+    }
+
+    // Wait for the server's settings, to check whether it supports an extension
+    async fn remote_supports(&self, supported: fn(&Settings) -> bool) -> Result<bool, Error> {
+        future::poll_fn(|cx| {
+            let mut conn = self.0.h3.lock().unwrap();
+            conn.poll_remote_settings(cx).map(|r| r.map(supported))
+        })
+        .await
+    }
+
+    // Send a CONNECT request, resolving once the server accepted it
+    async fn send_connect(
+        &self,
+        header: Header,
+        send: SendStream,
+        recv: RecvStream,
+    ) -> Result<(SendStream, RecvBody), Error> {
+        let stream_id = send.id();
+        let guard = RequestGuard::new(self.0.clone(), stream_id);
+        let send = SendHeaders::new(header, &self.0, send, stream_id)?.await?;
+        let (headers, body) =
+            RecvData::new(FrameDecoder::stream(recv), self.0.clone(), stream_id).await?;
+        let response = build_response(headers, body)?;
+        if !response.status().is_success() {
+            return Err(Error::ConnectRefused(response.status()));
+        }
+        // Finished by the tunnel or session once closed
+        guard.keep();
+        Ok((send, response.into_body()))
+    }
+
+    /// ID of the first request stream the server will not process, once it sent a `GOAWAY`
+    ///
+    /// A server shutting down first sends the largest possible ID, asking not to send any more
+    /// requests on this connection, then the ID of the first request it did not receive. Requests
+    /// whose [`SendRequest::stream_id()`] is lower are processed as usual, the others are rejected
+    /// with `H3_REQUEST_REJECTED` and can safely be retried on a new connection.
+    ///
+    /// [`SendRequest::stream_id()`]: struct.SendRequest.html#method.stream_id
+    pub fn go_away_id(&self) -> Option<StreamId> {
+        self.0.h3.lock().unwrap().inner.peer_go_away()
+    }
+
+    /// Wait for the connection to be closed
+    ///
+    /// Resolves with the reason it was closed for, by either peer. A connection the server went
+    /// away from is closed once its requests in flight are finished.
+    pub async fn closed(&self) -> quinn::ConnectionError {
+        self.0.quic.closed().await
     }
 
     /// Close the connection immediately
@@ -507,7 +844,10 @@ pub struct SendRequest<B, D> {
     #[pin]
     state: SendRequestState<B, D>,
     open: OpenBi,
-    chan: Option<oneshot::Sender<(RecvStream, StreamId)>>,
+    chan: Option<oneshot::Sender<(RecvStream, RequestGuard)>>,
+    stream_id: Option<StreamId>,
+    datagrams: Option<oneshot::Sender<StreamId>>,
+    proceed: Option<oneshot::Receiver<bool>>,
 }
 
 impl<B> SendRequest<B, B::Data>
@@ -516,7 +856,8 @@ where
     B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
 {
     pub(crate) fn new(
-        open_send: oneshot::Sender<(RecvStream, StreamId)>,
+        open_send: oneshot::Sender<(RecvStream, RequestGuard)>,
+        proceed: oneshot::Receiver<bool>,
         conn: ConnectionRef,
         request: Request<B>,
     ) -> Self {
@@ -527,9 +868,41 @@ where
             chan: Some(open_send),
             request: Some(request),
             state: SendRequestState::Opening,
+            stream_id: None,
+            datagrams: None,
+            proceed: Some(proceed),
         }
     }
 
+    /// Datagrams associated with this request
+    ///
+    /// HTTP datagrams must be enabled in the local [`Settings`]. They can only be taken once per
+    /// request. Datagrams are usable once the request stream is opened, when this is polled.
+    ///
+    /// [`Settings`]: ../struct.Settings.html
+    pub fn datagrams(&mut self) -> Result<RequestDatagrams, Error> {
+        if let Some(stream_id) = self.stream_id {
+            self.conn.h3.lock().unwrap().register_datagrams(stream_id)?;
+            return Ok(RequestDatagrams::new(self.conn.clone(), stream_id));
+        }
+        if !self.conn.h3.lock().unwrap().datagrams_enabled() {
+            return Err(Error::internal(
+                "HTTP datagrams are disabled in local settings",
+            ));
+        }
+        if self.datagrams.is_some() {
+            return Err(Error::internal("datagrams already taken"));
+        }
+        let (send, recv) = oneshot::channel();
+        self.datagrams = Some(send);
+        Ok(RequestDatagrams::pending(self.conn.clone(), recv))
+    }
+
+    /// ID of the request stream, `None` until it is opened, when this is polled
+    pub fn stream_id(&self) -> Option<StreamId> {
+        self.stream_id
+    }
+
     /// Cancel the request
     ///
     /// The peer will receive a request error with `REQUEST_CANCELLED` code.
@@ -565,6 +938,7 @@ where
                                 method,
                                 uri,
                                 headers,
+                                extensions,
                                 ..
                             } = parts;
 
@@ -574,14 +948,36 @@ where
                                 return Poll::Ready(Err(err));
                             }
 
+                            let guard = RequestGuard::new(me.conn.clone(), send.id());
                             me.chan
                                 .take()
                                 .unwrap()
-                                .send((recv, send.id()))
+                                .send((recv, guard))
                                 .map_err(|_| Error::internal("SendRequest chan cancelled"))?;
 
-                            let header = Header::request(method, uri, headers);
-                            let send = SendData::new(send, me.conn.clone(), header, body, false);
+                            let stream_id = send.id();
+                            *me.stream_id = Some(stream_id);
+                            if let Some(datagrams) = me.datagrams.take() {
+                                let mut conn = me.conn.h3.lock().unwrap();
+                                if conn.register_datagrams(stream_id).is_ok()
+                                    && datagrams.send(stream_id).is_err()
+                                {
+                                    conn.close_datagrams(stream_id);
+                                }
+                            }
+
+                            let expect_continue = headers.get(EXPECT).map_or(false, |v| {
+                                v.as_bytes().eq_ignore_ascii_case(b"100-continue")
+                            });
+                            let mut header = Header::request(method, uri, headers);
+                            if let Some(protocol) = extensions.get::<Protocol>() {
+                                header.set_protocol(protocol.clone());
+                            }
+                            let mut send =
+                                SendData::new(send, me.conn.clone(), header, body, false);
+                            if expect_continue {
+                                send.expect_continue(me.proceed.take().unwrap());
+                            }
                             me.state.set(SendRequestState::Sending(send));
                         }
                     }
@@ -607,7 +1003,8 @@ enum SendRequestState<B, D> {
 /// headers are received and decoded.
 ///
 /// Upon success, it will yield a [`Response`], containing an instance of [`RecvBody`] enabling
-/// to stream data in.
+/// to stream data in. Informational `1xx` responses received before are skipped, unless read with
+/// [`informational()`].
 ///
 /// # Example
 /// ```
@@ -638,22 +1035,31 @@ enum SendRequestState<B, D> {
 /// [`Connection::send_request()`]: struct.Connection.htm#method.send_request
 /// [`Response`]: https://docs.rs/http/*/http/response/index.html
 /// [`RecvBody`]: ../struct.RecvBody.html
+/// [`informational()`]: #method.informational
 pub struct RecvResponse {
     state: RecvResponseState,
     conn: ConnectionRef,
     stream_id: Option<StreamId>,
+    // Handed over to the response body
+    guard: Option<RequestGuard>,
     recv: Option<RecvData>,
+    priority: Option<Priority>,
+    // Tells `SendRequest` whether to send a body held back by `expect: 100-continue`
+    proceed: Option<oneshot::Sender<bool>>,
+    // Final response, or error, received while reading informational responses
+    response: Option<Result<Response<RecvBody>, Error>>,
 }
 
 enum RecvResponseState {
-    Opening(oneshot::Receiver<(RecvStream, StreamId)>),
+    Opening(oneshot::Receiver<(RecvStream, RequestGuard)>),
     Receiving,
     Finished,
 }
 
 impl RecvResponse {
     pub(crate) fn new(
-        recv: oneshot::Receiver<(RecvStream, StreamId)>,
+        recv: oneshot::Receiver<(RecvStream, RequestGuard)>,
+        proceed: oneshot::Sender<bool>,
         conn: ConnectionRef,
     ) -> Self {
         Self {
@@ -661,6 +1067,57 @@ impl RecvResponse {
             recv: None,
             state: RecvResponseState::Opening(recv),
             stream_id: None,
+            guard: None,
+            priority: None,
+            proceed: Some(proceed),
+            response: None,
+        }
+    }
+
+    /// Informational responses received before the final one
+    ///
+    /// Servers can send any number of `1xx` responses, such as `103 Early Hints`, ahead of the
+    /// final response. The returned `Stream` yields them as they arrive, and ends once the final
+    /// response is received, which this future then resolves to. Those not read are ignored.
+    ///
+    /// ```
+    /// # use anyhow::Result;
+    /// use futures::StreamExt;
+    /// use http::{header::LINK, Request};
+    /// use quinn_h3::{client::Connection, Body};
+    ///
+    /// async fn early_hints(connection: &Connection) -> Result<()> {
+    ///     let request = Request::get("https://example.com/").body(Body::from(()))?;
+    ///     let (send_request, mut recv_response) = connection.send_request(request);
+    ///     send_request.await?;
+    ///
+    ///     let mut informational = recv_response.informational();
+    ///     while let Some(response) = informational.next().await {
+    ///         for link in response.headers().get_all(LINK) {
+    ///             println!("preloading {:?}", link);
+    ///         }
+    ///     }
+    ///
+    ///     let response = recv_response.await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn informational(&mut self) -> Informational<'_> {
+        Informational(self)
+    }
+
+    /// Change the priority of the request
+    ///
+    /// The initial priority is given by the request's `priority` header. This lets the server
+    /// know the client's needs have changed, it can be called again on the response's
+    /// [`RecvBody`] once headers are received.
+    ///
+    /// [`RecvBody`]: ../struct.RecvBody.html
+    pub fn update_priority(&mut self, priority: Priority) {
+        match self.stream_id {
+            Some(id) => self.conn.h3.lock().unwrap().update_priority(id, priority),
+            // Sent as soon as the request stream is open
+            None => self.priority = Some(priority),
         }
     }
 
@@ -674,9 +1131,9 @@ impl RecvResponse {
             RecvResponseState::Opening(ref mut o) => {
                 future::poll_fn(|cx| {
                     Poll::Ready(match o.poll_unpin(cx) {
-                        Poll::Ready(Ok((mut r, i))) => {
+                        Poll::Ready(Ok((mut r, guard))) => {
                             let _ = r.stop(ErrorCode::REQUEST_CANCELLED.into());
-                            Some(i)
+                            Some(guard.stream_id())
                         }
                         _ => None,
                     })
@@ -696,14 +1153,13 @@ impl RecvResponse {
             self.conn.h3.lock().unwrap().cancel_request(id);
         }
 
+        self.proceed(false);
         self.state = RecvResponseState::Finished;
     }
 }
 
-impl Future for RecvResponse {
-    type Output = Result<Response<RecvBody>, crate::Error>;
-
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+impl RecvResponse {
+    fn poll_head(&mut self, cx: &mut Context) -> Poll<Result<ResponseHead, Error>> {
         loop {
             match self.state {
                 RecvResponseState::Finished => {
@@ -712,9 +1168,14 @@ impl Future for RecvResponse {
                     )))
                 }
                 RecvResponseState::Opening(ref mut open) => {
-                    let (recv, id) = ready!(open.poll_unpin(cx))
+                    let (recv, guard) = ready!(open.poll_unpin(cx))
                         .map_err(|_| Error::internal("RecvResponse channel cancelled"))?;
+                    let id = guard.stream_id();
                     self.stream_id = Some(id);
+                    self.guard = Some(guard);
+                    if let Some(priority) = self.priority.take() {
+                        self.conn.h3.lock().unwrap().update_priority(id, priority);
+                    }
                     self.recv = Some(RecvData::new(
                         FrameDecoder::stream(recv),
                         self.conn.clone(),
@@ -724,21 +1185,281 @@ impl Future for RecvResponse {
                 }
                 RecvResponseState::Receiving => {
                     let (headers, body) = ready!(self.recv.as_mut().unwrap().poll_unpin(cx))?;
+                    let mut response = build_response(headers, body)?;
+                    if !response.status().is_informational() {
+                        self.state = RecvResponseState::Finished;
+                        self.proceed(false);
+                        if let Some(guard) = self.guard.take() {
+                            response.body_mut().hold(guard);
+                        }
+                        return Poll::Ready(Ok(ResponseHead::Final(response)));
+                    }
 
-                    let (status, headers) = headers.into_response_parts()?;
-                    let mut response = Response::builder()
-                        .status(status)
-                        .version(http::version::Version::HTTP_3)
-                        .body(body)
-                        .unwrap();
-                    *response.headers_mut() = headers;
-
-                    self.state = RecvResponseState::Finished;
-                    return Poll::Ready(Ok(response));
+                    let (parts, body) = response.into_parts();
+                    match parts.status {
+                        StatusCode::SWITCHING_PROTOCOLS => {
+                            return Poll::Ready(Err(Error::peer(
+                                "101 Switching Protocols is not used in HTTP/3",
+                            )));
+                        }
+                        StatusCode::CONTINUE => self.proceed(true),
+                        _ => (),
+                    }
+                    // The final response follows on the same stream
+                    self.recv = Some(RecvData::new(
+                        body.into_inner(),
+                        self.conn.clone(),
+                        self.stream_id.unwrap(),
+                    ));
+                    return Poll::Ready(Ok(ResponseHead::Informational(Response::from_parts(
+                        parts,
+                        (),
+                    ))));
                 }
             }
         }
     }
+
+    fn proceed(&mut self, send_body: bool) {
+        if let Some(proceed) = self.proceed.take() {
+            let _ = proceed.send(send_body);
+        }
+    }
+}
+
+impl Future for RecvResponse {
+    type Output = Result<Response<RecvBody>, crate::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Some(response) = self.response.take() {
+            return Poll::Ready(response);
+        }
+        loop {
+            match ready!(self.poll_head(cx))? {
+                ResponseHead::Informational(_) => (),
+                ResponseHead::Final(response) => return Poll::Ready(Ok(response)),
+            }
+        }
+    }
+}
+
+enum ResponseHead {
+    Informational(Response<()>),
+    Final(Response<RecvBody>),
+}
+
+/// Stream of informational responses
+///
+/// Obtained with [`RecvResponse::informational()`], it yields each `1xx` response received, and
+/// ends with the final response.
+///
+/// [`RecvResponse::informational()`]: struct.RecvResponse.html#method.informational
+pub struct Informational<'a>(&'a mut RecvResponse);
+
+impl Stream for Informational<'_> {
+    type Item = Response<()>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let recv = &mut *self.0;
+        if recv.response.is_some() {
+            return Poll::Ready(None);
+        }
+        match ready!(recv.poll_head(cx)) {
+            Ok(ResponseHead::Informational(response)) => Poll::Ready(Some(response)),
+            Ok(ResponseHead::Final(response)) => {
+                recv.response = Some(Ok(response));
+                Poll::Ready(None)
+            }
+            Err(e) => {
+                recv.response = Some(Err(e));
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+// Futures sending a request on `conn` and receiving its response
+fn request_pair<B>(
+    conn: ConnectionRef,
+    request: Request<B>,
+) -> (SendRequest<B, B::Data>, RecvResponse)
+where
+    B: HttpBody + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
+{
+    let (open_send, open_recv) = oneshot::channel();
+    let (proceed_send, proceed_recv) = oneshot::channel();
+    let recv = RecvResponse::new(open_recv, proceed_send, conn.clone());
+    let send = SendRequest::new(open_send, proceed_recv, conn, request);
+    (send, recv)
+}
+
+// Whether a failed request was not processed by the server, so it can be sent again: it was
+// rejected, or its stream is beyond the last one the server accepted before going away
+fn is_unprocessed<B, D>(conn: &ConnectionRef, send: &SendRequest<B, D>, error: &Error) -> bool {
+    if let Error::Http(HttpError::RequestRejected, _) = error {
+        return true;
+    }
+    let go_away = conn.h3.lock().unwrap().inner.peer_go_away();
+    match (send.stream_id, go_away) {
+        (Some(id), Some(last)) => id.0 >= last.0,
+        _ => false,
+    }
+}
+
+fn build_response(headers: Header, body: RecvBody) -> Result<Response<RecvBody>, Error> {
+    let (status, headers) = headers.into_response_parts()?;
+    let mut response = Response::builder()
+        .status(status)
+        .version(http::version::Version::HTTP_3)
+        .body(body)
+        .unwrap();
+    *response.headers_mut() = headers;
+    Ok(response)
+}
+
+/// Stream of requests promised by the server
+///
+/// Obtained with [`Connection::incoming_pushes()`], it yields a [`PushPromise`] for each
+/// `PUSH_PROMISE` received on any request stream of the connection.
+///
+/// [`Connection::incoming_pushes()`]: struct.Connection.html#method.incoming_pushes
+/// [`PushPromise`]: struct.PushPromise.html
+pub struct IncomingPush(ConnectionRef);
+
+impl Stream for IncomingPush {
+    type Item = PushPromise;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match self.0.h3.lock().unwrap().next_push_promise(cx) {
+            Ok(Some((stream_id, frame))) => Poll::Ready(Some(PushPromise {
+                conn: self.0.clone(),
+                stream_id,
+                push_id: frame.id,
+                headers: HeadersFrame {
+                    encoded: frame.encoded,
+                },
+                done: false,
+            })),
+            Ok(None) => Poll::Pending,
+            Err(_) => Poll::Ready(None),
+        }
+    }
+}
+
+/// A request promised by the server
+///
+/// This future resolves once the promised request headers are decoded, yielding the request
+/// and a [`RecvPush`] future for the pushed response. The push can be refused with
+/// [`cancel()`], which also happens when it is dropped before resolving.
+///
+/// [`RecvPush`]: struct.RecvPush.html
+/// [`cancel()`]: #method.cancel
+pub struct PushPromise {
+    conn: ConnectionRef,
+    stream_id: StreamId,
+    push_id: u64,
+    headers: HeadersFrame,
+    // Set once the push was handed over to a `RecvPush`
+    done: bool,
+}
+
+impl PushPromise {
+    /// Cancel the push
+    ///
+    /// The server is notified with a `CANCEL_PUSH` frame and should not send the response.
+    pub fn cancel(self) {
+        // Done when dropped
+    }
+}
+
+impl Drop for PushPromise {
+    fn drop(&mut self) {
+        if !self.done {
+            self.conn.h3.lock().unwrap().cancel_push(self.push_id);
+        }
+    }
+}
+
+impl Future for PushPromise {
+    type Output = Result<(Request<()>, RecvPush), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let header = {
+            let mut conn = self.conn.h3.lock().unwrap();
+            ready!(conn.poll_decode(cx, self.stream_id, &self.headers))?
+        };
+        let (method, uri, _, headers) = header.into_request_parts()?;
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .version(http::version::Version::HTTP_3)
+            .body(())
+            .unwrap();
+        *request.headers_mut() = headers;
+
+        let recv_push = RecvPush {
+            conn: self.conn.clone(),
+            push_id: self.push_id,
+            recv: None,
+            done: false,
+        };
+        self.done = true;
+        Poll::Ready(Ok((request, recv_push)))
+    }
+}
+
+/// Receive a pushed response
+///
+/// Resolves once the server opened the push stream and the response headers are decoded.
+/// Fails with a `REQUEST_CANCELLED` error if either side cancelled the push. Dropping it before
+/// the response arrives cancels the push.
+pub struct RecvPush {
+    conn: ConnectionRef,
+    push_id: u64,
+    recv: Option<RecvData>,
+    // Set once the response was handed over to the application
+    done: bool,
+}
+
+impl RecvPush {
+    /// Cancel the push
+    ///
+    /// The server is notified with a `CANCEL_PUSH` frame, and the push stream is stopped if
+    /// it already arrived.
+    pub fn cancel(self) {
+        // Done when dropped
+    }
+}
+
+impl Drop for RecvPush {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Some(mut recv) = self.recv.take() {
+            recv.reset(ErrorCode::REQUEST_CANCELLED);
+        }
+        self.conn.h3.lock().unwrap().cancel_push(self.push_id);
+    }
+}
+
+impl Future for RecvPush {
+    type Output = Result<Response<RecvBody>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.recv.is_none() {
+            let recv = {
+                let mut conn = self.conn.h3.lock().unwrap();
+                ready!(conn.poll_push(cx, self.push_id))?
+            };
+            let stream_id = recv.get_ref().id();
+            self.recv = Some(RecvData::new(recv, self.conn.clone(), stream_id));
+        }
+        let (headers, body) = ready!(self.recv.as_mut().unwrap().poll_unpin(cx))?;
+        self.done = true;
+        Poll::Ready(build_response(headers, body))
+    }
 }
 
 #[cfg(test)]
@@ -756,3 +1477,8 @@ impl Connection {
         conn.inner.had_refs
     }
 }
+
+// Port of `https` URIs without an explicit one
+const DEFAULT_PORT: u16 = 443;
+// Times a request rejected by the server is sent again
+const MAX_REQUEST_RETRIES: usize = 3;
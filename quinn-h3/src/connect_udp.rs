@@ -0,0 +1,493 @@
+//! Proxying UDP in HTTP, also known as MASQUE CONNECT-UDP
+//!
+//! A client asks a proxy to relay UDP payloads to a [`Target`] with an extended `CONNECT`
+//! request, carrying the `connect-udp` [`Protocol`]. The target is given in the request's path,
+//! following the proxy's [`UriTemplate`]. Once the proxy accepted the request, both ends exchange
+//! payloads through a [`UdpTunnel`].
+//!
+//! Clients open one with [`Connection::connect_udp()`], proxies accept one with
+//! [`Sender::accept_connect_udp()`], which needs the extended `CONNECT` to be enabled in the
+//! proxy's [`Settings`]. Payloads are sent in HTTP datagrams when both peers enabled them, and in
+//! capsules on the request stream otherwise.
+//!
+//! The `h3_udp_proxy` example implements a complete proxy.
+//!
+//! # Example: client
+//!
+//! ```
+//! use anyhow::Result;
+//! use quinn_h3::{
+//!     client::Connection,
+//!     connect_udp::{Target, UriTemplate},
+//! };
+//!
+//! async fn ping(connection: &Connection) -> Result<()> {
+//!     let template = UriTemplate::well_known("proxy.example.com")?;
+//!     let mut tunnel = connection
+//!         .connect_udp(&template, &Target::new("192.0.2.1", 4000))
+//!         .await?;
+//!     tunnel.send(b"ping").await?;
+//!     if let Some(payload) = tunnel.recv().await {
+//!         println!("received {:?}", payload?);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`Target`]: struct.Target.html
+//! [`Protocol`]: ../struct.Protocol.html
+//! [`UriTemplate`]: struct.UriTemplate.html
+//! [`UdpTunnel`]: struct.UdpTunnel.html
+//! [`Connection::connect_udp()`]: ../client/struct.Connection.html#method.connect_udp
+//! [`Sender::accept_connect_udp()`]: ../server/struct.Sender.html#method.accept_connect_udp
+//! [`Settings`]: ../struct.Settings.html
+
+use std::{
+    fmt::{self, Write as _},
+    pin::Pin,
+    str,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{future, ready, Stream};
+use http::Uri;
+use quinn::{SendDatagramError, SendStream};
+use quinn_proto::{
+    coding::{BufExt, BufMutExt},
+    StreamId, VarInt,
+};
+
+use crate::{
+    connection::ConnectionRef,
+    datagram::{Capsule, RecvCapsules, RequestDatagrams},
+    proto::frame::DataFrame,
+    Error, RecvBody,
+};
+
+/// Template of the URIs a proxy accepts CONNECT-UDP requests on
+///
+/// It must be an absolute URI, with both a `{target_host}` and a `{target_port}` variable in its
+/// path or query, such as `https://proxy.example.com/masque?h={target_host}&p={target_port}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UriTemplate {
+    // Scheme and authority
+    base: String,
+    // Path and query, split around the variables
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    TargetHost,
+    TargetPort,
+}
+
+impl Part {
+    fn is_variable(&self) -> bool {
+        !matches!(self, Part::Literal(_))
+    }
+}
+
+impl UriTemplate {
+    /// Parse a URI template
+    pub fn new(template: &str) -> Result<Self, InvalidUriTemplate> {
+        let authority = template.find("://").ok_or(InvalidUriTemplate)? + 3;
+        let path = template[authority..].find('/').ok_or(InvalidUriTemplate)? + authority;
+        if template[..path].contains(&['{', '}'][..]) {
+            return Err(InvalidUriTemplate);
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = &template[path..];
+        while let Some(open) = rest.find('{') {
+            let close = rest.find('}').ok_or(InvalidUriTemplate)?;
+            if close < open {
+                return Err(InvalidUriTemplate);
+            }
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].into()));
+            }
+            let part = match &rest[open + 1..close] {
+                "target_host" => Part::TargetHost,
+                "target_port" => Part::TargetPort,
+                _ => return Err(InvalidUriTemplate),
+            };
+            if parts.contains(&part) {
+                return Err(InvalidUriTemplate);
+            }
+            parts.push(part);
+            rest = &rest[close + 1..];
+        }
+        if rest.contains('}') {
+            return Err(InvalidUriTemplate);
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.into()));
+        }
+
+        // Both variables are needed, separated by a literal so they can be told apart
+        let variables = parts.iter().filter(|p| p.is_variable()).count();
+        let adjacent = parts
+            .windows(2)
+            .any(|w| w[0].is_variable() && w[1].is_variable());
+        if variables != 2 || adjacent {
+            return Err(InvalidUriTemplate);
+        }
+
+        let template = Self {
+            base: template[..path].into(),
+            parts,
+        };
+        match template.try_expand(&Target::new("localhost", 443)) {
+            Ok(uri) if uri.scheme().is_some() && uri.authority().is_some() => Ok(template),
+            _ => Err(InvalidUriTemplate),
+        }
+    }
+
+    /// The default template of a proxy, at `/.well-known/masque/udp/`
+    pub fn well_known(authority: &str) -> Result<Self, InvalidUriTemplate> {
+        Self::new(&format!(
+            "https://{}/.well-known/masque/udp/{{target_host}}/{{target_port}}/",
+            authority
+        ))
+    }
+
+    /// The URI to request for proxying UDP to `target`
+    pub fn expand(&self, target: &Target) -> Uri {
+        self.try_expand(target).expect("valid URI")
+    }
+
+    fn try_expand(&self, target: &Target) -> Result<Uri, http::uri::InvalidUri> {
+        let mut uri = self.base.clone();
+        for part in &self.parts {
+            match part {
+                Part::Literal(x) => uri.push_str(x),
+                Part::TargetHost => percent_encode(&target.host, &mut uri),
+                Part::TargetPort => write!(uri, "{}", target.port).unwrap(),
+            }
+        }
+        uri.parse()
+    }
+
+    /// The target of a request, if its URI matches this template
+    pub fn target(&self, uri: &Uri) -> Option<Target> {
+        let mut rest = uri.path_and_query()?.as_str();
+        let (mut host, mut port) = (None, None);
+        let mut parts = self.parts.iter().peekable();
+        while let Some(part) = parts.next() {
+            if let Part::Literal(literal) = part {
+                if !rest.starts_with(literal.as_str()) {
+                    return None;
+                }
+                rest = &rest[literal.len()..];
+                continue;
+            }
+
+            let end = match parts.peek() {
+                Some(Part::Literal(literal)) => rest.find(literal.as_str())?,
+                _ => rest.len(),
+            };
+            let value = percent_decode(&rest[..end])?;
+            rest = &rest[end..];
+            match part {
+                Part::TargetHost => host = Some(value),
+                _ => port = Some(value.parse::<u16>().ok()?),
+            }
+        }
+
+        match (host, port) {
+            (Some(host), Some(port)) if rest.is_empty() && !host.is_empty() && port != 0 => {
+                Some(Target { host, port })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UriTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.base)?;
+        for part in &self.parts {
+            match part {
+                Part::Literal(x) => f.write_str(x)?,
+                Part::TargetHost => f.write_str("{target_host}")?,
+                Part::TargetPort => f.write_str("{target_port}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The URI template is not absolute, or does not have exactly one of each variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUriTemplate;
+
+impl fmt::Display for InvalidUriTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid CONNECT-UDP URI template")
+    }
+}
+
+impl std::error::Error for InvalidUriTemplate {}
+
+/// Host and port a proxy relays UDP payloads to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    host: String,
+    port: u16,
+}
+
+impl Target {
+    /// Create a target, `host` being a domain name or an IP address, without brackets for IPv6
+    pub fn new<T: Into<String>>(host: T, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+        }
+    }
+
+    /// Domain name or IP address of the target
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// UDP port of the target
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// UDP payloads relayed between a client and a proxy
+///
+/// On the client, payloads sent are forwarded by the proxy to the target, and those received come
+/// from it. On the proxy, it is the other way around. Dropping the tunnel closes it.
+pub struct UdpTunnel {
+    conn: ConnectionRef,
+    stream_id: StreamId,
+    send: SendStream,
+    capsules: RecvCapsules,
+    datagrams: Option<RequestDatagrams>,
+}
+
+impl UdpTunnel {
+    pub(crate) fn new(
+        conn: ConnectionRef,
+        send: SendStream,
+        body: RecvBody,
+        datagrams: Option<RequestDatagrams>,
+    ) -> Self {
+        Self {
+            conn,
+            stream_id: send.id(),
+            send,
+            capsules: RecvCapsules::new(body),
+            datagrams,
+        }
+    }
+
+    /// ID of the request stream of the tunnel
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Send a UDP payload to the peer
+    ///
+    /// It is sent in an HTTP datagram if possible, or in a capsule when the peer did not enable
+    /// them, or when the payload does not fit in a QUIC datagram.
+    pub async fn send(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut buf = BytesMut::with_capacity(VarInt::MAX_SIZE + payload.len());
+        buf.write_var(UDP_PAYLOAD_CONTEXT);
+        buf.extend_from_slice(payload);
+        let datagram = buf.freeze();
+
+        if let Some(datagrams) = self.datagrams.as_mut() {
+            match datagrams.send_datagram(datagram.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(Error::Unsupported(_))
+                | Err(Error::Datagram(SendDatagramError::UnsupportedByPeer))
+                | Err(Error::Datagram(SendDatagramError::Disabled))
+                | Err(Error::Datagram(SendDatagramError::TooLarge)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut capsule = BytesMut::new();
+        Capsule::Datagram(datagram).encode(&mut capsule);
+        let mut buf = BytesMut::with_capacity(2 * VarInt::MAX_SIZE + capsule.len());
+        DataFrame { payload: capsule }.encode(&mut buf);
+        self.send.write_all(&buf).await?;
+        Ok(())
+    }
+
+    /// Receive the next UDP payload, `None` once the peer closed the tunnel
+    pub async fn recv(&mut self) -> Option<Result<Bytes, Error>> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<Result<Bytes, Error>>> {
+        loop {
+            if let Some(datagrams) = self.datagrams.as_ref() {
+                if let Poll::Ready(Some(datagram)) = datagrams.poll_read(cx) {
+                    match udp_payload(datagram) {
+                        Some(payload) => return Poll::Ready(Some(Ok(payload))),
+                        None => continue,
+                    }
+                }
+            }
+
+            match ready!(Pin::new(&mut self.capsules).poll_next(cx)) {
+                None => return Poll::Ready(None),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Some(Ok(Capsule::Datagram(datagram))) => {
+                    if let Some(payload) = udp_payload(datagram) {
+                        return Poll::Ready(Some(Ok(payload)));
+                    }
+                }
+                // Unknown capsules are ignored
+                Some(Ok(_)) => (),
+            }
+        }
+    }
+
+    /// Close the tunnel
+    ///
+    /// Finishes the request stream, resolving once the peer acknowledged it.
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.send.finish().await?;
+        Ok(())
+    }
+}
+
+impl Drop for UdpTunnel {
+    fn drop(&mut self) {
+        let mut conn = self.conn.h3.lock().unwrap();
+        conn.inner.request_finished(self.stream_id);
+        conn.wake();
+    }
+}
+
+// Payload of a datagram carrying UDP, payloads with other context IDs are dropped
+fn udp_payload(mut datagram: Bytes) -> Option<Bytes> {
+    match datagram.get_var() {
+        Ok(UDP_PAYLOAD_CONTEXT) => Some(datagram.to_bytes()),
+        _ => None,
+    }
+}
+
+fn percent_encode(value: &str, out: &mut String) {
+    for c in value.bytes() {
+        match c {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(c as char)
+            }
+            _ => write!(out, "%{:02X}", c).unwrap(),
+        }
+    }
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(c) = bytes.next() {
+        if c != b'%' {
+            decoded.push(c);
+            continue;
+        }
+        let hex = [bytes.next()?, bytes.next()?];
+        decoded.push(u8::from_str_radix(str::from_utf8(&hex).ok()?, 16).ok()?);
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// Context ID of datagrams carrying a UDP payload
+const UDP_PAYLOAD_CONTEXT: u64 = 0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn well_known_template() {
+        let template = UriTemplate::well_known("proxy.example:4433").unwrap();
+        let target = Target::new("2001:db8::1", 53);
+        let uri = template.expand(&target);
+        assert_eq!(
+            uri,
+            "https://proxy.example:4433/.well-known/masque/udp/2001%3Adb8%3A%3A1/53/"
+        );
+        assert_eq!(template.target(&uri), Some(target));
+        assert_eq!(target.to_string(), "[2001:db8::1]:53");
+    }
+
+    #[test]
+    fn query_template() {
+        let template =
+            UriTemplate::new("https://proxy.example/masque?h={target_host}&p={target_port}")
+                .unwrap();
+        let target = Target::new("192.0.2.1", 443);
+        let uri = template.expand(&target);
+        assert_eq!(uri, "https://proxy.example/masque?h=192.0.2.1&p=443");
+        assert_eq!(template.target(&uri), Some(target));
+        assert_eq!(
+            template.to_string(),
+            "https://proxy.example/masque?h={target_host}&p={target_port}"
+        );
+    }
+
+    #[test]
+    fn target_mismatch() {
+        let template = UriTemplate::well_known("proxy.example").unwrap();
+        for uri in &[
+            "https://proxy.example/.well-known/masque/tcp/host/53/",
+            "https://proxy.example/.well-known/masque/udp/host/53",
+            "https://proxy.example/.well-known/masque/udp/host/port/",
+            "https://proxy.example/.well-known/masque/udp/host/0/",
+            "https://proxy.example/.well-known/masque/udp//53/",
+            "https://proxy.example/.well-known/masque/udp/ho%2/53/",
+        ] {
+            assert_eq!(template.target(&uri.parse().unwrap()), None, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn invalid_templates() {
+        for template in &[
+            "/masque/{target_host}/{target_port}",
+            "https://{target_host}/{target_port}",
+            "https://proxy.example/{target_host}{target_port}",
+            "https://proxy.example/{target_host}/{target_host}/{target_port}",
+            "https://proxy.example/{target_host}/{target_port}/{other}",
+            "https://proxy.example/{target_host}/",
+            "https://proxy.example/{target_host}/{target_port",
+        ] {
+            assert_eq!(
+                UriTemplate::new(template),
+                Err(InvalidUriTemplate),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn udp_payload_context() {
+        assert_eq!(
+            udp_payload(Bytes::from_static(b"\x00payload")),
+            Some(Bytes::from_static(b"payload"))
+        );
+        assert_eq!(udp_payload(Bytes::from_static(b"\x01payload")), None);
+        assert_eq!(udp_payload(Bytes::new()), None);
+    }
+}
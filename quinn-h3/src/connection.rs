@@ -1,31 +1,37 @@
 use std::{
-    collections::{BTreeMap, HashMap, VecDeque},
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
     future::Future,
     io, mem,
     pin::Pin,
     sync::{Arc, Mutex},
     task::{Context, Poll, Waker},
+    time::Duration,
 };
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::{io::AsyncRead, Stream};
-use quinn::{IncomingBiStreams, IncomingUniStreams, RecvStream, SendStream};
+use quinn::{
+    Datagrams, IncomingBiStreams, IncomingUniStreams, RecvStream, SendStream, ShuttingDown,
+};
 use quinn_proto::{
-    ConnectionClose, ConnectionError as QuicConnError, Side, StreamId, TransportErrorCode,
+    coding::{BufExt, BufMutExt},
+    ConnectionClose, ConnectionError as QuicConnError, Side, StreamId, TransportErrorCode, VarInt,
 };
-use tracing::{error, trace, trace_span, warn};
+use tokio::time::{delay_for, delay_until, Delay, Instant};
+use tracing::{error, trace, trace_span};
 
 use crate::{
-    frame::{self, FrameStream},
+    frame::{self, FrameDecoder, FrameStream},
     proto::{
         self,
         connection::{Connection, DecodeResult, Error as ConnectionError, PendingStreamType},
-        frame::{HeadersFrame, HttpFrame},
+        frame::{HeadersFrame, HttpFrame, PushPromiseFrame},
         headers::Header,
+        priority::Priority,
         settings::Error as SettingsError,
         ErrorCode, StreamType,
     },
-    streams::{NewUni, RecvUni, SendUni},
+    streams::{NewBi, NewUni, PushStream, RecvBi, RecvUni, Reset, SendUni},
     Error, Settings,
 };
 
@@ -38,7 +44,12 @@ impl Future for ConnectionDriver {
         let mut conn = self.0.h3.lock().unwrap();
         match conn.drive(cx) {
             Ok(false) => Poll::Pending,
-            Ok(true) => Poll::Ready(()),
+            Ok(true) => {
+                trace!("connection shut down");
+                self.0.quic.close(ErrorCode::NO_ERROR.into(), b"shutdown");
+                conn.terminate();
+                Poll::Ready(())
+            }
             Err(DriverError(err, code, msg)) => {
                 match err.try_into_quic() {
                     // Send CONNECTION_CLOSE and log only if it's pertinent:
@@ -64,6 +75,44 @@ impl Future for ConnectionDriver {
     }
 }
 
+// Keeps a client request in flight while its response is awaited or read, so a connection the
+// server went away from is only closed once it is done
+pub(crate) struct RequestGuard {
+    conn: ConnectionRef,
+    stream_id: StreamId,
+    armed: bool,
+}
+
+impl RequestGuard {
+    pub fn new(conn: ConnectionRef, stream_id: StreamId) -> Self {
+        conn.h3.lock().unwrap().inner.request_initiated(stream_id);
+        Self {
+            conn,
+            stream_id,
+            armed: true,
+        }
+    }
+
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    // Leave the request in flight, for the tunnel or session taking it over to finish
+    pub fn keep(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            let mut conn = self.conn.h3.lock().unwrap();
+            conn.inner.request_finished(self.stream_id);
+            conn.wake();
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct ConnectionRef {
     pub h3: Arc<Mutex<ConnectionInner>>,
@@ -76,6 +125,7 @@ impl ConnectionRef {
         side: Side,
         uni_streams: IncomingUniStreams,
         bi_streams: IncomingBiStreams,
+        datagrams: Datagrams,
         settings: Settings,
     ) -> Self {
         Self {
@@ -85,7 +135,12 @@ impl ConnectionRef {
                 driver: None,
                 incoming_bi: bi_streams,
                 incoming_uni: uni_streams,
+                datagrams,
                 pending_uni: VecDeque::with_capacity(3),
+                pending_bi: VecDeque::new(),
+                webtransport: settings.enable_webtransport(),
+                connect_protocol: settings.enable_connect_protocol(),
+                h3_datagram: settings.h3_datagram(),
                 inner: Connection::with_settings(settings),
                 requests: VecDeque::with_capacity(16),
                 requests_task: None,
@@ -98,48 +153,223 @@ impl ConnectionRef {
                     SendUni::new(StreamType::ENCODER, quic.open_uni()),
                     SendUni::new(StreamType::DECODER, quic.open_uni()),
                 ],
+                promises: VecDeque::new(),
+                promises_task: None,
+                promised: HashSet::new(),
+                pushes: HashMap::new(),
+                settings_tasks: Vec::new(),
+                sessions: HashMap::new(),
+                sessions_expiry: None,
+                flows: HashMap::new(),
+                shutdown: match side {
+                    Side::Server => Shutdown::Waiting(quic.shutting_down(), quic.clone()),
+                    Side::Client => Shutdown::Disabled,
+                },
                 closed: false,
             })),
         }
     }
+
+    pub fn on_push_promise(
+        &self,
+        stream_id: StreamId,
+        frame: PushPromiseFrame,
+    ) -> Result<(), Error> {
+        let res = self.h3.lock().unwrap().on_push_promise(stream_id, frame);
+        res.map_err(|DriverError(err, code, msg)| {
+            self.quic.close(code.into(), msg.as_bytes());
+            err
+        })
+    }
+
+    pub fn send_datagram(&self, stream_id: StreamId, data: Bytes) -> Result<(), Error> {
+        let enabled = {
+            let conn = self.h3.lock().unwrap();
+            conn.inner
+                .remote_settings()
+                .as_ref()
+                .map_or(false, |s| s.h3_datagram())
+        };
+        if !enabled {
+            return Err(Error::Unsupported("HTTP datagrams".into()));
+        }
+
+        let mut buf = BytesMut::with_capacity(VarInt::MAX_SIZE + data.len());
+        buf.write_var(stream_id.0 / 4);
+        buf.put(data);
+        self.quic.send_datagram(buf.freeze())?;
+        Ok(())
+    }
 }
 
 pub(crate) struct ConnectionInner {
     pub inner: Connection,
-    requests: VecDeque<(SendStream, RecvStream)>,
+    requests: VecDeque<(SendStream, FrameStream)>,
     requests_task: Option<Waker>,
     side: Side,
     driver: Option<Waker>,
     incoming_bi: IncomingBiStreams,
     incoming_uni: IncomingUniStreams,
+    datagrams: Datagrams,
     pending_uni: VecDeque<Option<RecvUni>>,
+    // Only used when WebTransport is enabled, to tell its streams from requests
+    pending_bi: VecDeque<Option<RecvBi>>,
+    webtransport: bool,
+    connect_protocol: bool,
+    h3_datagram: bool,
     recv_control: Option<FrameStream>,
     recv_encoder: Option<(RecvStream, BytesMut)>,
     recv_decoder: Option<(RecvStream, BytesMut)>,
     blocked_streams: BTreeMap<usize, HashMap<StreamId, Waker>>,
     send_unis: [SendUni; 3],
+    promises: VecDeque<(StreamId, PushPromiseFrame)>,
+    promises_task: Option<Waker>,
+    promised: HashSet<u64>,
+    pushes: HashMap<u64, PushState>,
+    settings_tasks: Vec<Waker>,
+    sessions: HashMap<StreamId, Session>,
+    // Fires when the oldest buffered session expires
+    sessions_expiry: Option<Delay>,
+    flows: HashMap<StreamId, DatagramFlow>,
+    shutdown: Shutdown,
     closed: bool,
 }
 
+// Graceful shutdown of a server connection, started by `quinn::Endpoint::shutdown()`
+enum Shutdown {
+    // Clients have nothing to announce
+    Disabled,
+    // With the connection, to measure the round trip time once the endpoint shuts down
+    Waiting(ShuttingDown, quinn::Connection),
+    // A GOAWAY with the largest stream ID was sent, requests already on their way are accepted
+    // until the delay expires
+    Announced(Delay),
+    // New requests are rejected, the connection closes once those in flight are finished
+    Draining,
+}
+
+enum PushState {
+    Pending(Option<Waker>),
+    Arrived(FrameStream),
+}
+
+// WebTransport session state, created when the session is established or, on the server, when
+// one of its streams arrives first
+#[derive(Default)]
+struct Session {
+    // Request stream of the session, polled to notice its closure
+    connect: Option<FrameStream>,
+    bi: VecDeque<(SendStream, RecvStream)>,
+    uni: VecDeque<RecvStream>,
+    bi_task: Option<Waker>,
+    uni_task: Option<Waker>,
+    closed: bool,
+    // When streams buffered on the server are rejected if the session wasn't established by then
+    expires: Option<Instant>,
+}
+
+impl Session {
+    fn is_buffering(&self) -> bool {
+        self.connect.is_none() && !self.closed
+    }
+
+    fn reject_streams(self, code: ErrorCode) {
+        for (mut send, mut recv) in self.bi {
+            send.reset(code.into());
+            let _ = recv.stop(code.into());
+        }
+        for mut recv in self.uni {
+            let _ = recv.stop(code.into());
+        }
+    }
+
+    fn wake(&mut self) {
+        for task in &mut [&mut self.bi_task, &mut self.uni_task] {
+            if let Some(t) = task.take() {
+                t.wake();
+            }
+        }
+    }
+}
+
+// Datagrams received for a request stream, until the application stops reading them
+#[derive(Default)]
+struct DatagramFlow {
+    datagrams: VecDeque<Bytes>,
+    task: Option<Waker>,
+    closed: bool,
+}
+
+impl DatagramFlow {
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(t) = self.task.take() {
+            t.wake();
+        }
+    }
+}
+
 impl ConnectionInner {
     fn drive(&mut self, cx: &mut Context) -> Result<bool, DriverError> {
+        self.poll_shutdown(cx);
         self.poll_incoming_uni(cx)?;
         self.poll_send(cx)?;
         self.poll_recv_control(cx)?;
         self.poll_recv_encoder(cx)?;
         self.poll_recv_decoder(cx)?;
         self.poll_incoming_bi(cx)?;
+        self.poll_sessions(cx);
+        self.poll_datagrams(cx)?;
         self.poll_send(cx)?;
 
         self.reset_waker(cx);
 
-        Ok(self.inner.is_closing() && self.inner.requests_in_flight() == 0)
+        let drained = match self.shutdown {
+            Shutdown::Draining => self.inner.requests_in_flight() == 0,
+            // Clients close once the server went away and their requests are done
+            Shutdown::Disabled => self.inner.is_closing() && self.inner.requests_in_flight() == 0,
+            _ => false,
+        };
+        Ok(drained)
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context) {
+        loop {
+            match &mut self.shutdown {
+                Shutdown::Waiting(shutting_down, quic) => {
+                    if Pin::new(shutting_down).poll(cx).is_pending() {
+                        return;
+                    }
+                    trace!("shutting down");
+                    self.inner.announce_go_away();
+                    let delay = GOAWAY_DELAY_RTTS * quic.rtt();
+                    self.shutdown = Shutdown::Announced(delay_for(delay));
+                }
+                Shutdown::Announced(delay) => {
+                    if Pin::new(delay).poll(cx).is_pending() {
+                        return;
+                    }
+                    self.inner.go_away();
+                    self.shutdown = Shutdown::Draining;
+                }
+                Shutdown::Disabled | Shutdown::Draining => return,
+            }
+        }
+    }
+
+    // Stop accepting requests right away, closing once those in flight are finished
+    pub fn go_away(&mut self) {
+        self.inner.go_away();
+        if self.side == Side::Server {
+            self.shutdown = Shutdown::Draining;
+        }
+        self.wake();
     }
 
     pub fn next_request(
         &mut self,
         cx: &mut Context,
-    ) -> Result<Option<(SendStream, RecvStream)>, ()> {
+    ) -> Result<Option<(SendStream, FrameStream)>, ()> {
         if self.closed {
             return Err(());
         }
@@ -157,12 +387,431 @@ impl ConnectionInner {
         self.wake();
     }
 
+    pub fn update_priority(&mut self, stream_id: StreamId, priority: Priority) {
+        // Only clients can signal priorities
+        if self.side == Side::Client {
+            self.inner.priority_update(stream_id, priority);
+            self.wake();
+        }
+    }
+
+    pub fn next_push_promise(
+        &mut self,
+        cx: &mut Context,
+    ) -> Result<Option<(StreamId, PushPromiseFrame)>, ()> {
+        if self.closed {
+            return Err(());
+        }
+        match self.promises.pop_front() {
+            Some(x) => Ok(Some(x)),
+            None => {
+                self.promises_task = Some(cx.waker().clone());
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn poll_push(
+        &mut self,
+        cx: &mut Context,
+        push_id: u64,
+    ) -> Poll<Result<FrameStream, Error>> {
+        if self.closed {
+            return Poll::Ready(Err(Error::Aborted));
+        }
+        if self.inner.is_push_cancelled(push_id) {
+            return Poll::Ready(Err(ErrorCode::REQUEST_CANCELLED.into()));
+        }
+        match self.pushes.remove(&push_id) {
+            Some(PushState::Arrived(recv)) => Poll::Ready(Ok(recv)),
+            _ => {
+                self.pushes
+                    .insert(push_id, PushState::Pending(Some(cx.waker().clone())));
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn cancel_push(&mut self, push_id: u64) {
+        self.inner.cancel_push(push_id);
+        if let Some(PushState::Arrived(mut recv)) = self.pushes.remove(&push_id) {
+            recv.reset(ErrorCode::REQUEST_CANCELLED);
+        }
+        self.wake();
+    }
+
+    fn on_push_promise(
+        &mut self,
+        stream_id: StreamId,
+        frame: PushPromiseFrame,
+    ) -> Result<(), DriverError> {
+        if let Side::Server = self.side {
+            return Err(DriverError::peer(
+                ErrorCode::FRAME_UNEXPECTED,
+                "server received a push promise",
+            ));
+        }
+        self.inner.check_push_id(frame.id)?;
+        if !self.promised.insert(frame.id) {
+            trace!("push {} already promised", frame.id);
+            return Ok(());
+        }
+        self.promises.push_back((stream_id, frame));
+        if let Some(t) = self.promises_task.take() {
+            t.wake();
+        }
+        Ok(())
+    }
+
+    fn on_push_stream(&mut self, mut push: PushStream) -> Result<(), DriverError> {
+        if let Side::Server = self.side {
+            return Err(DriverError::peer(
+                ErrorCode::STREAM_CREATION_ERROR,
+                "server does not accept push streams",
+            ));
+        }
+        self.inner.check_push_id(push.push_id)?;
+        if self.inner.is_push_cancelled(push.push_id) {
+            push.recv.reset(ErrorCode::REQUEST_CANCELLED);
+            return Ok(());
+        }
+        match self
+            .pushes
+            .insert(push.push_id, PushState::Arrived(push.recv))
+        {
+            Some(PushState::Arrived(_)) => Err(DriverError::peer(
+                ErrorCode::ID_ERROR,
+                format!("push {} stream opened twice", push.push_id),
+            )),
+            Some(PushState::Pending(Some(waker))) => {
+                waker.wake();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn poll_remote_settings(&mut self, cx: &mut Context) -> Poll<Result<&Settings, Error>> {
+        if self.closed {
+            return Poll::Ready(Err(Error::Aborted));
+        }
+        match self.inner.remote_settings() {
+            Some(settings) => Poll::Ready(Ok(settings)),
+            None => {
+                self.settings_tasks.push(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub fn webtransport_enabled(&self) -> bool {
+        self.webtransport
+    }
+
+    pub fn connect_protocol_enabled(&self) -> bool {
+        self.connect_protocol
+    }
+
+    pub fn register_session(&mut self, session_id: StreamId) {
+        self.sessions.entry(session_id).or_default();
+        self.flows.entry(session_id).or_default();
+    }
+
+    pub fn open_session(&mut self, session_id: StreamId, connect: FrameStream) {
+        let session = self.sessions.entry(session_id).or_default();
+        session.connect = Some(connect);
+        session.expires = None;
+        self.flows.entry(session_id).or_default();
+        self.wake();
+    }
+
+    pub fn close_session(&mut self, session_id: StreamId) {
+        // Once the request is finished, later streams for the session are rejected as gone
+        if let Some(session) = self.sessions.remove(&session_id) {
+            session.reject_streams(ErrorCode::WEBTRANSPORT_SESSION_GONE);
+        }
+        self.flows.remove(&session_id);
+        self.inner.request_finished(session_id);
+        self.wake();
+    }
+
+    pub fn poll_session_bi(
+        &mut self,
+        cx: &mut Context,
+        session_id: StreamId,
+    ) -> Poll<Option<(SendStream, RecvStream)>> {
+        let closed = self.closed;
+        match self.sessions.get_mut(&session_id) {
+            Some(session) if !closed => match session.bi.pop_front() {
+                Some(x) => Poll::Ready(Some(x)),
+                None if session.closed => Poll::Ready(None),
+                None => {
+                    session.bi_task = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            },
+            _ => Poll::Ready(None),
+        }
+    }
+
+    pub fn poll_session_uni(
+        &mut self,
+        cx: &mut Context,
+        session_id: StreamId,
+    ) -> Poll<Option<RecvStream>> {
+        let closed = self.closed;
+        match self.sessions.get_mut(&session_id) {
+            Some(session) if !closed => match session.uni.pop_front() {
+                Some(x) => Poll::Ready(Some(x)),
+                None if session.closed => Poll::Ready(None),
+                None => {
+                    session.uni_task = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            },
+            _ => Poll::Ready(None),
+        }
+    }
+
+    pub fn datagrams_enabled(&self) -> bool {
+        self.h3_datagram
+    }
+
+    pub fn register_datagrams(&mut self, stream_id: StreamId) -> Result<(), Error> {
+        if !self.h3_datagram {
+            return Err(Error::internal(
+                "HTTP datagrams are disabled in local settings",
+            ));
+        }
+        if self.flows.contains_key(&stream_id) {
+            return Err(Error::internal(format!(
+                "datagrams of stream {} already taken",
+                stream_id
+            )));
+        }
+        self.flows.insert(stream_id, DatagramFlow::default());
+        Ok(())
+    }
+
+    pub fn close_datagrams(&mut self, stream_id: StreamId) {
+        self.flows.remove(&stream_id);
+    }
+
+    pub fn poll_datagram(&mut self, cx: &mut Context, stream_id: StreamId) -> Poll<Option<Bytes>> {
+        let closed = self.closed;
+        match self.flows.get_mut(&stream_id) {
+            Some(flow) if !closed => match flow.datagrams.pop_front() {
+                Some(x) => Poll::Ready(Some(x)),
+                None if flow.closed => Poll::Ready(None),
+                None => {
+                    flow.task = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            },
+            _ => Poll::Ready(None),
+        }
+    }
+
+    fn on_session_stream(
+        &mut self,
+        session_id: StreamId,
+        stream: SessionStream,
+    ) -> Result<(), DriverError> {
+        // Sessions are established by requests, always on client-initiated bidi streams
+        if session_id.0 % 4 != 0 {
+            return Err(DriverError::peer(
+                ErrorCode::ID_ERROR,
+                format!("invalid WebTransport session {}", session_id),
+            ));
+        }
+
+        let buffering = self.sessions.values().filter(|s| s.is_buffering()).count();
+        let code = match self.sessions.get(&session_id) {
+            Some(s) if s.closed => Some(ErrorCode::WEBTRANSPORT_SESSION_GONE),
+            Some(_) => None,
+            // Closed sessions and requests that were not a session leave no state: their IDs are
+            // below the next request ID and no longer in flight
+            None if self.side == Side::Client || !self.inner.may_open_session(session_id) => {
+                Some(ErrorCode::WEBTRANSPORT_SESSION_GONE)
+            }
+            None if buffering >= MAX_BUFFERED_SESSIONS => {
+                Some(ErrorCode::WEBTRANSPORT_BUFFERED_STREAM_REJECTED)
+            }
+            None => None,
+        };
+        if let Some(code) = code {
+            trace!("rejecting stream for WebTransport session {}", session_id);
+            match stream {
+                SessionStream::Bi(mut send, mut recv) => {
+                    send.reset(code.into());
+                    let _ = recv.stop(code.into());
+                }
+                SessionStream::Uni(mut recv) => {
+                    let _ = recv.stop(code.into());
+                }
+            }
+            return Ok(());
+        }
+
+        let session = match self.sessions.entry(session_id) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let expires = Instant::now() + BUFFERED_SESSION_TIMEOUT;
+                if self.sessions_expiry.is_none() {
+                    self.sessions_expiry = Some(delay_until(expires));
+                }
+                e.insert(Session {
+                    expires: Some(expires),
+                    ..Session::default()
+                })
+            }
+        };
+        match stream {
+            SessionStream::Bi(send, recv) => {
+                session.bi.push_back((send, recv));
+                if let Some(t) = session.bi_task.take() {
+                    t.wake();
+                }
+            }
+            SessionStream::Uni(recv) => {
+                session.uni.push_back(recv);
+                if let Some(t) = session.uni_task.take() {
+                    t.wake();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_sessions(&mut self, cx: &mut Context) {
+        self.poll_sessions_expiry(cx);
+
+        let flows = &mut self.flows;
+        for (id, session) in self.sessions.iter_mut() {
+            let connect = match session.connect.as_mut() {
+                Some(c) => c,
+                None => continue,
+            };
+            loop {
+                match Pin::new(&mut *connect).poll_next(cx) {
+                    Poll::Pending => break,
+                    Poll::Ready(Some(Ok(frame))) => {
+                        trace!("ignoring {} on WebTransport session {}", frame, id);
+                    }
+                    Poll::Ready(Some(Err(_))) | Poll::Ready(None) => {
+                        trace!("WebTransport session {} closed", id);
+                        session.connect = None;
+                        session.closed = true;
+                        session.wake();
+                        if let Some(flow) = flows.get_mut(id) {
+                            flow.close();
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn poll_sessions_expiry(&mut self, cx: &mut Context) {
+        loop {
+            let fired = match self.sessions_expiry.as_mut() {
+                Some(delay) => Pin::new(delay).poll(cx).is_ready(),
+                None => false,
+            };
+            if !fired {
+                return;
+            }
+
+            let now = Instant::now();
+            let expired = self
+                .sessions
+                .iter()
+                .filter(|(_, s)| s.is_buffering() && s.expires.map_or(false, |t| t <= now))
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            for id in expired {
+                trace!("buffered WebTransport session {} expired", id);
+                if let Some(session) = self.sessions.remove(&id) {
+                    session.reject_streams(ErrorCode::WEBTRANSPORT_BUFFERED_STREAM_REJECTED);
+                }
+            }
+
+            let next = self
+                .sessions
+                .values()
+                .filter(|s| s.is_buffering())
+                .filter_map(|s| s.expires)
+                .min();
+            match (next, self.sessions_expiry.as_mut()) {
+                (Some(t), Some(delay)) => delay.reset(t),
+                _ => self.sessions_expiry = None,
+            }
+        }
+    }
+
+    fn poll_datagrams(&mut self, cx: &mut Context) -> Result<(), DriverError> {
+        loop {
+            let mut datagram = match Pin::new(&mut self.datagrams).poll_next(cx) {
+                Poll::Pending | Poll::Ready(None) => return Ok(()),
+                Poll::Ready(Some(Err(e))) => return Err(e.into()),
+                Poll::Ready(Some(Ok(x))) => x,
+            };
+            if !self.h3_datagram {
+                return Err(DriverError::peer(
+                    ErrorCode::H3_DATAGRAM_ERROR,
+                    "datagram received while not enabled",
+                ));
+            }
+            let quarter_id = match datagram.get_var() {
+                Ok(x) if x <= MAX_QUARTER_STREAM_ID => x,
+                _ => {
+                    return Err(DriverError::peer(
+                        ErrorCode::H3_DATAGRAM_ERROR,
+                        "malformed datagram",
+                    ))
+                }
+            };
+            // Datagrams for unknown or closed request streams are dropped
+            match self.flows.get_mut(&StreamId(quarter_id * 4)) {
+                Some(flow) if !flow.closed => {
+                    if flow.datagrams.len() >= MAX_BUFFERED_DATAGRAMS {
+                        flow.datagrams.pop_front();
+                    }
+                    flow.datagrams.push_back(datagram);
+                    if let Some(t) = flow.task.take() {
+                        t.wake();
+                    }
+                }
+                _ => trace!("dropping datagram for stream {}", quarter_id * 4),
+            }
+        }
+    }
+
     pub fn terminate(&mut self) {
         self.closed = true;
 
+        for task in self.settings_tasks.drain(..) {
+            task.wake();
+        }
+        for session in self.sessions.values_mut() {
+            session.wake();
+        }
+        for flow in self.flows.values_mut() {
+            flow.close();
+        }
+
         if let Some(t) = self.requests_task.take() {
             t.wake();
         }
+        if let Some(t) = self.promises_task.take() {
+            t.wake();
+        }
+        for (_, push) in self.pushes.drain() {
+            if let PushState::Pending(Some(waker)) = push {
+                waker.wake();
+            }
+        }
 
         let requests = mem::replace(&mut self.blocked_streams, BTreeMap::new());
         for (_, waker) in requests.into_iter().map(|(_, v)| v).flatten() {
@@ -216,7 +865,7 @@ impl ConnectionInner {
     fn poll_incoming_bi(&mut self, cx: &mut Context) -> Result<(), DriverError> {
         loop {
             match Pin::new(&mut self.incoming_bi).poll_next(cx) {
-                Poll::Pending | Poll::Ready(None) => return Ok(()),
+                Poll::Pending | Poll::Ready(None) => break,
                 Poll::Ready(Some(Err(e))) => {
                     return Err(DriverError::new(
                         e,
@@ -224,6 +873,9 @@ impl ConnectionInner {
                         "incoming bi error",
                     ))
                 }
+                Poll::Ready(Some(Ok((send, recv)))) if self.webtransport => {
+                    self.pending_bi.push_back(Some(RecvBi::new(send, recv)));
+                }
                 Poll::Ready(Some(Ok((mut send, mut recv)))) => match self.side {
                     Side::Client => {
                         return Err(DriverError::peer(
@@ -236,16 +888,64 @@ impl ConnectionInner {
                             send.reset(ErrorCode::REQUEST_REJECTED.into());
                             let _ = recv.stop(ErrorCode::REQUEST_REJECTED.into());
                         } else {
-                            self.inner.request_initiated(send.id());
-                            self.requests.push_back((send, recv));
-                            if let Some(t) = self.requests_task.take() {
-                                t.wake();
-                            }
+                            self.on_request(send, FrameDecoder::stream(recv));
                         }
                     }
                 },
             }
         }
+        self.poll_resolve_bi(cx)
+    }
+
+    fn on_request(&mut self, send: SendStream, recv: FrameStream) {
+        self.inner.request_initiated(send.id());
+        self.requests.push_back((send, recv));
+        if let Some(t) = self.requests_task.take() {
+            t.wake();
+        }
+    }
+
+    fn poll_resolve_bi(&mut self, cx: &mut Context) -> Result<(), DriverError> {
+        let resolved: Vec<(usize, Result<NewBi, Error>)> = self
+            .pending_bi
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, x)| {
+                let mut pending = x.take().unwrap();
+                match Pin::new(&mut pending).poll(cx) {
+                    Poll::Ready(y) => Some((i, y)),
+                    Poll::Pending => {
+                        *x = Some(pending);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        for (removed, (i, res)) in resolved.into_iter().enumerate() {
+            self.pending_bi.remove(i - removed);
+            match res {
+                Err(e) => trace!("incoming bi stream failed: {:?}", e),
+                Ok(NewBi::WebTransport(session_id, send, recv)) => {
+                    self.on_session_stream(session_id, SessionStream::Bi(send, recv))?
+                }
+                Ok(NewBi::Request(_, _)) if self.side == Side::Client => {
+                    return Err(DriverError::peer(
+                        ErrorCode::STREAM_CREATION_ERROR,
+                        "client does not accept bidirectional streams",
+                    ));
+                }
+                Ok(NewBi::Request(mut send, mut recv)) => {
+                    if self.inner.is_closing() {
+                        send.reset(ErrorCode::REQUEST_REJECTED.into());
+                        recv.reset(ErrorCode::REQUEST_REJECTED);
+                    } else {
+                        self.on_request(send, recv);
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     fn poll_incoming_uni(&mut self, cx: &mut Context) -> Result<(), DriverError> {
@@ -336,9 +1036,17 @@ impl ConnectionInner {
                     "encoder stream already open",
                 )),
             },
-            NewUni::Push(_) => {
-                warn!("push stream ignored");
-                Ok(())
+            NewUni::Push(push) => {
+                trace!("Got Push stream {}", push.push_id);
+                self.on_push_stream(push)
+            }
+            NewUni::WebTransport(session_id, mut recv) => {
+                if !self.webtransport {
+                    let _ = recv.stop(ErrorCode::STREAM_CREATION_ERROR.into());
+                    return Ok(());
+                }
+                trace!("Got WebTransport stream for session {}", session_id);
+                self.on_session_stream(session_id, SessionStream::Uni(recv))
             }
             NewUni::Reserved => Ok(()),
         }
@@ -371,20 +1079,33 @@ impl ConnectionInner {
                         (_, _, HttpFrame::Settings(s)) => {
                             trace!("Got Settings: {:#?}", s);
                             self.inner.set_remote_settings(Settings::from_frame(s)?)?;
+                            for task in self.settings_tasks.drain(..) {
+                                task.wake();
+                            }
                         }
                         (true, Side::Client, HttpFrame::Goaway(id)) => {
                             trace!("Got Goaway({:?})", id);
-                            self.inner.leave(StreamId(id));
+                            self.inner.leave(StreamId(id))?;
+                        }
+                        (true, _, HttpFrame::CancelPush(id)) => {
+                            trace!("Got CancelPush({})", id);
+                            self.inner.on_cancel_push(id);
+                            if let Some(PushState::Pending(Some(w))) = self.pushes.remove(&id) {
+                                w.wake();
+                            }
                         }
-                        (true, Side::Server, HttpFrame::CancelPush(_)) => {
-                            warn!("CANCEL_PUSH frame ignored");
+                        (true, Side::Server, HttpFrame::MaxPushId(id)) => {
+                            trace!("Got MaxPushId({})", id);
+                            self.inner.set_max_push_id(id)?;
                         }
-                        (true, Side::Server, HttpFrame::MaxPushId(_)) => {
-                            warn!("MAX_PUSH_ID frame ignored");
+                        (true, Side::Server, HttpFrame::PriorityUpdate(p)) => {
+                            trace!("Got PriorityUpdate({}, {})", p.id, p.priority);
+                            self.inner.on_priority_update(p)?;
                         }
                         (true, _, HttpFrame::Reserved) => (),
-                        (false, Side::Server, HttpFrame::CancelPush(_))
+                        (false, _, HttpFrame::CancelPush(_))
                         | (false, Side::Server, HttpFrame::MaxPushId(_))
+                        | (false, Side::Server, HttpFrame::PriorityUpdate(_))
                         | (false, _, HttpFrame::Reserved)
                         | (false, Side::Client, HttpFrame::Goaway(_)) => {
                             return Err(DriverError::peer(
@@ -505,6 +1226,11 @@ impl ConnectionInner {
     }
 }
 
+enum SessionStream {
+    Bi(SendStream, RecvStream),
+    Uni(RecvStream),
+}
+
 struct DriverError(Error, ErrorCode, String);
 
 impl DriverError {
@@ -566,6 +1292,12 @@ impl From<ConnectionError> for DriverError {
             ConnectionError::DecodeError { reason } => {
                 DriverError::peer(ErrorCode::QPACK_ENCODER_STREAM_ERROR, format!("{}", reason))
             }
+            ConnectionError::InvalidPushId(id) => {
+                DriverError::peer(ErrorCode::ID_ERROR, format!("invalid push ID {}", id))
+            }
+            ConnectionError::InvalidStreamId(id) => {
+                DriverError::peer(ErrorCode::ID_ERROR, format!("invalid stream ID {}", id))
+            }
             // Those are excepted to happen on in Requests / Responses, just return internal error
             ConnectionError::HeaderListTooLarge
             | ConnectionError::InvalidHeaderName(_)
@@ -606,3 +1338,14 @@ impl From<SettingsError> for DriverError {
 
 const RECV_ENCODER_INITIAL_CAPACITY: usize = 20480;
 const RECV_DECODER_INITIAL_CAPACITY: usize = 2048;
+// Sessions whose streams arrived before they were established
+const MAX_BUFFERED_SESSIONS: usize = 16;
+// Time given to a session to be established once its first stream was buffered
+const BUFFERED_SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+// Received datagrams kept for each request stream, the oldest are dropped beyond this
+const MAX_BUFFERED_DATAGRAMS: usize = 256;
+// Quarter stream IDs are bounded by the largest client-initiated bidirectional stream ID
+const MAX_QUARTER_STREAM_ID: u64 = (1 << 60) - 1;
+// Time given to requests sent before the peer received the first GOAWAY of a shutdown to arrive,
+// in round trips, leaving room for RTT variation
+const GOAWAY_DELAY_RTTS: u32 = 2;
@@ -3,13 +3,15 @@ use std::{
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
-use futures::{ready, Stream as _};
+use futures::{channel::oneshot, ready, FutureExt, Stream as _};
 use http_body::Body as HttpBody;
-use pin_project::{pin_project, project};
+use pin_project::{pin_project, pinned_drop, project};
 use quinn::SendStream;
 use quinn_proto::StreamId;
+use tokio::time::{delay_for, Delay};
 
 use crate::{
     body::RecvBody,
@@ -30,7 +32,7 @@ use crate::{
 /// This is yielded by [`SendRequest`] and [`SendResponse`]. It will encode and send
 /// the headers, then send the body if any data is polled from [`HttpBody::poll_data()`].
 /// It also encodes and sends the trailer a similar way, if any.
-#[pin_project]
+#[pin_project(PinnedDrop)]
 pub struct SendData<B, P> {
     headers: Option<Header>,
     #[pin]
@@ -40,13 +42,17 @@ pub struct SendData<B, P> {
     conn: ConnectionRef,
     send: Option<SendStream>,
     stream_id: StreamId,
+    // Whether this sends a response, finishing its request in flight once done or dropped
     finish: bool,
+    // Whether to send the body, held back until the server answers `expect: 100-continue`
+    expect_continue: Option<oneshot::Receiver<bool>>,
 }
 
 #[pin_project]
 enum SendDataState<P> {
     Initial,
     Headers(SendHeaders),
+    WaitContinue(Delay),
     PollBody,
     Write(#[pin] WriteFrame<DataFrame<P>>),
     PollTrailers,
@@ -75,14 +81,19 @@ where
             stream_id: send.id(),
             send: Some(send),
             state: SendDataState::Initial,
+            expect_continue: None,
         }
     }
 
+    // Send the body once `proceed` resolves to `true`, or if it does not resolve in time
+    pub(crate) fn expect_continue(&mut self, proceed: oneshot::Receiver<bool>) {
+        self.expect_continue = Some(proceed);
+    }
+
     /// Cancel the request
     ///
     /// The peer will receive a request error with `REQUEST_CANCELLED` code.
     pub fn cancel(&mut self) {
-        self.state = SendDataState::Finished;
         match self.state {
             SendDataState::Write(ref mut w) => {
                 w.reset(ErrorCode::REQUEST_CANCELLED);
@@ -125,9 +136,45 @@ where
                 }
                 SendDataState::Headers(ref mut send) => {
                     *me.send = Some(ready!(Pin::new(send).poll(cx))?);
-                    me.state.set(SendDataState::PollBody);
+                    if me.expect_continue.is_some() {
+                        let delay = delay_for(EXPECT_CONTINUE_TIMEOUT);
+                        me.state.set(SendDataState::WaitContinue(delay));
+                    } else {
+                        me.state.set(SendDataState::PollBody);
+                    }
+                }
+                SendDataState::WaitContinue(ref mut delay) => {
+                    let proceed = me.expect_continue.as_mut().expect("expect continue");
+                    let proceed = match proceed.poll_unpin(cx) {
+                        // Nobody waits for the response anymore, send the body anyway
+                        Poll::Ready(proceed) => proceed.unwrap_or(true),
+                        Poll::Pending => {
+                            ready!(Pin::new(delay).poll(cx));
+                            true
+                        }
+                    };
+                    *me.expect_continue = None;
+                    if proceed {
+                        me.state.set(SendDataState::PollBody);
+                    } else {
+                        // The final response came first, the body is not needed anymore
+                        if let Some(send) = me.send.as_mut() {
+                            send.reset(ErrorCode::REQUEST_CANCELLED.into());
+                        }
+                        me.state.set(SendDataState::Finished);
+                    }
                 }
                 SendDataState::PollBody => {
+                    let update = me
+                        .conn
+                        .h3
+                        .lock()
+                        .unwrap()
+                        .inner
+                        .take_priority_update(*me.stream_id);
+                    if let (Some(priority), Some(send)) = (update, me.send.as_ref()) {
+                        let _ = send.set_priority(priority.quic_priority(*me.stream_id));
+                    }
                     let next = match ready!(Pin::new(&mut me.body).poll_data(cx)) {
                         None => SendDataState::PollTrailers,
                         Some(Err(e)) => return Poll::Ready(Err(Error::body(e.into()))),
@@ -168,6 +215,8 @@ where
                     if *me.finish {
                         let mut conn = me.conn.h3.lock().unwrap();
                         conn.inner.request_finished(*me.stream_id);
+                        conn.wake();
+                        *me.finish = false;
                     }
                     return Poll::Ready(Ok(()));
                 }
@@ -177,6 +226,35 @@ where
     }
 }
 
+#[pinned_drop]
+impl<B, P> PinnedDrop for SendData<B, P> {
+    #[project]
+    fn drop(self: Pin<&mut Self>) {
+        let me = self.project();
+        if !*me.finish {
+            return;
+        }
+
+        // The response was not sent in full, so it must not end as if it was
+        #[project]
+        match me.state.project() {
+            SendDataState::Headers(send) | SendDataState::Trailers(send) => {
+                send.reset(ErrorCode::REQUEST_CANCELLED);
+            }
+            SendDataState::Write(write) => write.reset_pinned(ErrorCode::REQUEST_CANCELLED),
+            SendDataState::Finished => (),
+            _ => {
+                if let Some(send) = me.send.as_mut() {
+                    send.reset(ErrorCode::REQUEST_CANCELLED.into());
+                }
+            }
+        }
+        let mut conn = me.conn.h3.lock().unwrap();
+        conn.inner.request_finished(*me.stream_id);
+        conn.wake();
+    }
+}
+
 pub struct RecvData {
     state: RecvDataState,
     conn: ConnectionRef,
@@ -215,6 +293,9 @@ impl Future for RecvData {
                 RecvDataState::Receiving => {
                     match ready!(Pin::new(self.recv.as_mut().unwrap()).poll_next(cx)) {
                         Some(Ok(HttpFrame::Reserved)) => continue,
+                        Some(Ok(HttpFrame::PushPromise(p))) => {
+                            self.conn.on_push_promise(self.stream_id, p)?;
+                        }
                         Some(Ok(HttpFrame::Headers(h))) => {
                             self.state = RecvDataState::Decoding(DecodeHeaders::new(
                                 h,
@@ -253,3 +334,7 @@ impl Future for RecvData {
         }
     }
 }
+
+// How long a request sent with `expect: 100-continue` waits for the server to answer before
+// sending its body anyway
+const EXPECT_CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
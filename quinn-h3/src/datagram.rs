@@ -0,0 +1,284 @@
+//! HTTP Datagrams and the Capsule Protocol
+//!
+//! A request can be associated with unreliable datagrams, sent in QUIC `DATAGRAM` frames and
+//! prefixed by the request's stream ID. Both peers need to enable them in their [`Settings`],
+//! with [`set_h3_datagram()`]. Servers get the [`RequestDatagrams`] of a request from
+//! [`Sender::datagrams()`], clients from [`SendRequest::datagrams()`].
+//!
+//! A request and its response can also exchange [`Capsule`]s in place of their bodies. They are
+//! sent with a [`CapsuleBody`], and received by wrapping the peer's [`RecvBody`] in
+//! [`RecvCapsules`]. Peers usually agree on this with the `capsule-protocol: ?1` header.
+//!
+//! Together, they are the building blocks of tunneling protocols over HTTP/3.
+//!
+//! # Example: echo server
+//!
+//! ```
+//! use anyhow::Result;
+//! use futures::StreamExt;
+//! use http::{Request, Response, StatusCode};
+//! use quinn_h3::{
+//!     datagram::{CapsuleBody, RecvCapsules},
+//!     server::Sender,
+//!     RecvBody,
+//! };
+//!
+//! async fn echo(request: Request<RecvBody>, mut sender: Sender) -> Result<()> {
+//!     let mut datagrams = sender.datagrams()?;
+//!     tokio::spawn(async move {
+//!         while let Some(datagram) = datagrams.read_datagram().await {
+//!             let _ = datagrams.send_datagram(datagram).await;
+//!         }
+//!     });
+//!
+//!     let (mut send_capsules, body) = CapsuleBody::channel();
+//!     let response = Response::builder()
+//!         .status(StatusCode::OK)
+//!         .header("capsule-protocol", "?1")
+//!         .body(body)?;
+//!     let send_response = sender.send_response(response);
+//!
+//!     let echo_capsules = async move {
+//!         let mut capsules = RecvCapsules::new(request.into_body());
+//!         while let Some(capsule) = capsules.next().await {
+//!             send_capsules.send(capsule?).await?;
+//!         }
+//!         Ok::<_, quinn_h3::Error>(())
+//!     };
+//!     futures::try_join!(send_response, echo_capsules)?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`Settings`]: ../struct.Settings.html
+//! [`set_h3_datagram()`]: ../struct.Settings.html#method.set_h3_datagram
+//! [`RequestDatagrams`]: struct.RequestDatagrams.html
+//! [`Sender::datagrams()`]: ../server/struct.Sender.html#method.datagrams
+//! [`SendRequest::datagrams()`]: ../client/struct.SendRequest.html#method.datagrams
+//! [`Capsule`]: enum.Capsule.html
+//! [`CapsuleBody`]: struct.CapsuleBody.html
+//! [`RecvBody`]: ../struct.RecvBody.html
+//! [`RecvCapsules`]: struct.RecvCapsules.html
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{
+    channel::{mpsc, oneshot},
+    future, ready, SinkExt, Stream,
+};
+use http::HeaderMap;
+use http_body::Body as HttpBody;
+use quinn_proto::StreamId;
+
+use crate::{connection::ConnectionRef, proto::capsule, Error, RecvBody};
+
+pub use crate::proto::capsule::Capsule;
+
+/// Datagrams associated with a request
+///
+/// Received datagrams are buffered until read with [`read_datagram()`], the oldest ones being
+/// dropped when too many are waiting. They stop being received once this is dropped.
+///
+/// [`read_datagram()`]: #method.read_datagram
+pub struct RequestDatagrams {
+    conn: ConnectionRef,
+    stream_id: Option<StreamId>,
+    open: Option<oneshot::Receiver<StreamId>>,
+}
+
+impl RequestDatagrams {
+    pub(crate) fn new(conn: ConnectionRef, stream_id: StreamId) -> Self {
+        Self {
+            conn,
+            stream_id: Some(stream_id),
+            open: None,
+        }
+    }
+
+    // Datagrams of a request whose stream is not open yet
+    pub(crate) fn pending(conn: ConnectionRef, open: oneshot::Receiver<StreamId>) -> Self {
+        Self {
+            conn,
+            stream_id: None,
+            open: Some(open),
+        }
+    }
+
+    /// ID of the request stream, `None` until the client opened it
+    pub fn stream_id(&self) -> Option<StreamId> {
+        self.stream_id
+    }
+
+    /// Send an unreliable datagram to the peer
+    ///
+    /// Waits for the request stream to be opened. Fails if the peer did not enable HTTP
+    /// datagrams, or if `data` does not fit in a QUIC datagram once prefixed with the stream ID.
+    pub async fn send_datagram(&mut self, data: Bytes) -> Result<(), Error> {
+        let stream_id = self.opened().await?;
+        self.conn.send_datagram(stream_id, data)
+    }
+
+    /// Receive the next datagram sent by the peer for this request
+    ///
+    /// Resolves to `None` once the connection is closed, or if the request stream could not be
+    /// opened.
+    pub async fn read_datagram(&mut self) -> Option<Bytes> {
+        let stream_id = self.opened().await.ok()?;
+        let conn = &self.conn;
+        future::poll_fn(|cx| conn.h3.lock().unwrap().poll_datagram(cx, stream_id)).await
+    }
+
+    pub(crate) fn poll_read(&self, cx: &mut Context) -> Poll<Option<Bytes>> {
+        match self.stream_id {
+            Some(stream_id) => self.conn.h3.lock().unwrap().poll_datagram(cx, stream_id),
+            None => Poll::Ready(None),
+        }
+    }
+
+    async fn opened(&mut self) -> Result<StreamId, Error> {
+        if let Some(stream_id) = self.stream_id {
+            return Ok(stream_id);
+        }
+        let open = self
+            .open
+            .as_mut()
+            .ok_or_else(|| Error::internal("request stream could not be opened"))?;
+        let stream_id = match open.await {
+            Ok(x) => x,
+            Err(_) => {
+                self.open = None;
+                return Err(Error::internal("request stream could not be opened"));
+            }
+        };
+        self.open = None;
+        self.stream_id = Some(stream_id);
+        Ok(stream_id)
+    }
+}
+
+impl Drop for RequestDatagrams {
+    fn drop(&mut self) {
+        if let Some(stream_id) = self.stream_id {
+            self.conn.h3.lock().unwrap().close_datagrams(stream_id);
+        }
+    }
+}
+
+/// Body sending capsules
+///
+/// Use it as the body of a request or a response, and send capsules with the [`CapsuleSender`]
+/// created along with it by [`channel()`]. The body ends once the sender is dropped.
+///
+/// [`CapsuleSender`]: struct.CapsuleSender.html
+/// [`channel()`]: #method.channel
+pub struct CapsuleBody(mpsc::Receiver<Capsule>);
+
+impl CapsuleBody {
+    /// Create a body, and the sender feeding it
+    pub fn channel() -> (CapsuleSender, Self) {
+        let (send, recv) = mpsc::channel(CAPSULE_CHANNEL_SIZE);
+        (CapsuleSender(send), CapsuleBody(recv))
+    }
+}
+
+impl HttpBody for CapsuleBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let capsule = match ready!(Pin::new(&mut self.0).poll_next(cx)) {
+            Some(x) => x,
+            None => return Poll::Ready(None),
+        };
+        let mut buf = BytesMut::new();
+        capsule.encode(&mut buf);
+        Poll::Ready(Some(Ok(buf.freeze())))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _: &mut Context,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+/// Send capsules into a [`CapsuleBody`]
+///
+/// [`CapsuleBody`]: struct.CapsuleBody.html
+#[derive(Clone)]
+pub struct CapsuleSender(mpsc::Sender<Capsule>);
+
+impl CapsuleSender {
+    /// Send a capsule, waiting for the previous ones to be transmitted if too many are pending
+    pub async fn send(&mut self, capsule: Capsule) -> Result<(), Error> {
+        self.0
+            .send(capsule)
+            .await
+            .map_err(|_| Error::internal("capsule body dropped"))
+    }
+}
+
+/// Capsules received in the body of a request or a response
+///
+/// A `Stream` decoding the [`Capsule`]s of a [`RecvBody`], ending with it.
+///
+/// [`Capsule`]: enum.Capsule.html
+/// [`RecvBody`]: ../struct.RecvBody.html
+pub struct RecvCapsules {
+    body: RecvBody,
+    buf: BytesMut,
+}
+
+impl RecvCapsules {
+    /// Decode capsules from `body`
+    pub fn new(body: RecvBody) -> Self {
+        Self {
+            body,
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+impl Stream for RecvCapsules {
+    type Item = Result<Capsule, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            match Capsule::decode(&mut self.buf) {
+                Ok(Some(capsule)) => return Poll::Ready(Some(Ok(capsule))),
+                Ok(None) => (),
+                Err(capsule::Error::TooLarge(len)) => {
+                    self.buf.clear();
+                    self.body.cancel();
+                    return Poll::Ready(Some(Err(Error::peer(format!(
+                        "capsule too large: {} bytes",
+                        len
+                    )))));
+                }
+            }
+
+            match ready!(Pin::new(&mut self.body).poll_data(cx)) {
+                Some(Ok(data)) => self.buf.extend_from_slice(&data),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None if self.buf.is_empty() => return Poll::Ready(None),
+                None => {
+                    self.buf.clear();
+                    return Poll::Ready(Some(Err(Error::peer("incomplete capsule"))));
+                }
+            }
+        }
+    }
+}
+
+pub(crate) const CAPSULE_PROTOCOL: &str = "capsule-protocol";
+
+// Capsules waiting in a `CapsuleBody` before senders have to wait
+const CAPSULE_CHANNEL_SIZE: usize = 16;
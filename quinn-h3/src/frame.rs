@@ -5,7 +5,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::{ready, FutureExt};
 use pin_project::{pin_project, project};
 use quinn::{RecvStream, SendStream, VarInt};
@@ -26,6 +26,8 @@ impl Reset for FrameStream {
 pub struct FrameDecoder {
     partial: Option<PartialData>,
     expected: Option<usize>,
+    // Bytes already read from the stream, decoded before the rest
+    prefix: Option<Bytes>,
 }
 
 impl FrameDecoder {
@@ -35,10 +37,17 @@ impl FrameDecoder {
             FrameDecoder {
                 expected: None,
                 partial: None,
+                prefix: None,
             },
             65535,
         )
     }
+
+    pub fn stream_with_prefix<T: AsyncRead>(stream: T, prefix: Bytes) -> FramedRead<T, Self> {
+        let mut stream = Self::stream(stream);
+        stream.decoder_mut().prefix = Some(prefix);
+        stream
+    }
 }
 
 macro_rules! decode {
@@ -54,6 +63,12 @@ impl Decoder for FrameDecoder {
     type Error = Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(prefix) = self.prefix.take() {
+            let rest = src.split();
+            src.extend_from_slice(&prefix);
+            src.extend_from_slice(&rest);
+        }
+
         if src.is_empty() {
             return Ok(None);
         }
@@ -148,6 +163,14 @@ where
     }
 }
 
+impl<F> WriteFrame<F> {
+    pub fn reset_pinned(self: Pin<&mut Self>, err_code: ErrorCode) {
+        if let Some(s) = self.project().send.get_mut() {
+            s.reset(err_code.into());
+        }
+    }
+}
+
 impl<F> Future for WriteFrame<F>
 where
     F: FrameHeader + IntoPayload,
@@ -287,4 +310,20 @@ mod tests {
         assert_matches!(decoder.decode(&mut buf), Ok(Some(HttpFrame::Data(_))));
         assert_matches!(decoder.decode(&mut buf), Ok(None));
     }
+
+    #[test]
+    fn prefixed_frame() {
+        let frame = frame::HeadersFrame {
+            encoded: b"salut"[..].into(),
+        };
+
+        let mut buf = BytesMut::with_capacity(16);
+        frame.encode(&mut buf);
+        let mut rest = buf.split_off(1);
+
+        let mut decoder = FrameDecoder::default();
+        decoder.prefix = Some(buf.freeze());
+        assert_matches!(decoder.decode(&mut rest), Ok(Some(HttpFrame::Headers(_))));
+        assert!(rest.is_empty());
+    }
 }
@@ -18,6 +18,11 @@
 //!
 //! It also offers Server Push features similar to HTTP/2.
 //!
+//! Beyond requests, a connection can carry [WebTransport] sessions, see the [`webtransport`]
+//! module. Requests can also exchange unreliable datagrams and capsules, see the [`datagram`]
+//! module, on which the UDP proxying of the [`connect_udp`] module is built. Byte streams, such
+//! as TCP connections, are proxied through `CONNECT` requests with the [`tunnel`] module.
+//!
 //! The HTTP/3 specification has not yet been stabilized. The version currently implemented by this
 //! crate is [draft-27].
 //!
@@ -39,8 +44,6 @@
 //!
 //! * Though having been tested as compatible with a majority of other HTTP/3 implementations,
 //!   `quinn-h3` does not implement all interoperability tests for the moment.
-//! * The CONNECT method and associated proxy features are not implemented.
-//! * Server PUSH is not implemented.
 //!
 //! # Getting started
 //!
@@ -60,6 +63,11 @@
 //! [Head-Of-Line blocking]: https://en.wikipedia.org/wiki/Head-of-line_blocking
 //! [QPACK]: https://datatracker.ietf.org/doc/draft-ietf-quic-qpack/
 //! [draft-27]: https://tools.ietf.org/html/draft-ietf-quic-http-27
+//! [WebTransport]: https://datatracker.ietf.org/doc/draft-ietf-webtrans-http3/
+//! [`webtransport`]: webtransport/index.html
+//! [`datagram`]: datagram/index.html
+//! [`connect_udp`]: connect_udp/index.html
+//! [`tunnel`]: tunnel/index.html
 //! [Server Push]: https://en.wikipedia.org/wiki/HTTP/2_Server_Push
 //! [`client`]: client/index.html
 //! [`server`]: server/index.html
@@ -89,16 +97,23 @@ mod tests;
 
 pub use body::{Body, RecvBody};
 pub use data::SendData;
+pub use proto::headers::{InvalidProtocol, Protocol};
+pub use proto::priority::Priority;
 pub use proto::settings::Settings;
 
 pub mod client;
+pub mod connect_udp;
+pub mod datagram;
 pub mod server;
+pub mod tunnel;
+pub mod webtransport;
 
 mod body;
 mod connection;
 mod data;
 mod frame;
 mod headers;
+mod pool;
 mod proto;
 mod streams;
 
@@ -110,7 +125,7 @@ mod qpack;
 pub mod qpack;
 
 use err_derive::Error;
-use quinn::{ApplicationClose, ConnectionError, ReadError, WriteError};
+use quinn::{ApplicationClose, ConnectionError, ReadError, SendDatagramError, WriteError};
 use std::{error::Error as StdError, io::ErrorKind};
 
 use proto::ErrorCode;
@@ -124,6 +139,9 @@ pub enum Error {
     /// Cannot make a new request, bescause the connection is closing
     #[error(display = "Connection is closing, resquest aborted")]
     Aborted,
+    /// Cannot push a response, the client's push limit has been reached
+    #[error(display = "Push refused by peer")]
+    PushRefused,
     /// Protocol violation detected by the internal HTTP/3 protocol state machine
     #[error(display = "H3 protocol error: {:?}", _0)]
     Proto(proto::connection::Error),
@@ -160,6 +178,23 @@ pub enum Error {
     /// Polling the issued body data yielded an error
     #[error(display = "Polling body error: {}", _0)]
     Body(Box<dyn StdError + Send + Sync>),
+    /// The peer did not enable a protocol extension this operation relies on
+    #[error(display = "Unsupported by peer: {}", _0)]
+    Unsupported(String),
+    /// A datagram could not be sent
+    #[error(display = "Datagram error: {}", _0)]
+    Datagram(SendDatagramError),
+    /// The server answered a `CONNECT` request with an unsuccessful status
+    #[error(display = "CONNECT refused with status {}", _0)]
+    ConnectRefused(http::StatusCode),
+    /// A service given to [`server::serve()`] failed
+    ///
+    /// [`server::serve()`]: server/fn.serve.html
+    #[error(display = "Service error: {}", _0)]
+    Service(Box<dyn StdError + Send + Sync>),
+    /// A connection could not be initiated
+    #[error(display = "Connect error: {}", _0)]
+    Connect(quinn::ConnectError),
 }
 
 impl Error {
@@ -207,6 +242,18 @@ impl From<quinn::ConnectionError> for Error {
     }
 }
 
+impl From<quinn::ConnectError> for Error {
+    fn from(err: quinn::ConnectError) -> Error {
+        Error::Connect(err)
+    }
+}
+
+impl From<SendDatagramError> for Error {
+    fn from(err: SendDatagramError) -> Error {
+        Error::Datagram(err)
+    }
+}
+
 impl From<ErrorCode> for Error {
     fn from(code: ErrorCode) -> Self {
         Error::Http(code.into(), None)
@@ -314,6 +361,8 @@ pub enum HttpError {
     QpackEncoderStreamError,
     /// Error on the decoder stream
     QpackDecoderStreamError,
+    /// An HTTP datagram or a capsule was malformed, or received while not enabled
+    DatagramError,
     /// Unknown error code
     Unknown(u32),
 }
@@ -340,6 +389,7 @@ impl From<ErrorCode> for HttpError {
             ErrorCode::QPACK_DECOMPRESSION_FAILED => HttpError::QpackDecompressionFailed,
             ErrorCode::QPACK_ENCODER_STREAM_ERROR => HttpError::QpackEncoderStreamError,
             ErrorCode::QPACK_DECODER_STREAM_ERROR => HttpError::QpackDecoderStreamError,
+            ErrorCode::H3_DATAGRAM_ERROR => HttpError::DatagramError,
             _ => HttpError::Unknown(code.0),
         }
     }
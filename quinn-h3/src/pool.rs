@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures::lock::Mutex as AsyncMutex;
+use quinn_proto::Dir;
+
+use crate::{connection::ConnectionRef, proto::ErrorCode};
+
+// Connections kept by a client, keyed by the authority they are connected to
+pub(crate) struct Pool {
+    connections: Mutex<HashMap<String, Vec<ConnectionRef>>>,
+    // Held while establishing a connection to an authority, so concurrent requests share it
+    connecting: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl Pool {
+    pub fn new() -> Self {
+        Self {
+            connections: Mutex::new(HashMap::new()),
+            connecting: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Lock to take before connecting to `authority`, to be handed back to `release()`
+    pub fn connect_lock(&self, authority: &str) -> Arc<AsyncMutex<()>> {
+        self.connecting
+            .lock()
+            .unwrap()
+            .entry(authority.into())
+            .or_default()
+            .clone()
+    }
+
+    pub fn release(&self, authority: &str, lock: Arc<AsyncMutex<()>>) {
+        let mut connecting = self.connecting.lock().unwrap();
+        // Nobody else is waiting on it when only the map and `lock` are left
+        if Arc::strong_count(&lock) == 2 {
+            connecting.remove(authority);
+        }
+    }
+
+    // A connection to `authority` which can open a request stream right away
+    pub fn get(&self, authority: &str) -> Option<ConnectionRef> {
+        let mut connections = self.connections.lock().unwrap();
+        let pooled = connections.get_mut(authority)?;
+        pooled.retain(is_reusable);
+        let conn = pooled
+            .iter()
+            .find(|c| c.quic.available_streams(Dir::Bi) > 0)
+            .cloned();
+        if pooled.is_empty() {
+            connections.remove(authority);
+        }
+        conn
+    }
+
+    pub fn insert(&self, authority: String, conn: ConnectionRef) {
+        self.connections
+            .lock()
+            .unwrap()
+            .entry(authority)
+            .or_default()
+            .push(conn);
+    }
+
+    #[cfg(test)]
+    pub fn len(&self, authority: &str) -> usize {
+        let connections = self.connections.lock().unwrap();
+        connections.get(authority).map_or(0, |pooled| pooled.len())
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        let connections = self.connections.get_mut().unwrap();
+        for conn in connections.drain().flat_map(|(_, pooled)| pooled) {
+            conn.quic
+                .close(ErrorCode::NO_ERROR.into(), b"Connection closed");
+        }
+    }
+}
+
+// Connections closed, or whose server sent a GOAWAY, cannot take new requests. Dropping them from
+// the pool lets those in flight finish.
+fn is_reusable(conn: &ConnectionRef) -> bool {
+    conn.quic.close_reason().is_none() && !conn.h3.lock().unwrap().inner.is_closing()
+}
@@ -0,0 +1,120 @@
+use std::io::Cursor;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use quinn_proto::coding::{BufExt, BufMutExt};
+
+/// A message of the Capsule Protocol
+///
+/// Capsules are sent in place of the body of a request or its response, once both ends agreed to
+/// use the Capsule Protocol. They are sent with a [`CapsuleBody`] and received with
+/// [`RecvCapsules`].
+///
+/// [`CapsuleBody`]: struct.CapsuleBody.html
+/// [`RecvCapsules`]: struct.RecvCapsules.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Capsule {
+    /// An HTTP datagram, carried reliably on the request stream
+    Datagram(Bytes),
+    /// A capsule of a type this crate does not know about
+    Unknown {
+        /// Capsule type
+        ty: u64,
+        /// Undecoded capsule value
+        payload: Bytes,
+    },
+}
+
+impl Capsule {
+    /// Encode the capsule, prefixed by its type and length
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        let (ty, payload) = match self {
+            Capsule::Datagram(payload) => (DATAGRAM, payload),
+            Capsule::Unknown { ty, payload } => (*ty, payload),
+        };
+        buf.write_var(ty);
+        buf.write_var(payload.len() as u64);
+        buf.put_slice(payload);
+    }
+
+    /// Decode the first capsule of `buf`, `None` until it is complete
+    pub(crate) fn decode(buf: &mut BytesMut) -> Result<Option<Self>, Error> {
+        let mut cursor = Cursor::new(&buf[..]);
+        let (ty, len) = match (cursor.get_var(), cursor.get_var()) {
+            (Ok(ty), Ok(len)) => (ty, len),
+            _ => return Ok(None),
+        };
+        if len > MAX_CAPSULE_SIZE {
+            return Err(Error::TooLarge(len));
+        }
+
+        let header_len = cursor.position() as usize;
+        if buf.len() < header_len + len as usize {
+            return Ok(None);
+        }
+        buf.advance(header_len);
+        let payload = buf.split_to(len as usize).freeze();
+
+        Ok(Some(match ty {
+            DATAGRAM => Capsule::Datagram(payload),
+            ty => Capsule::Unknown { ty, payload },
+        }))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    TooLarge(u64),
+}
+
+const DATAGRAM: u64 = 0x00;
+// Largest capsule value accepted, enough for any UDP payload
+const MAX_CAPSULE_SIZE: u64 = 1 << 16;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capsule_codec() {
+        let capsules = [
+            Capsule::Datagram(Bytes::from_static(b"datagram")),
+            Capsule::Unknown {
+                ty: 0x1234,
+                payload: Bytes::from_static(b"unknown"),
+            },
+            Capsule::Datagram(Bytes::new()),
+        ];
+
+        let mut buf = BytesMut::new();
+        for capsule in capsules.iter() {
+            capsule.encode(&mut buf);
+        }
+        for capsule in capsules.iter() {
+            assert_eq!(Capsule::decode(&mut buf), Ok(Some(capsule.clone())));
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn incomplete_capsule() {
+        let mut buf = BytesMut::new();
+        Capsule::Datagram(Bytes::from_static(b"datagram")).encode(&mut buf);
+        let rest = buf.split_off(4);
+
+        assert_eq!(Capsule::decode(&mut buf), Ok(None));
+        assert_eq!(buf.len(), 4);
+        buf.unsplit(rest);
+        assert_matches!(Capsule::decode(&mut buf), Ok(Some(Capsule::Datagram(_))));
+    }
+
+    #[test]
+    fn capsule_too_large() {
+        let mut buf = BytesMut::new();
+        buf.write_var(DATAGRAM);
+        buf.write_var(MAX_CAPSULE_SIZE + 1);
+        assert_eq!(
+            Capsule::decode(&mut buf),
+            Err(Error::TooLarge(MAX_CAPSULE_SIZE + 1))
+        );
+    }
+}
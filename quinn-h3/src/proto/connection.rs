@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use bytes::{Buf, Bytes, BytesMut};
 use quinn_proto::StreamId;
@@ -7,8 +7,9 @@ use tracing::trace;
 
 use crate::{
     proto::{
-        frame::{HeadersFrame, HttpFrame},
+        frame::{HeadersFrame, HttpFrame, PriorityUpdateFrame, PushPromiseFrame},
         headers::{self, Header},
+        priority::Priority,
     },
     qpack::{self, DecoderError, DynamicTable, EncoderError, HeaderField},
     Settings,
@@ -46,8 +47,19 @@ pub struct Connection {
     encoder_table: DynamicTable,
     pending_streams: [BytesMut; 3],
     requests_in_flight: HashSet<StreamId>,
-    max_id_in_flight: u64,
+    // Lowest ID of the request streams not received yet, sent in GOAWAY
+    next_request_id: u64,
     go_away: bool,
+    // Lowest ID of the request streams the peer will not process, from its last GOAWAY
+    peer_go_away: Option<StreamId>,
+    // Highest push ID the peer lets us use, from its last MAX_PUSH_ID frame
+    max_push_id: Option<u64>,
+    next_push_id: u64,
+    // Highest push ID we let the peer use
+    local_max_push_id: Option<u64>,
+    cancelled_pushes: HashSet<u64>,
+    // Priorities the client updated, not yet applied to the response streams
+    priority_updates: HashMap<StreamId, Priority>,
 
     #[cfg(feature = "interop-test-accessors")]
     pub had_refs: bool,
@@ -77,8 +89,14 @@ impl Connection {
             remote_settings: None,
             encoder_table: DynamicTable::new(),
             requests_in_flight: HashSet::with_capacity(32),
-            max_id_in_flight: 0,
+            next_request_id: 0,
             go_away: false,
+            peer_go_away: None,
+            max_push_id: None,
+            next_push_id: 0,
+            local_max_push_id: None,
+            cancelled_pushes: HashSet::new(),
+            priority_updates: HashMap::new(),
 
             #[cfg(feature = "interop-test-accessors")]
             had_refs: false,
@@ -105,6 +123,19 @@ impl Connection {
         })
     }
 
+    pub fn encode_push_promise(
+        &mut self,
+        stream_id: StreamId,
+        push_id: u64,
+        headers: Header,
+    ) -> Result<PushPromiseFrame> {
+        let HeadersFrame { encoded } = self.encode_header(stream_id, headers)?;
+        Ok(PushPromiseFrame {
+            id: push_id,
+            encoded,
+        })
+    }
+
     pub fn decode_header(
         &mut self,
         stream_id: StreamId,
@@ -190,31 +221,56 @@ impl Connection {
     pub fn request_initiated(&mut self, id: StreamId) {
         if !self.go_away {
             self.requests_in_flight.insert(id);
-            self.max_id_in_flight = cmp::max(id.0, self.max_id_in_flight);
+            self.next_request_id = cmp::max(id.0 + 4, self.next_request_id);
         }
     }
 
     pub fn request_finished(&mut self, id: StreamId) {
-        if !self.go_away {
-            self.requests_in_flight.remove(&id);
-        }
+        self.priority_updates.remove(&id);
+        self.requests_in_flight.remove(&id);
     }
 
     pub fn requests_in_flight(&self) -> usize {
         self.requests_in_flight.len()
     }
 
+    // Whether the request on `id` may still establish a WebTransport session: it is yet to be
+    // received and would be accepted, or it is still being processed
+    pub fn may_open_session(&self, id: StreamId) -> bool {
+        (!self.go_away && id.0 >= self.next_request_id) || self.requests_in_flight.contains(&id)
+    }
+
     pub fn go_away(&mut self) {
         if !self.go_away {
             self.go_away = true;
-            HttpFrame::Goaway(self.max_id_in_flight)
+            HttpFrame::Goaway(self.next_request_id)
+                .encode(&mut self.pending_streams[PendingStreamType::Control as usize]);
+        }
+    }
+
+    // First GOAWAY of a graceful shutdown: the peer stops opening requests, but those already
+    // sent are still accepted until `go_away()` is called
+    pub fn announce_go_away(&mut self) {
+        if !self.go_away {
+            HttpFrame::Goaway(MAX_REQUEST_STREAM_ID)
                 .encode(&mut self.pending_streams[PendingStreamType::Control as usize]);
         }
     }
 
-    pub fn leave(&mut self, id: StreamId) {
+    pub fn leave(&mut self, id: StreamId) -> Result<()> {
+        // Only client-initiated bidirectional streams are requests, and a GOAWAY cannot take
+        // back the promise to process those below a previous one
+        if id.0 % 4 != 0 || self.peer_go_away.map_or(false, |last| id.0 > last.0) {
+            return Err(Error::InvalidStreamId(id.0));
+        }
         self.go_away = true;
-        self.requests_in_flight.retain(|i| i.0 <= id.0);
+        self.peer_go_away = Some(id);
+        self.requests_in_flight.retain(|i| i.0 < id.0);
+        Ok(())
+    }
+
+    pub fn peer_go_away(&self) -> Option<StreamId> {
+        self.peer_go_away
     }
 
     pub fn stream_cancel(&mut self, stream_id: StreamId) {
@@ -227,8 +283,101 @@ impl Connection {
     pub fn is_closing(&self) -> bool {
         self.go_away
     }
+
+    pub fn allow_pushes(&mut self, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let max = count - 1;
+        if self.local_max_push_id.map_or(false, |x| x >= max) {
+            return;
+        }
+        self.local_max_push_id = Some(max);
+        HttpFrame::MaxPushId(max)
+            .encode(&mut self.pending_streams[PendingStreamType::Control as usize]);
+    }
+
+    pub fn check_push_id(&self, id: u64) -> Result<()> {
+        match self.local_max_push_id {
+            Some(max) if id <= max => Ok(()),
+            _ => Err(Error::InvalidPushId(id)),
+        }
+    }
+
+    pub fn set_max_push_id(&mut self, id: u64) -> Result<()> {
+        if self.max_push_id.map_or(false, |max| id < max) {
+            return Err(Error::InvalidPushId(id));
+        }
+        self.max_push_id = Some(id);
+        Ok(())
+    }
+
+    pub fn next_push_id(&mut self) -> Option<u64> {
+        match self.max_push_id {
+            Some(max) if self.next_push_id <= max => {
+                self.next_push_id += 1;
+                Some(self.next_push_id - 1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn cancel_push(&mut self, id: u64) {
+        if self.cancelled_pushes.insert(id) {
+            HttpFrame::CancelPush(id)
+                .encode(&mut self.pending_streams[PendingStreamType::Control as usize]);
+        }
+    }
+
+    pub fn on_cancel_push(&mut self, id: u64) {
+        self.cancelled_pushes.insert(id);
+    }
+
+    pub fn is_push_cancelled(&self, id: u64) -> bool {
+        self.cancelled_pushes.contains(&id)
+    }
+
+    pub fn priority_update(&mut self, stream_id: StreamId, priority: Priority) {
+        HttpFrame::PriorityUpdate(PriorityUpdateFrame {
+            push: false,
+            id: stream_id.0,
+            priority,
+        })
+        .encode(&mut self.pending_streams[PendingStreamType::Control as usize]);
+    }
+
+    pub fn on_priority_update(&mut self, frame: PriorityUpdateFrame) -> Result<()> {
+        if frame.push {
+            // Pushed responses are sent with the priority of the request that promised them
+            return Ok(());
+        }
+        // Only client-initiated bidirectional streams carry requests
+        if frame.id % 4 != 0 {
+            return Err(Error::InvalidStreamId(frame.id));
+        }
+        if self.priority_updates.len() >= MAX_PRIORITY_UPDATES
+            && !self.priority_updates.contains_key(&StreamId(frame.id))
+        {
+            trace!("too many priority updates, ignoring {}", frame.id);
+            return Ok(());
+        }
+        self.priority_updates
+            .insert(StreamId(frame.id), frame.priority);
+        Ok(())
+    }
+
+    pub fn take_priority_update(&mut self, stream_id: StreamId) -> Option<Priority> {
+        self.priority_updates.remove(&stream_id)
+    }
 }
 
+// Bounds the memory used by updates for requests not yet received
+const MAX_PRIORITY_UPDATES: usize = 256;
+
+// Largest ID of a client-initiated bidirectional stream, announcing a shutdown without refusing
+// any request yet
+const MAX_REQUEST_STREAM_ID: u64 = (1 << 62) - 4;
+
 type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, PartialEq)]
@@ -238,6 +387,8 @@ pub enum Error {
     InvalidHeaderValue(String),
     InvalidRequest(String),
     InvalidResponse(String),
+    InvalidPushId(u64),
+    InvalidStreamId(u64),
     Settings { reason: String },
     EncodeError { reason: EncoderError },
     DecodeError { reason: DecoderError },
@@ -271,6 +422,12 @@ impl From<headers::Error> for Error {
             headers::Error::InvalidRequest(e) => Error::InvalidRequest(format!("{:?}", e)),
             headers::Error::MissingMethod => Error::InvalidRequest("missing method".into()),
             headers::Error::MissingStatus => Error::InvalidResponse("missing status".into()),
+            headers::Error::UnexpectedProtocol => {
+                Error::InvalidRequest(":protocol outside of CONNECT".into())
+            }
+            headers::Error::MalformedConnect => {
+                Error::InvalidRequest("CONNECT without authority, or with scheme or path".into())
+            }
         }
     }
 }
@@ -296,8 +453,14 @@ mod tests {
                     BytesMut::with_capacity(2048),
                 ],
                 requests_in_flight: HashSet::with_capacity(32),
-                max_id_in_flight: 0,
+                next_request_id: 0,
                 go_away: false,
+                peer_go_away: None,
+                max_push_id: None,
+                next_push_id: 0,
+                local_max_push_id: None,
+                cancelled_pushes: HashSet::new(),
+                priority_updates: HashMap::new(),
 
                 #[cfg(feature = "interop-test-accessors")]
                 had_refs: false,
@@ -378,6 +541,92 @@ mod tests {
         assert!(server.pending_streams[PendingStreamType::Decoder as usize].is_empty());
     }
 
+    #[test]
+    fn push_ids_within_max_push_id() {
+        let mut conn = Connection::default();
+        assert_eq!(conn.next_push_id(), None);
+
+        conn.set_max_push_id(1).unwrap();
+        assert_eq!(conn.next_push_id(), Some(0));
+        assert_eq!(conn.next_push_id(), Some(1));
+        assert_eq!(conn.next_push_id(), None);
+
+        assert_eq!(conn.set_max_push_id(0), Err(Error::InvalidPushId(0)));
+        conn.set_max_push_id(2).unwrap();
+        assert_eq!(conn.next_push_id(), Some(2));
+    }
+
+    #[test]
+    fn allow_pushes() {
+        let mut conn = Connection::default();
+        conn.allow_pushes(0);
+        assert_eq!(conn.check_push_id(0), Err(Error::InvalidPushId(0)));
+        assert!(conn.pending_streams[PendingStreamType::Control as usize].is_empty());
+
+        conn.allow_pushes(2);
+        assert_eq!(conn.check_push_id(1), Ok(()));
+        assert_eq!(conn.check_push_id(2), Err(Error::InvalidPushId(2)));
+        assert!(!conn.pending_streams[PendingStreamType::Control as usize].is_empty());
+    }
+
+    #[test]
+    fn priority_updates() {
+        let mut conn = Connection::default();
+        let update = |id, push| PriorityUpdateFrame {
+            push,
+            id,
+            priority: Priority::new(0, true),
+        };
+
+        assert_eq!(
+            conn.on_priority_update(update(2, false)),
+            Err(Error::InvalidStreamId(2))
+        );
+        conn.on_priority_update(update(3, true)).unwrap();
+        conn.on_priority_update(update(4, false)).unwrap();
+        assert_eq!(conn.take_priority_update(StreamId(3)), None);
+        assert_eq!(
+            conn.take_priority_update(StreamId(4)),
+            Some(Priority::new(0, true))
+        );
+        assert_eq!(conn.take_priority_update(StreamId(4)), None);
+
+        conn.on_priority_update(update(8, false)).unwrap();
+        conn.request_finished(StreamId(8));
+        assert_eq!(conn.take_priority_update(StreamId(8)), None);
+    }
+
+    #[test]
+    fn go_away() {
+        let mut server = Connection::default();
+        server.request_initiated(StreamId(0));
+        server.request_initiated(StreamId(8));
+        server.announce_go_away();
+        assert!(!server.is_closing());
+        server.go_away();
+        assert!(server.is_closing());
+        server.request_finished(StreamId(0));
+        assert_eq!(server.requests_in_flight(), 1);
+
+        let mut control = server.pending_streams[PendingStreamType::Control as usize].clone();
+        assert_eq!(
+            HttpFrame::decode(&mut control),
+            Ok(HttpFrame::Goaway(MAX_REQUEST_STREAM_ID))
+        );
+        assert_eq!(HttpFrame::decode(&mut control), Ok(HttpFrame::Goaway(12)));
+    }
+
+    #[test]
+    fn leave() {
+        let mut client = Connection::default();
+        assert_eq!(client.leave(StreamId(2)), Err(Error::InvalidStreamId(2)));
+        client.leave(StreamId(MAX_REQUEST_STREAM_ID)).unwrap();
+        client.leave(StreamId(8)).unwrap();
+        assert!(client.is_closing());
+        assert_eq!(client.peer_go_away(), Some(StreamId(8)));
+        assert_eq!(client.leave(StreamId(12)), Err(Error::InvalidStreamId(12)));
+    }
+
     #[test]
     fn decode_blocked() {
         let mut header_map = HeaderMap::new();
@@ -7,7 +7,10 @@ use quinn_proto::{
 };
 use tracing::trace;
 
-use super::settings::{Error as SettingsError, SettingId, SettingsFrame};
+use super::{
+    priority::Priority,
+    settings::{Error as SettingsError, SettingId, SettingsFrame},
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
@@ -34,6 +37,7 @@ pub enum HttpFrame {
     Goaway(u64),
     MaxPushId(u64),
     DuplicatePush(u64),
+    PriorityUpdate(PriorityUpdateFrame),
     Reserved,
 }
 
@@ -48,6 +52,7 @@ impl HttpFrame {
             HttpFrame::Goaway(id) => simple_frame_encode(Type::GOAWAY, *id, buf),
             HttpFrame::MaxPushId(id) => simple_frame_encode(Type::MAX_PUSH_ID, *id, buf),
             HttpFrame::DuplicatePush(id) => simple_frame_encode(Type::DUPLICATE_PUSH, *id, buf),
+            HttpFrame::PriorityUpdate(f) => f.encode(buf),
             HttpFrame::Reserved => (),
         }
     }
@@ -80,6 +85,13 @@ impl HttpFrame {
             Type::GOAWAY => Ok(HttpFrame::Goaway(payload.get_var()?)),
             Type::MAX_PUSH_ID => Ok(HttpFrame::MaxPushId(payload.get_var()?)),
             Type::DUPLICATE_PUSH => Ok(HttpFrame::DuplicatePush(payload.get_var()?)),
+            Type::PRIORITY_UPDATE => Ok(HttpFrame::PriorityUpdate(PriorityUpdateFrame::decode(
+                false,
+                &mut payload,
+            )?)),
+            Type::PRIORITY_UPDATE_PUSH => Ok(HttpFrame::PriorityUpdate(
+                PriorityUpdateFrame::decode(true, &mut payload)?,
+            )),
             Type::H2_PRIORITY | Type::H2_PING | Type::H2_WINDOW_UPDATE | Type::H2_CONTINUATION => {
                 Err(Error::UnsupportedFrame(ty.0))
             }
@@ -112,6 +124,9 @@ impl fmt::Display for HttpFrame {
             HttpFrame::Goaway(id) => write!(f, "GoAway({})", id),
             HttpFrame::MaxPushId(id) => write!(f, "MaxPushId({})", id),
             HttpFrame::DuplicatePush(id) => write!(f, "DuplicatePush({})", id),
+            HttpFrame::PriorityUpdate(frame) => {
+                write!(f, "PriorityUpdate({}, {})", frame.id, frame.priority)
+            }
             HttpFrame::Reserved => write!(f, "Reserved"),
         }
     }
@@ -137,6 +152,9 @@ frame_types! {
     H2_CONTINUATION = 0x9,
     MAX_PUSH_ID = 0xD,
     DUPLICATE_PUSH = 0xE,
+    WEBTRANSPORT_STREAM = 0x41,
+    PRIORITY_UPDATE = 0xF0700,
+    PRIORITY_UPDATE_PUSH = 0xF0701,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -263,8 +281,8 @@ impl IntoPayload for HeadersFrame {
 
 #[derive(Debug, PartialEq)]
 pub struct PushPromiseFrame {
-    id: u64,
-    encoded: Bytes,
+    pub id: u64,
+    pub encoded: Bytes,
 }
 
 impl FrameHeader for PushPromiseFrame {
@@ -281,13 +299,44 @@ impl PushPromiseFrame {
             encoded: buf.to_bytes(),
         })
     }
-    fn encode<B: BufMut>(&self, buf: &mut B) {
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
         self.encode_header(buf);
         buf.write_var(self.id);
         buf.put(self.encoded.clone());
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct PriorityUpdateFrame {
+    /// Whether `id` designates a push rather than a request stream
+    pub push: bool,
+    pub id: u64,
+    pub priority: Priority,
+}
+
+impl PriorityUpdateFrame {
+    fn decode<B: Buf>(push: bool, buf: &mut B) -> Result<Self, UnexpectedEnd> {
+        Ok(PriorityUpdateFrame {
+            push,
+            id: buf.get_var()?,
+            priority: Priority::parse(&buf.to_bytes()),
+        })
+    }
+
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        let value = self.priority.to_string();
+        let ty = if self.push {
+            Type::PRIORITY_UPDATE_PUSH
+        } else {
+            Type::PRIORITY_UPDATE
+        };
+        ty.encode(buf);
+        buf.write_var((VarInt::from_u64(self.id).unwrap().size() + value.len()) as u64);
+        buf.write_var(self.id);
+        buf.put_slice(value.as_bytes());
+    }
+}
+
 fn simple_frame_encode<B: BufMut>(ty: Type, id: u64, buf: &mut B) {
     ty.encode(buf);
     buf.write_var(1);
@@ -411,6 +460,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn priority_update_frames() {
+        codec_frame_check(
+            HttpFrame::PriorityUpdate(PriorityUpdateFrame {
+                push: false,
+                id: 4,
+                priority: Priority::new(1, true),
+            }),
+            &[0x80, 0x0f, 0x07, 0x00, 7, 4, 117, 61, 49, 44, 32, 105],
+        );
+        codec_frame_check(
+            HttpFrame::PriorityUpdate(PriorityUpdateFrame {
+                push: true,
+                id: 2,
+                priority: Priority::default(),
+            }),
+            &[0x80, 0x0f, 0x07, 0x01, 4, 2, 117, 61, 51],
+        );
+    }
+
     #[test]
     fn reserved_frame() {
         let mut raw = vec![];
@@ -2,6 +2,7 @@ use std::{
     borrow::Cow,
     collections::HashMap,
     convert::TryFrom,
+    fmt,
     iter::{IntoIterator, Iterator},
     str::FromStr,
 };
@@ -37,6 +38,12 @@ impl Header {
         }
     }
 
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        if self.pseudo.protocol.replace(protocol).is_none() {
+            self.pseudo.len += 1;
+        }
+    }
+
     pub fn trailer(fields: HeaderMap) -> Self {
         Self {
             pseudo: Pseudo::default(),
@@ -44,7 +51,22 @@ impl Header {
         }
     }
 
-    pub fn into_request_parts(self) -> Result<(Method, Uri, HeaderMap), Error> {
+    pub fn into_request_parts(self) -> Result<(Method, Uri, Option<Protocol>, HeaderMap), Error> {
+        let method = self.pseudo.method.ok_or(Error::MissingMethod)?;
+        if self.pseudo.protocol.is_some() && method != Method::CONNECT {
+            return Err(Error::UnexpectedProtocol);
+        }
+
+        // A tunnel request only carries the authority to connect to
+        if method == Method::CONNECT
+            && self.pseudo.protocol.is_none()
+            && (self.pseudo.authority.is_none()
+                || self.pseudo.scheme.is_some()
+                || self.pseudo.path.is_some())
+        {
+            return Err(Error::MalformedConnect);
+        }
+
         let mut uri = Uri::builder();
 
         if let Some(path) = self.pseudo.path {
@@ -60,8 +82,9 @@ impl Header {
         }
 
         Ok((
-            self.pseudo.method.ok_or(Error::MissingMethod)?,
+            method,
             uri.build().map_err(Error::InvalidRequest)?,
+            self.pseudo.protocol,
             self.fields,
         ))
     }
@@ -104,6 +127,10 @@ impl Iterator for HeaderIter {
                 return Some((":method", method.as_str()).into());
             }
 
+            if let Some(protocol) = pseudo.protocol.take() {
+                return Some((":protocol", protocol.as_str()).into());
+            }
+
             if let Some(scheme) = pseudo.scheme.take() {
                 return Some((":scheme", scheme.as_str().as_bytes()).into());
             }
@@ -162,6 +189,10 @@ impl TryFrom<Vec<HeaderField>> for Header {
                     pseudo.status = Some(s);
                     pseudo.len += 1;
                 }
+                Field::Protocol(p) => {
+                    pseudo.protocol = Some(p);
+                    pseudo.len += 1;
+                }
                 Field::Header((n, v)) => {
                     fields.append(n, v);
                 }
@@ -178,6 +209,7 @@ enum Field {
     Authority(Authority),
     Path(PathAndQuery),
     Status(StatusCode),
+    Protocol(Protocol),
     Header((HeaderName, HeaderValue)),
 }
 
@@ -217,6 +249,7 @@ impl Field {
                 StatusCode::from_bytes(value.as_ref())
                     .or_else(|_| Err(Error::invalid_value(name, value)))?,
             ),
+            PseudoType::PROTOCOL => Field::Protocol(try_value(name, value)?),
         })
     }
 }
@@ -245,6 +278,8 @@ struct Pseudo {
     scheme: Option<Scheme>,
     authority: Option<Authority>,
     path: Option<PathAndQuery>,
+    // Extended CONNECT
+    protocol: Option<Protocol>,
 
     // Response
     status: Option<StatusCode>,
@@ -262,6 +297,19 @@ impl Pseudo {
             ..
         } = uri::Parts::from(uri);
 
+        // Authority-form URI of a CONNECT request, without scheme nor path
+        if method == Method::CONNECT && scheme.is_none() && path_and_query.is_none() {
+            return Self {
+                method: Some(method),
+                scheme: None,
+                len: 1 + if authority.is_some() { 1 } else { 0 },
+                authority,
+                path: None,
+                protocol: None,
+                status: None,
+            };
+        }
+
         let path = path_and_query.map_or_else(
             || PathAndQuery::from_static("/"),
             |path| {
@@ -280,6 +328,7 @@ impl Pseudo {
             scheme: scheme.or(Some(Scheme::HTTPS)),
             authority,
             path: Some(path),
+            protocol: None,
             status: None,
             len,
         }
@@ -291,6 +340,7 @@ impl Pseudo {
             scheme: None,
             authority: None,
             path: None,
+            protocol: None,
             status: Some(status),
             len: 1,
         }
@@ -324,8 +374,67 @@ pseudo_type![
     (AUTHORITY, b":authority"),
     (PATH, b":path"),
     (STATUS, b":status"),
+    (PROTOCOL, b":protocol"),
 ];
 
+/// Protocol tunnelled by an extended `CONNECT` request
+///
+/// Clients set it as an extension of the [`Request`], and it is given to the server the
+/// same way. Both peers need to enable extended `CONNECT` in their [`Settings`].
+///
+/// ```
+/// use http::{Method, Request};
+/// use quinn_h3::{Body, Protocol};
+///
+/// let mut request = Request::connect("https://example.com/chat")
+///     .body(Body::from(()))
+///     .unwrap();
+/// request.extensions_mut().insert(Protocol::WEB_TRANSPORT);
+/// ```
+///
+/// [`Request`]: https://docs.rs/http/*/http/request/struct.Request.html
+/// [`Settings`]: ../struct.Settings.html
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Protocol(Cow<'static, str>);
+
+impl Protocol {
+    /// WebTransport sessions
+    pub const WEB_TRANSPORT: Protocol = Protocol(Cow::Borrowed("webtransport"));
+    /// Proxying UDP in HTTP
+    pub const CONNECT_UDP: Protocol = Protocol(Cow::Borrowed("connect-udp"));
+
+    /// Protocol with a static name
+    pub const fn from_static(name: &'static str) -> Self {
+        Protocol(Cow::Borrowed(name))
+    }
+
+    /// Name of the protocol
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = InvalidProtocol;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.bytes().all(|c| c.is_ascii_graphic()) {
+            return Err(InvalidProtocol);
+        }
+        Ok(Protocol(Cow::Owned(s.into())))
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The protocol name is empty or contains invalid characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidProtocol;
+
 #[derive(Debug)]
 pub enum Error {
     InvalidHeaderName(String),
@@ -333,6 +442,8 @@ pub enum Error {
     InvalidRequest(http::Error),
     MissingMethod,
     MissingStatus,
+    UnexpectedProtocol,
+    MalformedConnect,
 }
 
 impl Error {
@@ -355,3 +466,48 @@ impl Error {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(header: Header) -> Result<(Method, Uri, Option<Protocol>, HeaderMap), Error> {
+        Header::try_from(header.into_iter().collect::<Vec<_>>())?.into_request_parts()
+    }
+
+    #[test]
+    fn connect_authority_form() {
+        let uri = Uri::from_static("example.com:443");
+        let header = Header::request(Method::CONNECT, uri.clone(), HeaderMap::new());
+        assert_eq!(header.len(), 2);
+
+        let (method, received, protocol, _) = round_trip(header).unwrap();
+        assert_eq!(method, Method::CONNECT);
+        assert_eq!(received, uri);
+        assert_eq!(protocol, None);
+    }
+
+    #[test]
+    fn extended_connect() {
+        let uri = Uri::from_static("https://example.com/chat");
+        let mut header = Header::request(Method::CONNECT, uri.clone(), HeaderMap::new());
+        header.set_protocol(Protocol::WEB_TRANSPORT);
+
+        let (_, received, protocol, _) = round_trip(header).unwrap();
+        assert_eq!(received, uri);
+        assert_eq!(protocol, Some(Protocol::WEB_TRANSPORT));
+    }
+
+    #[test]
+    fn malformed_connect() {
+        let header = Header::request(
+            Method::CONNECT,
+            Uri::from_static("https://example.com/"),
+            HeaderMap::new(),
+        );
+        assert_matches!(round_trip(header), Err(Error::MalformedConnect));
+
+        let header = Header::try_from(vec![HeaderField::new(":method", "CONNECT")]).unwrap();
+        assert_matches!(header.into_request_parts(), Err(Error::MalformedConnect));
+    }
+}
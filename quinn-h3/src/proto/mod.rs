@@ -5,9 +5,11 @@ use quinn_proto::{
 };
 use std::fmt;
 
+pub mod capsule;
 pub mod connection;
 pub mod frame;
 pub mod headers;
+pub mod priority;
 pub mod settings;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -26,6 +28,7 @@ stream_types! {
     PUSH = 0x01,
     ENCODER = 0x02,
     DECODER = 0x03,
+    WEBTRANSPORT_UNI = 0x54,
 }
 
 impl StreamType {
@@ -87,6 +90,9 @@ error_codes! {
     QPACK_DECOMPRESSION_FAILED = 0x200,
     QPACK_ENCODER_STREAM_ERROR = 0x201,
     QPACK_DECODER_STREAM_ERROR = 0x202,
+    H3_DATAGRAM_ERROR = 0x33,
+    WEBTRANSPORT_BUFFERED_STREAM_REJECTED = 0x3994_bd84,
+    WEBTRANSPORT_SESSION_GONE = 0x170d_7b68,
 }
 
 impl From<ErrorCode> for VarInt {
@@ -0,0 +1,180 @@
+use std::{cmp, fmt, str};
+
+use http::{HeaderMap, HeaderValue};
+use quinn_proto::StreamId;
+
+/// Priority of a request, following the Extensible Prioritization Scheme for HTTP
+///
+/// Clients signal it in the `priority` request header, and can update it later while the
+/// response is in flight. Servers send the most urgent responses first. Between responses of the
+/// same urgency, incremental ones are interleaved, while others are sent one after the other in
+/// the order they were requested.
+///
+/// ```
+/// use http::Request;
+/// use quinn_h3::{Body, Priority};
+///
+/// let request = Request::get("https://example.com/style.css")
+///     .header("priority", Priority::new(0, false).to_header_value())
+///     .body(Body::from(()))
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priority {
+    urgency: u8,
+    incremental: bool,
+}
+
+impl Priority {
+    /// Create a new priority
+    ///
+    /// `urgency` ranges from 0, the most urgent, to 7, the least. Higher values are clamped.
+    pub fn new(urgency: u8, incremental: bool) -> Self {
+        Self {
+            urgency: cmp::min(urgency, MAX_URGENCY),
+            incremental,
+        }
+    }
+
+    /// Urgency, from 0, the most urgent, to 7
+    pub fn urgency(&self) -> u8 {
+        self.urgency
+    }
+
+    /// Whether the response can be sent interleaved with others of the same urgency
+    pub fn incremental(&self) -> bool {
+        self.incremental
+    }
+
+    /// Parse a `priority` header field value
+    ///
+    /// Unknown and invalid parameters are ignored, leaving their default value.
+    pub fn parse(value: &[u8]) -> Self {
+        let mut priority = Self::default();
+        let value = match str::from_utf8(value) {
+            Ok(x) => x,
+            Err(_) => return priority,
+        };
+
+        for member in value.split(',') {
+            // Parameters of dictionary members carry no meaning here
+            let member = member.split(';').next().unwrap_or("").trim();
+            let mut kv = member.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some("u"), Some(u)) => match u.parse::<u8>() {
+                    Ok(u) if u <= MAX_URGENCY => priority.urgency = u,
+                    _ => (),
+                },
+                (Some("i"), None) | (Some("i"), Some("?1")) => priority.incremental = true,
+                (Some("i"), Some("?0")) => priority.incremental = false,
+                _ => (),
+            }
+        }
+        priority
+    }
+
+    /// Value of the `priority` header field representing this priority
+    pub fn to_header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string()).expect("valid header value")
+    }
+
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(PRIORITY_HEADER)
+            .map(|v| Self::parse(v.as_bytes()))
+            .unwrap_or_default()
+    }
+
+    /// Priority of the QUIC stream sending the response for the request on `stream_id`
+    ///
+    /// Each urgency level gets its own range, incremental responses all share its lowest value
+    /// so QUIC takes turns between them, and the others are ordered above it by stream ID.
+    pub(crate) fn quic_priority(&self, stream_id: StreamId) -> i32 {
+        let base = i32::from(MAX_URGENCY - self.urgency) * URGENCY_RANGE;
+        if self.incremental {
+            return base;
+        }
+        let index = cmp::min(stream_id.index(), URGENCY_RANGE as u64 - 2) as i32;
+        base + URGENCY_RANGE - 1 - index
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self {
+            urgency: DEFAULT_URGENCY,
+            incremental: false,
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "u={}", self.urgency)?;
+        if self.incremental {
+            write!(f, ", i")?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) const PRIORITY_HEADER: &str = "priority";
+
+const DEFAULT_URGENCY: u8 = 3;
+const MAX_URGENCY: u8 = 7;
+// Span of QUIC priorities given to each urgency level
+const URGENCY_RANGE: i32 = 1 << 24;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Priority::parse(b""), Priority::default());
+        assert_eq!(Priority::parse(b"u=1"), Priority::new(1, false));
+        assert_eq!(Priority::parse(b"u=5, i"), Priority::new(5, true));
+        assert_eq!(Priority::parse(b"i=?1,u=0"), Priority::new(0, true));
+        assert_eq!(Priority::parse(b"u=2, i=?0"), Priority::new(2, false));
+    }
+
+    #[test]
+    fn parse_ignores_invalid() {
+        assert_eq!(Priority::parse(b"u=9, i"), Priority::new(3, true));
+        assert_eq!(Priority::parse(b"u=one"), Priority::default());
+        assert_eq!(Priority::parse(b"x=1;y, u=6;z=2"), Priority::new(6, false));
+        assert_eq!(Priority::parse(&[0xff, 0xfe]), Priority::default());
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        for &p in &[
+            Priority::default(),
+            Priority::new(0, true),
+            Priority::new(7, false),
+        ] {
+            assert_eq!(Priority::parse(p.to_string().as_bytes()), p);
+        }
+        assert_eq!(Priority::new(1, true).to_string(), "u=1, i");
+    }
+
+    #[test]
+    fn quic_priority_order() {
+        let first = StreamId(0);
+        let second = StreamId(4);
+
+        let urgent = Priority::new(0, true).quic_priority(second);
+        let sequential = Priority::new(1, false);
+        assert!(urgent > sequential.quic_priority(first));
+        assert!(sequential.quic_priority(first) > sequential.quic_priority(second));
+        assert!(sequential.quic_priority(second) > Priority::new(1, true).quic_priority(first));
+        assert_eq!(
+            Priority::new(4, true).quic_priority(first),
+            Priority::new(4, true).quic_priority(second)
+        );
+        assert!(
+            Priority::new(1, true).quic_priority(first)
+                > Priority::new(2, false).quic_priority(first)
+        );
+    }
+}
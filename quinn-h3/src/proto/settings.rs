@@ -25,6 +25,9 @@ setting_identifiers! {
     QPACK_MAX_TABLE_CAPACITY = 0x1,
     QPACK_MAX_BLOCKED_STREAMS = 0x7,
     MAX_HEADER_LIST_SIZE = 0x6,
+    ENABLE_CONNECT_PROTOCOL = 0x8,
+    H3_DATAGRAM = 0x33,
+    ENABLE_WEBTRANSPORT = 0x2b60_3742,
 }
 
 impl Codec for SettingId {
@@ -43,12 +46,19 @@ impl Codec for SettingId {
 ///
 /// See the [QPACK] specification for more details.
 ///
+/// Extensions to the protocol, such as extended `CONNECT` or [WebTransport], are only used once
+/// both peers have enabled them in their settings.
+///
 /// [QPACK]: https://quicwg.org/base-drafts/draft-ietf-quic-qpack.html
+/// [WebTransport]: https://datatracker.ietf.org/doc/draft-ietf-webtrans-http3/
 #[derive(Clone, Debug)]
 pub struct Settings {
     max_header_list_size: u64,
     qpack_max_table_capacity: u64,
     qpack_max_blocked_streams: u64,
+    enable_connect_protocol: bool,
+    h3_datagram: bool,
+    enable_webtransport: bool,
 }
 
 impl Default for Settings {
@@ -58,6 +68,9 @@ impl Default for Settings {
             max_header_list_size: DEFAULT_MAX_HEADER_LIST_SIZE,
             qpack_max_table_capacity: DEFAULT_QPACK_MAX_TABLE_CAPACITY,
             qpack_max_blocked_streams: DEFAULT_QPACK_MAX_BLOCKED_STREAMS,
+            enable_connect_protocol: false,
+            h3_datagram: false,
+            enable_webtransport: false,
         }
     }
 }
@@ -71,6 +84,9 @@ impl Settings {
             max_header_list_size: 0,
             qpack_max_table_capacity: 4096,
             qpack_max_blocked_streams: 128,
+            enable_connect_protocol: false,
+            h3_datagram: false,
+            enable_webtransport: false,
         }
     }
 
@@ -131,6 +147,48 @@ impl Settings {
         Ok(self)
     }
 
+    /// Whether extended `CONNECT` requests, carrying a `:protocol`, are accepted
+    pub fn enable_connect_protocol(&self) -> bool {
+        self.enable_connect_protocol
+    }
+
+    /// Whether HTTP datagrams can be received
+    pub fn h3_datagram(&self) -> bool {
+        self.h3_datagram
+    }
+
+    /// Whether WebTransport sessions are accepted
+    pub fn enable_webtransport(&self) -> bool {
+        self.enable_webtransport
+    }
+
+    /// Accept extended `CONNECT` requests
+    pub fn set_enable_connect_protocol(&mut self, value: bool) -> &mut Self {
+        self.enable_connect_protocol = value;
+        self
+    }
+
+    /// Accept HTTP datagrams
+    ///
+    /// QUIC datagrams also need to be enabled in the transport configuration.
+    pub fn set_h3_datagram(&mut self, value: bool) -> &mut Self {
+        self.h3_datagram = value;
+        self
+    }
+
+    /// Accept WebTransport sessions
+    ///
+    /// Enabling WebTransport also enables extended `CONNECT` and HTTP datagrams, which it
+    /// relies on.
+    pub fn set_enable_webtransport(&mut self, value: bool) -> &mut Self {
+        if value {
+            self.enable_connect_protocol = true;
+            self.h3_datagram = true;
+        }
+        self.enable_webtransport = value;
+        self
+    }
+
     pub(crate) fn from_frame(settings: SettingsFrame) -> Result<Settings, Error> {
         let mut this = Self::default();
         for (id, val) in settings.entries[..settings.len].iter() {
@@ -138,6 +196,18 @@ impl Settings {
                 SettingId::MAX_HEADER_LIST_SIZE => this.set_max_header_list_size(*val)?,
                 SettingId::QPACK_MAX_TABLE_CAPACITY => this.set_qpack_max_table_capacity(*val)?,
                 SettingId::QPACK_MAX_BLOCKED_STREAMS => this.set_qpack_max_blocked_streams(*val)?,
+                SettingId::ENABLE_CONNECT_PROTOCOL => {
+                    this.enable_connect_protocol = bool_value(*id, *val)?;
+                    &mut this
+                }
+                SettingId::H3_DATAGRAM => {
+                    this.h3_datagram = bool_value(*id, *val)?;
+                    &mut this
+                }
+                SettingId::ENABLE_WEBTRANSPORT => {
+                    this.enable_webtransport = bool_value(*id, *val)?;
+                    &mut this
+                }
                 x => return Err(Error::InvalidSettingId(x.0)),
             };
         }
@@ -167,20 +237,39 @@ impl Settings {
                 )
                 .expect("qpack max blocked");
         }
+        let flags = [
+            (
+                SettingId::ENABLE_CONNECT_PROTOCOL,
+                self.enable_connect_protocol,
+            ),
+            (SettingId::H3_DATAGRAM, self.h3_datagram),
+            (SettingId::ENABLE_WEBTRANSPORT, self.enable_webtransport),
+        ];
+        for (id, _) in flags.iter().filter(|(_, enabled)| *enabled) {
+            frame.insert(*id, 1).expect("extension setting");
+        }
         frame
     }
 }
 
+fn bool_value(id: SettingId, value: u64) -> Result<bool, InvalidValue> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(InvalidValue(id, value)),
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct SettingsFrame {
-    entries: [(SettingId, u64); 3],
+    entries: [(SettingId, u64); 6],
     len: usize,
 }
 
 impl Default for SettingsFrame {
     fn default() -> Self {
         Self {
-            entries: [(SettingId::NONE, 0); 3],
+            entries: [(SettingId::NONE, 0); 6],
             len: 0,
         }
     }
@@ -195,7 +284,10 @@ impl SettingsFrame {
         match id {
             SettingId::MAX_HEADER_LIST_SIZE
             | SettingId::QPACK_MAX_TABLE_CAPACITY
-            | SettingId::QPACK_MAX_BLOCKED_STREAMS => (),
+            | SettingId::QPACK_MAX_BLOCKED_STREAMS
+            | SettingId::ENABLE_CONNECT_PROTOCOL
+            | SettingId::H3_DATAGRAM
+            | SettingId::ENABLE_WEBTRANSPORT => (),
             _ => return Ok(()),
         }
 
@@ -316,7 +408,7 @@ mod tests {
     }
 
     #[test]
-    fn settings_frame_no_more_than_3() {
+    fn settings_frame_no_more_than_6() {
         let mut frame = SettingsFrame::default();
         frame.insert(SettingId::MAX_HEADER_LIST_SIZE, 0).unwrap();
         frame
@@ -325,9 +417,34 @@ mod tests {
         frame
             .insert(SettingId::QPACK_MAX_BLOCKED_STREAMS, 2)
             .unwrap();
+        frame.insert(SettingId::ENABLE_CONNECT_PROTOCOL, 1).unwrap();
+        frame.insert(SettingId::H3_DATAGRAM, 1).unwrap();
+        frame.insert(SettingId::ENABLE_WEBTRANSPORT, 1).unwrap();
         assert_matches!(frame.insert(SettingId::NONE, 42), Err(Error::Exceeded));
     }
 
+    #[test]
+    fn settings_webtransport() {
+        let mut settings = Settings::default();
+        settings.set_enable_webtransport(true);
+        let frame = settings.to_frame();
+        assert_eq!(frame.len, 3);
+        let settings = Settings::from_frame(frame).unwrap();
+        assert!(settings.enable_connect_protocol());
+        assert!(settings.h3_datagram());
+        assert!(settings.enable_webtransport());
+    }
+
+    #[test]
+    fn settings_flag_invalid_value() {
+        let mut frame = SettingsFrame::default();
+        frame.insert(SettingId::H3_DATAGRAM, 2).unwrap();
+        assert_matches!(
+            Settings::from_frame(frame),
+            Err(Error::InvalidSettingValue(SettingId::H3_DATAGRAM, 2))
+        );
+    }
+
     #[test]
     fn settings_default_values_not_encoded() {
         let mut settings = Settings::default();
@@ -14,6 +14,10 @@
 //! representing the header reception: [`RecvRequest`] will resolve into the request's header values,
 //! along with a [`Sender`] to manage the response.
 //!
+//! Instead of driving these by hand, [`serve()`] dispatches every request to a [`tower::Service`],
+//! sending back the response it returns. Middleware stacks can then be shared with HTTP/1 and
+//! HTTP/2 servers, such as `hyper`.
+//!
 //! # Example: simple server
 //!
 //! ```
@@ -93,6 +97,8 @@
 //! [`BodyWriter`]: ../struct.BodyWriter.html
 //! [`Sender`]: struct.Sender.html
 //! [`Sender::send_response()`]: struct.Sender.html#method.send_response
+//! [`serve()`]: fn.serve.html
+//! [`tower::Service`]: https://docs.rs/tower-service/*/tower_service/trait.Service.html
 //! [`AsyncWrite`]: https://docs.rs/futures/*/futures/io/trait.AsyncWrite.html
 //! [`http::Response<B>`]: https://docs.rs/http/*/http/response/index.html
 //! [`types`]: ../enum.Body.html
@@ -105,24 +111,30 @@ use std::{
     net::{Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket},
     pin::Pin,
     task::{Context, Poll},
+    time::Instant,
 };
 
-use futures::{ready, FutureExt, Stream};
-use http::{response, Request, Response};
+use bytes::BytesMut;
+use futures::{future, ready, FutureExt, Stream, StreamExt};
+use http::{request, response, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
 use http_body::Body as HttpBody;
-use quinn::{
-    CertificateChain, EndpointBuilder, PrivateKey, RecvStream, SendStream, ZeroRttAccepted,
-};
-use quinn_proto::Side;
+use quinn::{CertificateChain, EndpointBuilder, PrivateKey, SendStream, VarInt, ZeroRttAccepted};
+use quinn_proto::{coding::BufMutExt, Side};
 use rustls::TLSError;
+use tower_service::Service;
+use tracing::debug;
 
 use crate::{
     body::RecvBody,
+    connect_udp::UdpTunnel,
     connection::{ConnectionDriver, ConnectionRef},
     data::{RecvData, SendData},
-    frame::FrameDecoder,
-    proto::{headers::Header, ErrorCode},
-    Error, Settings,
+    datagram::{RequestDatagrams, CAPSULE_PROTOCOL},
+    frame::FrameStream,
+    proto::{frame::HttpFrame, headers::Header, priority::Priority, ErrorCode, StreamType},
+    tunnel::Tunnel,
+    webtransport::WebTransportSession,
+    Error, Protocol, Settings,
 };
 
 /// Configure and build a HTTP/3.0 server
@@ -158,9 +170,10 @@ impl Builder {
         let mut endpoint_builder = quinn::Endpoint::builder();
         endpoint_builder.listen(self.config.build());
 
-        let (_, incoming) = endpoint_builder.bind(&self.listen)?;
+        let (endpoint, incoming) = endpoint_builder.bind(&self.listen)?;
 
         Ok(IncomingConnection {
+            endpoint,
             incoming,
             settings: self.settings,
         })
@@ -222,9 +235,10 @@ impl Builder {
         self,
         endpoint: EndpointBuilder,
     ) -> Result<IncomingConnection, quinn::EndpointError> {
-        let (_, incoming) = endpoint.bind(&self.listen)?;
+        let (endpoint, incoming) = endpoint.bind(&self.listen)?;
 
         Ok(IncomingConnection {
+            endpoint,
             incoming,
             settings: self.settings,
         })
@@ -237,8 +251,9 @@ impl Builder {
     ) -> Result<IncomingConnection, quinn::EndpointError> {
         let mut endpoint_builder = quinn::Endpoint::builder();
         endpoint_builder.listen(self.config.build());
-        let (_, incoming) = endpoint_builder.with_socket(socket)?;
+        let (endpoint, incoming) = endpoint_builder.with_socket(socket)?;
         Ok(IncomingConnection {
+            endpoint,
             incoming,
             settings: self.settings,
         })
@@ -275,10 +290,57 @@ impl Builder {
 /// [`Connecting`]: struct.Connecting.html
 /// [`0-RTT`]: struct.Connecting.html#method.into_0rtt
 pub struct IncomingConnection {
+    endpoint: quinn::Endpoint,
     incoming: quinn::Incoming,
     settings: Settings,
 }
 
+impl IncomingConnection {
+    /// Gracefully shut the server down
+    ///
+    /// New connections are refused, and this stream ends. Every connection is sent a first
+    /// `GOAWAY`, so clients stop sending requests on it, then a second one once the requests they
+    /// already sent had time to arrive. Requests received later are rejected with
+    /// `H3_REQUEST_REJECTED`, clients being free to retry them elsewhere. Each connection is closed
+    /// with `H3_NO_ERROR` as soon as its requests in flight are finished, or at `deadline`.
+    ///
+    /// Resolves once every connection is closed.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    /// use futures::{channel::oneshot, StreamExt};
+    /// use quinn_h3::server::{Connecting, IncomingConnection};
+    ///
+    /// # async fn handle_connection(connecting: Connecting) {}
+    /// async fn run(mut incoming: IncomingConnection, mut stop: oneshot::Receiver<()>) {
+    ///     loop {
+    ///         tokio::select! {
+    ///             Some(connecting) = incoming.next() => {
+    ///                 tokio::spawn(handle_connection(connecting));
+    ///             }
+    ///             _ = &mut stop => break,
+    ///         }
+    ///     }
+    ///     incoming.shutdown(Instant::now() + Duration::from_secs(10)).await;
+    /// }
+    /// ```
+    ///
+    /// Connections accepted from an endpoint managed by hand, with [`Connecting::from_quic()`],
+    /// are shut down the same way by `quinn::Endpoint::shutdown()`.
+    ///
+    /// [`Connecting::from_quic()`]: struct.Connecting.html#method.from_quic
+    pub async fn shutdown(&self, deadline: Instant) {
+        self.shutdown_handle().shutdown(deadline).await;
+    }
+
+    /// Handle to shut the server down once this is moved elsewhere, such as into [`serve()`]
+    ///
+    /// [`serve()`]: fn.serve.html
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.endpoint.clone())
+    }
+}
+
 impl Stream for IncomingConnection {
     type Item = Connecting;
 
@@ -292,6 +354,176 @@ impl Stream for IncomingConnection {
     }
 }
 
+/// Shuts a server down, obtained from [`IncomingConnection::shutdown_handle()`]
+///
+/// [`IncomingConnection::shutdown_handle()`]: struct.IncomingConnection.html#method.shutdown_handle
+#[derive(Clone)]
+pub struct ShutdownHandle(quinn::Endpoint);
+
+impl ShutdownHandle {
+    /// Gracefully shut the server down
+    ///
+    /// Same as [`IncomingConnection::shutdown()`].
+    ///
+    /// [`IncomingConnection::shutdown()`]: struct.IncomingConnection.html#method.shutdown
+    pub async fn shutdown(&self, deadline: Instant) {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.0
+            .shutdown(timeout, ErrorCode::NO_ERROR.into(), b"shutdown")
+            .await;
+    }
+}
+
+/// Serve the connections of `incoming` with a [`Service`]
+///
+/// For each connection, `make_service` is called with the client's address to create the service
+/// its requests are dispatched to. Every request is handled in its own task, by a clone of this
+/// service, and the response it returns is sent back to the client. When the service fails, the
+/// request is cancelled.
+///
+/// This resolves once `incoming` ends, connections still being served in their own tasks. It only
+/// fails if `make_service` is not ready to create services anymore. To stop serving, keep a
+/// [`ShutdownHandle`] before passing `incoming` in, and shut the server down with it.
+///
+/// ```
+/// use std::{
+///     convert::Infallible,
+///     net::SocketAddr,
+///     task::{Context, Poll},
+/// };
+///
+/// use futures::future::{self, Ready};
+/// use http::{Request, Response};
+/// use quinn_h3::{
+///     server::{self, IncomingConnection},
+///     Body, RecvBody,
+/// };
+/// use tower_service::Service;
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl Service<Request<RecvBody>> for Hello {
+///     type Response = Response<Body>;
+///     type Error = Infallible;
+///     type Future = Ready<Result<Self::Response, Self::Error>>;
+///
+///     fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+///         Poll::Ready(Ok(()))
+///     }
+///
+///     fn call(&mut self, _: Request<RecvBody>) -> Self::Future {
+///         future::ok(Response::new(Body::from("hello")))
+///     }
+/// }
+///
+/// struct MakeHello;
+///
+/// impl Service<SocketAddr> for MakeHello {
+///     type Response = Hello;
+///     type Error = Infallible;
+///     type Future = Ready<Result<Self::Response, Self::Error>>;
+///
+///     fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+///         Poll::Ready(Ok(()))
+///     }
+///
+///     fn call(&mut self, _: SocketAddr) -> Self::Future {
+///         future::ok(Hello)
+///     }
+/// }
+///
+/// async fn run(incoming: IncomingConnection) -> Result<(), quinn_h3::Error> {
+///     server::serve(incoming, MakeHello).await
+/// }
+/// ```
+///
+/// [`Service`]: https://docs.rs/tower-service/*/tower_service/trait.Service.html
+/// [`ShutdownHandle`]: struct.ShutdownHandle.html
+pub async fn serve<M, S, B>(
+    mut incoming: IncomingConnection,
+    mut make_service: M,
+) -> Result<(), Error>
+where
+    M: Service<SocketAddr, Response = S>,
+    M::Error: Into<Box<dyn StdError + Send + Sync>>,
+    M::Future: Send + 'static,
+    S: Service<Request<RecvBody>, Response = Response<B>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S::Future: Send,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
+{
+    while let Some(connecting) = incoming.next().await {
+        future::poll_fn(|cx| make_service.poll_ready(cx))
+            .await
+            .map_err(|e| Error::Service(e.into()))?;
+        let make = make_service.call(connecting.remote_address());
+        tokio::spawn(async move {
+            let service = match make.await {
+                Ok(service) => service,
+                Err(e) => {
+                    debug!("failed to make service: {}", e.into());
+                    return;
+                }
+            };
+            match connecting.await {
+                Ok(incoming_request) => serve_connection(incoming_request, service).await,
+                Err(e) => debug!("handshake failed: {}", e),
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn serve_connection<S, B>(mut incoming_request: IncomingRequest, service: S)
+where
+    S: Service<Request<RecvBody>, Response = Response<B>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S::Future: Send,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
+{
+    while let Some(recv_request) = incoming_request.next().await {
+        let service = service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_request(recv_request, service).await {
+                debug!("request failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn serve_request<S, B>(recv_request: RecvRequest, mut service: S) -> Result<(), Error>
+where
+    S: Service<Request<RecvBody>, Response = Response<B>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
+{
+    let (request, mut sender) = recv_request.await?;
+    let ready = future::poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .map_err(|e| Error::Service(e.into()));
+    let response = match ready {
+        Ok(()) => service
+            .call(request)
+            .await
+            .map_err(|e| Error::Service(e.into())),
+        Err(e) => Err(e),
+    };
+    match response {
+        Ok(response) => sender.send_response(response).await,
+        Err(e) => {
+            sender.cancel();
+            Err(e)
+        }
+    }
+}
+
 /// HTTP/3 handshake future.
 ///
 /// Represents an ongoing HTTP/3 handshake. Upon success, this future will resolve to a
@@ -305,6 +537,11 @@ pub struct Connecting {
 }
 
 impl Connecting {
+    /// The peer's UDP address
+    pub fn remote_address(&self) -> SocketAddr {
+        self.connecting.remote_address()
+    }
+
     /// Try to convert an ongoing handshake into a 0-RTT enabled exchange.
     ///
     /// # About 0-RTT
@@ -372,11 +609,18 @@ impl Connecting {
             connection,
             bi_streams,
             uni_streams,
+            datagrams,
             ..
         } = new_connection;
 
-        let conn_ref =
-            ConnectionRef::new(connection, Side::Server, uni_streams, bi_streams, settings);
+        let conn_ref = ConnectionRef::new(
+            connection,
+            Side::Server,
+            uni_streams,
+            bi_streams,
+            datagrams,
+            settings,
+        );
         tokio::spawn(ConnectionDriver(conn_ref.clone()));
         Ok((IncomingRequest(conn_ref), zerortt_accepted))
     }
@@ -451,6 +695,7 @@ impl Future for Connecting {
             connection,
             bi_streams,
             uni_streams,
+            datagrams,
             ..
         } = ready!(Pin::new(&mut self.connecting).poll(cx))?;
         let conn_ref = ConnectionRef::new(
@@ -458,6 +703,7 @@ impl Future for Connecting {
             Side::Server,
             uni_streams,
             bi_streams,
+            datagrams,
             self.settings.clone(),
         );
         tokio::spawn(ConnectionDriver(conn_ref.clone()));
@@ -503,10 +749,15 @@ pub struct IncomingRequest(ConnectionRef);
 impl IncomingRequest {
     /// Gracefully close the connection.
     ///
-    /// All currently running requests will be honored, as well as those sent before the
-    /// client received the GoAway frame.
+    /// All requests received so far will be honored, later ones are rejected with
+    /// `H3_REQUEST_REJECTED` so the client can retry them on another connection. The connection is
+    /// closed once the requests in flight are finished.
+    ///
+    /// To shut a whole server down, see [`IncomingConnection::shutdown()`].
+    ///
+    /// [`IncomingConnection::shutdown()`]: struct.IncomingConnection.html#method.shutdown
     pub fn go_away(&mut self) {
-        self.0.h3.lock().unwrap().inner.go_away();
+        self.0.h3.lock().unwrap().go_away();
     }
 }
 
@@ -569,21 +820,25 @@ pub struct RecvRequest {
 impl RecvRequest {
     /// Reject this request with `REQUEST_REJECTED` code.
     pub fn reject(mut self) {
+        self.abort(ErrorCode::REQUEST_REJECTED);
+    }
+
+    // Reset a request no response will be sent to, which is not in flight anymore
+    fn abort(&mut self, code: ErrorCode) {
         if let Some(mut recv) = self.recv.take() {
-            recv.reset(ErrorCode::REQUEST_REJECTED);
+            recv.reset(code);
         }
         if let Some(mut send) = self.send.take() {
-            send.reset(ErrorCode::REQUEST_REJECTED.into());
+            send.reset(code.into());
+            let mut conn = self.conn.h3.lock().unwrap();
+            conn.inner.request_finished(send.id());
+            conn.wake();
         }
     }
 
-    fn new(recv: RecvStream, send: SendStream, conn: ConnectionRef) -> Self {
-        let is_0rtt = recv.is_0rtt();
-        let recv = Some(RecvData::new(
-            FrameDecoder::stream(recv),
-            conn.clone(),
-            send.id(),
-        ));
+    fn new(recv: FrameStream, send: SendStream, conn: ConnectionRef) -> Self {
+        let is_0rtt = recv.get_ref().is_0rtt();
+        let recv = Some(RecvData::new(recv, conn.clone(), send.id()));
         Self {
             conn,
             recv,
@@ -597,15 +852,10 @@ impl RecvRequest {
         headers: Header,
         body: RecvBody,
     ) -> Result<Request<RecvBody>, Error> {
-        let (method, uri, headers) = headers.into_request_parts()?;
+        let (method, uri, protocol, headers) = headers.into_request_parts()?;
 
         if self.is_0rtt && !method.is_idempotent() {
-            if let Some(r) = &mut self.recv {
-                r.reset(ErrorCode::REQUEST_REJECTED);
-            }
-            if let Some(s) = &mut self.send {
-                s.reset(ErrorCode::REQUEST_REJECTED.into());
-            }
+            self.abort(ErrorCode::REQUEST_REJECTED);
             return Err(Error::peer(format!(
                 "Tried an non indempotent method in 0-RTT: {}",
                 method,
@@ -620,6 +870,9 @@ impl RecvRequest {
             .unwrap();
 
         *request.headers_mut() = headers;
+        if let Some(protocol) = protocol {
+            request.extensions_mut().insert(protocol);
+        }
         Ok(request)
     }
 }
@@ -628,16 +881,44 @@ impl Future for RecvRequest {
     type Output = Result<(Request<RecvBody>, Sender), Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        let (header, body) = ready!(self.recv.as_mut().unwrap().poll_unpin(cx))?;
-        let request = self.build_request(header, body)?;
+        let recv = match self.recv.as_mut() {
+            Some(recv) => recv,
+            None => return Poll::Ready(Err(Error::Poll)),
+        };
+        let request = match ready!(recv.poll_unpin(cx)) {
+            Ok((header, body)) => self.build_request(header, body),
+            Err(e) => Err(e),
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                self.abort(ErrorCode::GENERAL_PROTOCOL_ERROR);
+                return Poll::Ready(Err(e));
+            }
+        };
+        let mut priority = Priority::from_headers(request.headers());
+        if let Some(send) = self.send.as_ref() {
+            let id = send.id();
+            if let Some(update) = self.conn.h3.lock().unwrap().inner.take_priority_update(id) {
+                priority = update;
+            }
+            let _ = send.set_priority(priority.quic_priority(id));
+        }
         let sender = Sender {
             send: self.send.take(),
             conn: Some(self.conn.clone()),
+            priority,
         };
         Poll::Ready(Ok((request, sender)))
     }
 }
 
+impl Drop for RecvRequest {
+    fn drop(&mut self) {
+        self.abort(ErrorCode::REQUEST_CANCELLED);
+    }
+}
+
 /// Send a response back to the client.
 ///
 /// This struct is made available once request headers are received, when [`RecvRequest`] resolves.
@@ -648,14 +929,20 @@ impl Future for RecvRequest {
 /// The request can also be cancelled with [`cancel()`], after which the client will receive a request
 /// error with `REQUEST_CANCELLED` cause.
 ///
+/// Before sending the response, the server can anticipate the client's next requests with
+/// [`push_promise()`], and send informational responses with [`send_informational()`].
+///
 /// [`RecvRequest`]: struct.RecvRequest.html
 /// [`http::Response`]: https://docs.rs/http/*/http/response/struct.Response.html
 /// [`send_response()`]: #method.Response
 /// [`BodyReader`]: ../body/struct.BodyReader.html
 /// [`cancel()`]: #method.cancel
+/// [`push_promise()`]: #method.push_promise
+/// [`send_informational()`]: #method.send_informational
 pub struct Sender {
     send: Option<SendStream>,
     conn: Option<ConnectionRef>,
+    priority: Priority,
 }
 
 impl Sender {
@@ -706,10 +993,90 @@ impl Sender {
         } = response;
         let header = Header::response(status, headers);
 
+        self.priority();
         let (send, conn) = (self.send.take().unwrap(), self.conn.take().unwrap());
         SendData::new(send, conn, header, body, true)
     }
 
+    /// Priority of the response, as last signalled by the client
+    ///
+    /// This comes from the request's `priority` header, unless the client has updated it since.
+    /// Updates received before the response is sent are applied to it when this is called.
+    pub fn priority(&mut self) -> Priority {
+        if let (Some(send), Some(conn)) = (self.send.as_ref(), self.conn.as_ref()) {
+            let id = send.id();
+            if let Some(update) = conn.h3.lock().unwrap().inner.take_priority_update(id) {
+                let _ = send.set_priority(update.quic_priority(id));
+                self.priority = update;
+            }
+        }
+        self.priority
+    }
+
+    /// Send an informational response, ahead of the final one
+    ///
+    /// Any number of `1xx` responses can precede the one sent with [`send_response()`]. A
+    /// `103 Early Hints` lets the client preload resources while the final response is being
+    /// prepared. Clients sending a request with an `expect: 100-continue` header hold its body back
+    /// until they receive a `100 Continue`, or the final response when the body is not wanted.
+    ///
+    /// Fails if the status is not informational, or if the final response was already sent.
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use http::{header::EXPECT, Request, Response, StatusCode};
+    /// use quinn_h3::{server::Sender, Body, RecvBody};
+    ///
+    /// async fn upload(mut request: Request<RecvBody>, mut sender: Sender) -> Result<()> {
+    ///     if request.headers().get(EXPECT).map_or(false, |v| v == "100-continue") {
+    ///         let response = Response::builder().status(StatusCode::CONTINUE).body(())?;
+    ///         sender.send_informational(response).await?;
+    ///     }
+    ///     let body = request.body_mut().read_to_end().await?;
+    ///
+    ///     let response = Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(Body::from(format!("received {} bytes", body.len())))?;
+    ///     sender.send_response(response).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// [`send_response()`]: #method.send_response
+    pub async fn send_informational(&mut self, response: Response<()>) -> Result<(), Error> {
+        let (parts, _) = response.into_parts();
+        // HTTP/3 has no use for switching protocols
+        if !parts.status.is_informational() || parts.status == StatusCode::SWITCHING_PROTOCOLS {
+            return Err(Error::internal(format!(
+                "{} is not an informational status",
+                parts.status
+            )));
+        }
+        if self.send.is_none() {
+            return Err(Error::internal("final response already sent"));
+        }
+        self.write_header(Header::response(parts.status, parts.headers))
+            .await
+    }
+
+    // Written in place, so the stream stays with the sender if this fails or is dropped
+    async fn write_header(&mut self, header: Header) -> Result<(), Error> {
+        let (conn, send) = match (self.conn.as_ref(), self.send.as_mut()) {
+            (Some(conn), Some(send)) => (conn, send),
+            _ => return Err(Error::internal("response already sent")),
+        };
+
+        let mut buf = BytesMut::with_capacity(512);
+        {
+            let mut h3 = conn.h3.lock().unwrap();
+            let frame = h3.inner.encode_header(send.id(), header)?;
+            h3.wake();
+            HttpFrame::Headers(frame).encode(&mut buf);
+        }
+        send.write_all(&buf).await?;
+        Ok(())
+    }
+
     /// Cancel request processing
     ///
     /// Sends a request error with `REQUEST_CANCELLED` HTTP/3 error code. Once called, all other
@@ -718,9 +1085,265 @@ impl Sender {
     /// Cancelling a request means that some request data have been processed by the application, which
     /// decided to abandon the response.
     pub fn cancel(&mut self) {
-        self.send
-            .as_mut()
+        self.abort(ErrorCode::REQUEST_CANCELLED);
+    }
+
+    // Reset a request no final response will be sent to, which is not in flight anymore
+    fn abort(&mut self, code: ErrorCode) {
+        if let Some(mut send) = self.send.take() {
+            send.reset(code.into());
+            if let Some(conn) = &self.conn {
+                let mut conn = conn.h3.lock().unwrap();
+                conn.inner.request_finished(send.id());
+                conn.wake();
+            }
+        }
+    }
+
+    /// Datagrams associated with this request
+    ///
+    /// HTTP datagrams must be enabled in the local [`Settings`]. They can only be taken once per
+    /// request, and before the response is sent, but remain usable while it is being sent.
+    ///
+    /// [`Settings`]: ../struct.Settings.html
+    pub fn datagrams(&self) -> Result<RequestDatagrams, Error> {
+        let (conn, send) = match (self.conn.as_ref(), self.send.as_ref()) {
+            (Some(conn), Some(send)) => (conn, send),
+            _ => return Err(Error::internal("datagrams taken after response")),
+        };
+        conn.h3.lock().unwrap().register_datagrams(send.id())?;
+        Ok(RequestDatagrams::new(conn.clone(), send.id()))
+    }
+
+    /// Accept a WebTransport session
+    ///
+    /// `request` must be the extended `CONNECT` request this sender belongs to, carrying the
+    /// `webtransport` [`Protocol`]. A `200` response is sent, leaving the request stream open for
+    /// the lifetime of the returned [`WebTransportSession`]. To refuse the session, send an
+    /// unsuccessful response with [`send_response()`] instead.
+    ///
+    /// [`Protocol`]: ../struct.Protocol.html
+    /// [`WebTransportSession`]: ../webtransport/struct.WebTransportSession.html
+    /// [`send_response()`]: #method.send_response
+    pub async fn accept_webtransport(
+        mut self,
+        request: Request<RecvBody>,
+    ) -> Result<WebTransportSession, Error> {
+        let enabled = self
+            .conn
+            .as_ref()
+            .map_or(false, |c| c.h3.lock().unwrap().webtransport_enabled());
+        if request.extensions().get::<Protocol>() != Some(&Protocol::WEB_TRANSPORT) || !enabled {
+            return Err(Error::internal("not a WebTransport request"));
+        }
+
+        let (conn, send) = self.respond_connect(HeaderMap::new()).await?;
+        Ok(WebTransportSession::new(
+            conn,
+            send,
+            request.into_body().into_inner(),
+        ))
+    }
+
+    /// Accept a CONNECT-UDP request
+    ///
+    /// `request` must be the extended `CONNECT` request this sender belongs to, carrying the
+    /// `connect-udp` [`Protocol`], which requires the extended `CONNECT` to be enabled in the local
+    /// [`Settings`]. A `200` response is sent, leaving the request stream open for the lifetime of
+    /// the returned [`UdpTunnel`].
+    ///
+    /// The target is found in the request's URI with the proxy's [`UriTemplate`]. If it is
+    /// invalid or unreachable, send an unsuccessful response with [`send_response()`] instead.
+    ///
+    /// [`Protocol`]: ../struct.Protocol.html
+    /// [`Settings`]: ../struct.Settings.html
+    /// [`UdpTunnel`]: ../connect_udp/struct.UdpTunnel.html
+    /// [`UriTemplate`]: ../connect_udp/struct.UriTemplate.html
+    /// [`send_response()`]: #method.send_response
+    pub async fn accept_connect_udp(
+        mut self,
+        request: Request<RecvBody>,
+    ) -> Result<UdpTunnel, Error> {
+        let enabled = self
+            .conn
+            .as_ref()
+            .map_or(false, |c| c.h3.lock().unwrap().connect_protocol_enabled());
+        if request.extensions().get::<Protocol>() != Some(&Protocol::CONNECT_UDP) || !enabled {
+            return Err(Error::internal("not a CONNECT-UDP request"));
+        }
+
+        // Registered before responding, so the client's first datagrams are not dropped
+        let datagrams = self.datagrams().ok();
+        let mut headers = HeaderMap::new();
+        headers.insert(CAPSULE_PROTOCOL, HeaderValue::from_static("?1"));
+        let (conn, send) = self.respond_connect(headers).await?;
+        Ok(UdpTunnel::new(conn, send, request.into_body(), datagrams))
+    }
+
+    /// Accept a `CONNECT` request, tunnelling bytes through its stream
+    ///
+    /// `request` must be the `CONNECT` request this sender belongs to, without a [`Protocol`]. Its
+    /// URI holds the authority the client wants to reach, which the server connects to before
+    /// accepting. A `200` response is sent, leaving the request stream open for the lifetime of
+    /// the returned [`Tunnel`]. To refuse the request, send an unsuccessful response with
+    /// [`send_response()`] instead.
+    ///
+    /// [`Protocol`]: ../struct.Protocol.html
+    /// [`Tunnel`]: ../tunnel/struct.Tunnel.html
+    /// [`send_response()`]: #method.send_response
+    pub async fn accept_connect(mut self, request: Request<RecvBody>) -> Result<Tunnel, Error> {
+        if request.method() != Method::CONNECT || request.extensions().get::<Protocol>().is_some() {
+            return Err(Error::internal("not a CONNECT request"));
+        }
+
+        let (conn, send) = self.respond_connect(HeaderMap::new()).await?;
+        Ok(Tunnel::new(conn, send, request.into_body()))
+    }
+
+    // Answer a CONNECT request with a 200, leaving its stream open
+    async fn respond_connect(
+        &mut self,
+        headers: HeaderMap,
+    ) -> Result<(ConnectionRef, SendStream), Error> {
+        if self.send.is_none() {
+            return Err(Error::internal("CONNECT accepted after response"));
+        }
+        self.write_header(Header::response(StatusCode::OK, headers))
+            .await?;
+        Ok((self.conn.take().unwrap(), self.send.take().unwrap()))
+    }
+
+    /// Promise a request the server will push a response for
+    ///
+    /// Sends a `PUSH_PROMISE` frame on this request's stream, announcing `request` to the client.
+    /// Its body is ignored, as pushed requests carry none. This must be done before sending the
+    /// response, so the client learns about the promise before it can issue the same request.
+    ///
+    /// On success, the returned [`Pusher`] is used to send the pushed response. Fails with
+    /// [`Error::PushRefused`] if the client's `MAX_PUSH_ID` does not allow another push.
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use http::{Request, Response, StatusCode};
+    /// use quinn_h3::{server::Sender, Body};
+    ///
+    /// async fn push_style(mut sender: Sender) -> Result<()> {
+    ///     let request = Request::get("https://example.com/style.css").body(())?;
+    ///     let pusher = sender.push_promise(request).await?;
+    ///
+    ///     let response = Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(Body::from("<html></html>"))?;
+    ///     sender.send_response(response).await?;
+    ///
+    ///     let pushed = Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(Body::from("body { color: red; }"))?;
+    ///     pusher.send_response(pushed).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// [`Pusher`]: struct.Pusher.html
+    /// [`Error::PushRefused`]: ../enum.Error.html#variant.PushRefused
+    pub async fn push_promise(&mut self, request: Request<()>) -> Result<Pusher, Error> {
+        let (conn, send) = match (self.conn.as_ref(), self.send.as_mut()) {
+            (Some(conn), Some(send)) => (conn.clone(), send),
+            _ => return Err(Error::internal("push promised after response")),
+        };
+
+        let request::Parts {
+            method,
+            uri,
+            headers,
+            ..
+        } = request.into_parts().0;
+
+        let mut buf = BytesMut::with_capacity(512);
+        let push_id = {
+            let mut h3 = conn.h3.lock().unwrap();
+            let push_id = h3.inner.next_push_id().ok_or(Error::PushRefused)?;
+            let frame = h3.inner.encode_push_promise(
+                send.id(),
+                push_id,
+                Header::request(method, uri, headers),
+            )?;
+            h3.wake();
+            HttpFrame::PushPromise(frame).encode(&mut buf);
+            push_id
+        };
+        send.write_all(&buf).await?;
+
+        Ok(Pusher { conn, push_id })
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        // Dropped without responding, the client must not wait for a response
+        self.abort(ErrorCode::INTERNAL_ERROR);
+    }
+}
+
+/// Send a pushed response
+///
+/// Obtained from [`Sender::push_promise()`], once the promised request has been sent to the
+/// client. The response is sent on a new push stream with [`send_response()`], or the push
+/// can be abandoned with [`cancel()`].
+///
+/// [`Sender::push_promise()`]: struct.Sender.html#method.push_promise
+/// [`send_response()`]: #method.send_response
+/// [`cancel()`]: #method.cancel
+pub struct Pusher {
+    conn: ConnectionRef,
+    push_id: u64,
+}
+
+impl Pusher {
+    /// Send the pushed response
+    ///
+    /// Opens a push stream and sends the response on it, resolving once transmission is
+    /// complete. Fails with a `REQUEST_CANCELLED` error if the client cancelled this push.
+    pub async fn send_response<B>(self, response: Response<B>) -> Result<(), Error>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Send + Sync>> + Send + Sync,
+    {
+        if self.is_cancelled() {
+            return Err(ErrorCode::REQUEST_CANCELLED.into());
+        }
+
+        let mut send = self.conn.quic.open_uni().await?;
+        let mut buf = BytesMut::with_capacity(2 * VarInt::MAX_SIZE);
+        StreamType::PUSH.encode(&mut buf);
+        buf.write_var(self.push_id);
+        send.write_all(&buf).await?;
+
+        let (response, body) = response.into_parts();
+        let response::Parts {
+            status, headers, ..
+        } = response;
+        let header = Header::response(status, headers);
+        SendData::new(send, self.conn, header, body, false).await
+    }
+
+    /// Cancel the push
+    ///
+    /// The client is notified with a `CANCEL_PUSH` frame, and will not expect the pushed
+    /// response anymore.
+    pub fn cancel(self) {
+        self.conn.h3.lock().unwrap().cancel_push(self.push_id);
+    }
+
+    /// Check whether the client cancelled this push
+    pub fn is_cancelled(&self) -> bool {
+        self.conn
+            .h3
+            .lock()
             .unwrap()
-            .reset(ErrorCode::REQUEST_CANCELLED.into());
+            .inner
+            .is_push_cancelled(self.push_id)
     }
 }
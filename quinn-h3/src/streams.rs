@@ -13,12 +13,15 @@ use futures::{
     ready,
 };
 use quinn::{OpenUni, RecvStream, SendStream};
-use quinn_proto::VarInt;
+use quinn_proto::{
+    coding::{BufExt, Codec},
+    StreamId, VarInt,
+};
 use tracing::trace;
 
 use crate::{
     frame::{FrameDecoder, FrameStream},
-    proto::{ErrorCode, StreamType},
+    proto::{frame::Type as FrameType, ErrorCode, StreamType},
     Error,
 };
 
@@ -27,6 +30,7 @@ pub enum NewUni {
     Push(PushStream),
     Encoder(RecvStream),
     Decoder(RecvStream),
+    WebTransport(StreamId, RecvStream),
     Reserved,
 }
 
@@ -36,7 +40,6 @@ impl TryFrom<(StreamType, RecvStream)> for NewUni {
         let (ty, recv) = value;
         Ok(match ty {
             StreamType::CONTROL => NewUni::Control(FrameDecoder::stream(recv)),
-            StreamType::PUSH => NewUni::Push(PushStream(FrameDecoder::stream(recv))),
             StreamType::ENCODER => NewUni::Encoder(recv),
             StreamType::DECODER => NewUni::Decoder(recv),
             t if t.0 > 0x21 && (t.0 - 0x21) % 0x1f == 0 => NewUni::Reserved,
@@ -47,12 +50,15 @@ impl TryFrom<(StreamType, RecvStream)> for NewUni {
 
 pub struct RecvUni {
     inner: Option<(RecvStream, [u8; VarInt::MAX_SIZE], usize, usize)>,
+    // Set once a push or WebTransport stream type is read, an ID follows
+    prefixed: Option<StreamType>,
 }
 
 impl RecvUni {
     pub fn new(recv: RecvStream) -> Self {
         Self {
             inner: Some((recv, [0u8; VarInt::MAX_SIZE], 1, 0)),
+            prefixed: None,
         }
     }
 }
@@ -61,8 +67,9 @@ impl Future for RecvUni {
     type Output = Result<NewUni, Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
         loop {
-            match self.inner {
+            match this.inner {
                 None => panic!("polled after resolved"),
                 Some((ref mut recv, ref mut buf, ref mut expected, ref mut len)) => {
                     match ready!(Pin::new(recv).poll_read(cx, &mut buf[*len..*expected]))? {
@@ -78,9 +85,31 @@ impl Future for RecvUni {
                             }
                             if len == expected {
                                 let mut cur = io::Cursor::new(&buf);
+                                if let Some(ty) = this.prefixed.take() {
+                                    let id = cur
+                                        .get_var()
+                                        .map_err(|_| Error::internal("stream id decode"))?;
+                                    let recv = match mem::replace(&mut this.inner, None) {
+                                        Some((recv, _, _, _)) => recv,
+                                        _ => unreachable!(),
+                                    };
+                                    return Poll::Ready(Ok(match ty {
+                                        StreamType::PUSH => NewUni::Push(PushStream {
+                                            push_id: id,
+                                            recv: FrameDecoder::stream(recv),
+                                        }),
+                                        _ => NewUni::WebTransport(StreamId(id), recv),
+                                    }));
+                                }
                                 let ty = StreamType::decode(&mut cur)
                                     .map_err(|_| Error::internal("stream type decode"))?;
-                                match mem::replace(&mut self.inner, None) {
+                                if ty == StreamType::PUSH || ty == StreamType::WEBTRANSPORT_UNI {
+                                    *expected = 1;
+                                    *len = 0;
+                                    this.prefixed = Some(ty);
+                                    continue;
+                                }
+                                match mem::replace(&mut this.inner, None) {
                                     Some((recv, _, _, _)) => {
                                         return Poll::Ready(NewUni::try_from((ty, recv)))
                                     }
@@ -95,7 +124,95 @@ impl Future for RecvUni {
     }
 }
 
-pub struct PushStream(FrameStream);
+pub struct PushStream {
+    pub push_id: u64,
+    pub recv: FrameStream,
+}
+
+pub enum NewBi {
+    Request(SendStream, FrameStream),
+    WebTransport(StreamId, SendStream, RecvStream),
+}
+
+/// Tells WebTransport streams from requests, reading the signal WebTransport streams start with
+pub struct RecvBi {
+    inner: Option<(SendStream, RecvStream)>,
+    buf: [u8; VarInt::MAX_SIZE],
+    expected: usize,
+    len: usize,
+    // Set once the WebTransport signal is read, the session ID follows
+    webtransport: bool,
+}
+
+impl RecvBi {
+    pub fn new(send: SendStream, recv: RecvStream) -> Self {
+        Self {
+            inner: Some((send, recv)),
+            buf: [0u8; VarInt::MAX_SIZE],
+            expected: 1,
+            len: 0,
+            webtransport: false,
+        }
+    }
+}
+
+impl Future for RecvBi {
+    type Output = Result<NewBi, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            let recv = match this.inner {
+                None => panic!("polled after resolved"),
+                Some((_, ref mut recv)) => recv,
+            };
+            match ready!(Pin::new(recv).poll_read(cx, &mut this.buf[this.len..this.expected]))? {
+                0 => {
+                    return Poll::Ready(Err(Error::peer(
+                        "Bidi stream closed before first frame received",
+                    )))
+                }
+                read => {
+                    this.len += read;
+                    if this.len == 1 {
+                        this.expected = VarInt::encoded_size(this.buf[0]);
+                    }
+                    if this.len < this.expected {
+                        continue;
+                    }
+
+                    let mut cur = io::Cursor::new(&this.buf[..this.len]);
+                    let (send, recv) = this.inner.take().unwrap();
+                    if this.webtransport {
+                        let session_id = cur
+                            .get_var()
+                            .map_err(|_| Error::internal("session id decode"))?;
+                        return Poll::Ready(Ok(NewBi::WebTransport(
+                            StreamId(session_id),
+                            send,
+                            recv,
+                        )));
+                    }
+                    let ty = FrameType::decode(&mut cur)
+                        .map_err(|_| Error::internal("frame type decode"))?;
+                    if ty == FrameType::WEBTRANSPORT_STREAM {
+                        this.inner = Some((send, recv));
+                        this.expected = 1;
+                        this.len = 0;
+                        this.webtransport = true;
+                        continue;
+                    }
+                    // A request, its first frame type is decoded again with the rest
+                    let prefix = Bytes::copy_from_slice(&this.buf[..this.len]);
+                    return Poll::Ready(Ok(NewBi::Request(
+                        send,
+                        FrameDecoder::stream_with_prefix(recv, prefix),
+                    )));
+                }
+            }
+        }
+    }
+}
 
 pub struct SendUni {
     ty: StreamType,
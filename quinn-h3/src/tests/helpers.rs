@@ -20,7 +20,7 @@ use crate::{
     proto::frame::HttpFrame,
     proto::headers::Header,
     server::{self, IncomingConnection},
-    ZeroRttAccepted,
+    Settings, ZeroRttAccepted,
 };
 
 pub fn get(path: &str) -> Request<Body> {
@@ -122,6 +122,40 @@ impl Helper {
             .expect("no 0rtt")
     }
 
+    // Listen on all addresses, for clients resolving `localhost`
+    pub fn listen_any(&mut self) {
+        self.server.listen(SocketAddr::new(
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            self.port,
+        ));
+    }
+
+    pub fn max_pushes(&mut self, count: u64) {
+        self.client.max_pushes(count);
+    }
+
+    pub fn server_settings(&mut self, settings: Settings) {
+        self.server.settings(settings);
+    }
+
+    pub fn client_settings(&mut self, settings: Settings) {
+        self.client.settings(settings);
+    }
+
+    pub fn datagrams(&mut self) {
+        let mut settings = Settings::new();
+        settings.set_h3_datagram(true);
+        self.server.settings(settings.clone());
+        self.client.settings(settings);
+    }
+
+    pub fn webtransport(&mut self) {
+        let mut settings = Settings::new();
+        settings.set_enable_webtransport(true);
+        self.server.settings(settings.clone());
+        self.client.settings(settings);
+    }
+
     pub fn socket_addr(&self) -> SocketAddr {
         SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), self.port)
     }
@@ -1,9 +1,36 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+
 use bytes::Bytes;
-use futures::StreamExt;
-use http::{Response, StatusCode};
-use tokio::time::{delay_for, Duration};
+use futures::{channel::oneshot, future, StreamExt};
+use http::{
+    header::{EXPECT, LINK},
+    uri::Authority,
+    HeaderValue, Method, Request, Response, StatusCode,
+};
+use quinn::{ApplicationClose, ConnectionError, WriteError};
+use quinn_proto::{StreamId, VarInt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{delay_for, timeout, Duration},
+};
+use tower_service::Service;
 
-use crate::{proto::frame::DataFrame, server::IncomingConnection, Body, Error, HttpError};
+use crate::{
+    connect_udp::{Target, UriTemplate},
+    datagram::{Capsule, CapsuleBody, RecvCapsules},
+    proto::{frame::DataFrame, ErrorCode},
+    server::{self, IncomingConnection},
+    Body, Error, HttpError, Priority, RecvBody, Settings,
+};
 
 mod helpers;
 use helpers::{get, post, timeout_join, Helper};
@@ -175,9 +202,75 @@ async fn server_cancel_response() {
     timeout_join(server_handle).await;
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn priority_update() {
+    let helper = Helper::new();
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let mut seen = Vec::new();
+        while let Some(recv_req) = incoming_req.next().await {
+            let (_, mut sender) = recv_req.await.expect("recv_req");
+            if seen.len() == 1 {
+                // The update travels on the control stream, so it may trail the request
+                while sender.priority() == Priority::new(1, false) {
+                    delay_for(Duration::from_millis(10)).await;
+                }
+            }
+            seen.push(sender.priority());
+            sender
+                .send_response(Response::new(Body::from(())))
+                .await
+                .expect("send_response");
+        }
+        seen
+    });
+
+    let conn = helper.make_connection().await;
+    let prioritized = || {
+        let mut request = get("/");
+        request
+            .headers_mut()
+            .insert("priority", Priority::new(1, false).to_header_value());
+        request
+    };
+
+    let (req, resp) = conn.send_request(prioritized());
+    req.await.expect("request");
+    resp.await.expect("recv response");
+
+    let (req, mut resp) = conn.send_request(prioritized());
+    resp.update_priority(Priority::new(0, true));
+    req.await.expect("request");
+    let mut response = resp.await.expect("recv response");
+    response.body_mut().update_priority(Priority::new(5, false));
+    response.body_mut().read_to_end().await.expect("read body");
+
+    // A misbehaving update would have closed the connection
+    let (req, resp) = conn.send_request(get("/"));
+    req.await.expect("third request");
+    assert_eq!(resp.await.expect("third response").status(), StatusCode::OK);
+
+    conn.close();
+    let seen = timeout_join(server_handle).await;
+    assert_eq!(seen.len(), 3);
+    assert_eq!(seen[0].urgency(), 1);
+    assert!(!seen[0].incremental());
+    assert_eq!(seen[1].urgency(), 0);
+    assert!(seen[1].incremental());
+    assert_eq!(seen[2], Priority::default());
+}
+
 #[tokio::test]
 async fn go_away() {
     let helper = Helper::new();
+    let (went_away, wait_go_away) = oneshot::channel();
+    let (respond, wait_respond) = oneshot::channel();
 
     let mut incoming = helper.make_server();
     let server_handle = tokio::spawn(async move {
@@ -189,7 +282,9 @@ async fn go_away() {
             .expect("accept");
         let recv_req = incoming_req.next().await.expect("wait request");
         incoming_req.go_away();
+        went_away.send(()).unwrap();
         let (_, mut sender) = recv_req.await.expect("recv_req");
+        wait_respond.await.expect("wait respond");
         sender
             .send_response(
                 Response::builder()
@@ -198,25 +293,906 @@ async fn go_away() {
                     .unwrap(),
             )
             .await
-            .map(|_| ())
+            .expect("send_response");
+        // The connection closes once the request in flight is finished
+        assert!(incoming_req.next().await.is_none());
     });
 
     let conn = helper.make_connection().await;
     let (req, resp) = conn.send_request(get("/"));
     req.await.unwrap();
-    assert!(resp.await.is_ok());
 
-    delay_for(Duration::from_millis(50)).await;
-    let (req, resp) = conn.send_request(get("/"));
+    wait_go_away.await.unwrap();
+    let (req, rejected) = conn.send_request(get("/"));
     req.await.unwrap();
     assert_matches!(
-        resp.await.map(|_| ()),
+        rejected.await.map(|_| ()),
         Err(Error::Http(HttpError::RequestRejected, None))
     );
 
+    respond.send(()).unwrap();
+    // Held, so the request stays in flight and the client does not close the connection itself
+    let _response = resp.await.expect("recv response");
+    assert_matches!(
+        conn.closed().await,
+        ConnectionError::ApplicationClosed(ApplicationClose { error_code, .. })
+            if error_code == ErrorCode::NO_ERROR.into()
+    );
+
+    timeout_join(server_handle).await;
+}
+
+#[tokio::test]
+async fn go_away_dropped_sender() {
+    let helper = Helper::new();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        incoming_req.go_away();
+        let (_, sender) = recv_req.await.expect("recv_req");
+        drop(sender);
+        assert!(incoming_req.next().await.is_none());
+    });
+
+    let conn = helper.make_connection().await;
+    let (req, resp) = conn.send_request(get("/"));
+    req.await.unwrap();
+
+    // The response is not read yet, so only the server can have closed the connection
+    assert_matches!(
+        conn.closed().await,
+        ConnectionError::ApplicationClosed(ApplicationClose { error_code, .. })
+            if error_code == ErrorCode::NO_ERROR.into()
+    );
+    assert!(resp.await.is_err());
+
+    timeout_join(server_handle).await;
+}
+
+#[tokio::test]
+async fn shutdown() {
+    let helper = Helper::new();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let response = tokio::spawn(async move {
+            let (_, mut sender) = recv_req.await.expect("recv_req");
+            // Still in flight once the second GOAWAY is sent
+            delay_for(Duration::from_millis(200)).await;
+            sender
+                .send_response(Response::new(Body::from("in flight")))
+                .await
+                .map(|_| ())
+        });
+        incoming
+            .shutdown(Instant::now() + Duration::from_secs(5))
+            .await;
+        assert!(incoming.next().await.is_none());
+        response.await.expect("response task")
+    });
+
+    let conn = helper.make_connection().await;
+    let (req, resp) = conn.send_request(get("/"));
+    req.await.unwrap();
+    let mut response = resp.await.expect("response");
+    assert_eq!(
+        response.body_mut().read_to_end().await.expect("read body"),
+        "in flight"
+    );
+    assert_eq!(conn.go_away_id(), Some(StreamId(4)));
+
+    timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("shutdown did not complete")
+        .expect("server panic")
+        .expect("server");
+}
+
+#[tokio::test]
+async fn informational_responses() {
+    let helper = Helper::new();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (_, mut sender) = recv_req.await?;
+        for link in &["</style.css>; rel=preload", "</script.js>; rel=preload"] {
+            let early_hints = Response::builder()
+                .status(StatusCode::from_u16(103).unwrap())
+                .header(LINK, *link)
+                .body(())
+                .unwrap();
+            sender.send_informational(early_hints).await?;
+        }
+        assert!(sender.send_informational(Response::new(())).await.is_err());
+        sender.send_response(Response::new(Body::from(()))).await?;
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let (req, mut resp) = conn.send_request(get("/"));
+    req.await.unwrap();
+    let links = resp
+        .informational()
+        .map(|response| {
+            assert_eq!(response.status(), 103);
+            response.headers()[LINK].clone()
+        })
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        links,
+        vec!["</style.css>; rel=preload", "</script.js>; rel=preload"]
+    );
+    assert_eq!(resp.await.expect("response").status(), StatusCode::OK);
+
     assert!(timeout_join(server_handle).await.is_ok());
 }
 
+#[tokio::test]
+async fn expect_continue() {
+    let helper = Helper::new();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (mut request, mut sender) = recv_req.await?;
+        assert_eq!(request.headers()[EXPECT], "100-continue");
+        let response = Response::builder()
+            .status(StatusCode::CONTINUE)
+            .body(())
+            .unwrap();
+        sender.send_informational(response).await?;
+        let body = request.body_mut().read_to_end().await?;
+        sender
+            .send_response(Response::new(Body::from(body)))
+            .await?;
+
+        // Refused without reading the body
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (mut request, mut sender) = recv_req.await?;
+        let response = Response::builder()
+            .status(StatusCode::PAYLOAD_TOO_LARGE)
+            .body(Body::from(()))
+            .unwrap();
+        sender.send_response(response).await?;
+        // The client gives up on the body it held back, without sending any of it
+        assert_matches!(
+            request.body_mut().data().await,
+            Some(Err(Error::Http(HttpError::RequestCancelled, _)))
+        );
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let upload = |body: &'static str| {
+        let mut request = post("/", body);
+        request
+            .headers_mut()
+            .insert(EXPECT, HeaderValue::from_static("100-continue"));
+        request
+    };
+
+    let (req, resp) = conn.send_request(upload("uploaded"));
+    let (_, mut response) = future::try_join(req, resp).await.expect("upload");
+    assert_eq!(
+        response.body_mut().read_to_end().await.expect("read body"),
+        "uploaded"
+    );
+
+    // Resolves before the body would be sent for lack of an answer
+    let (req, resp) = conn.send_request(upload("refused"));
+    let (_, response) = timeout(Duration::from_millis(500), future::try_join(req, resp))
+        .await
+        .expect("body held back")
+        .expect("refused upload");
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    assert!(timeout_join(server_handle).await.is_ok());
+}
+
+#[tokio::test]
+async fn server_push() {
+    let mut helper = Helper::new();
+    helper.max_pushes(1);
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (_, mut sender) = recv_req.await.expect("recv_req");
+        let promised = Request::get("https://localhost/pushed").body(()).unwrap();
+        let pusher = sender.push_promise(promised).await?;
+        let promised = Request::get("https://localhost/refused").body(()).unwrap();
+        let refused = sender.push_promise(promised).await.map(|_| ());
+        sender
+            .send_response(
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .body(Body::from(()))
+                    .unwrap(),
+            )
+            .await?;
+        pusher
+            .send_response(
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .body(Body::from("pushed body"))
+                    .unwrap(),
+            )
+            .await?;
+        Ok::<_, Error>(refused)
+    });
+
+    let conn = helper.make_connection().await;
+    let mut incoming_pushes = conn.incoming_pushes();
+    let (req, resp) = conn.send_request(get("/"));
+    req.await.expect("request");
+    resp.await.expect("response");
+
+    let (request, recv_push) = incoming_pushes
+        .next()
+        .await
+        .expect("push promise")
+        .await
+        .expect("promised request");
+    assert_eq!(request.uri().path(), "/pushed");
+    let mut response = recv_push.await.expect("pushed response");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response
+        .body_mut()
+        .read_to_end()
+        .await
+        .expect("pushed body");
+    assert_eq!(body, "pushed body");
+
+    assert_matches!(
+        timeout_join(server_handle).await,
+        Ok(Err(Error::PushRefused))
+    );
+}
+
+#[tokio::test]
+async fn server_push_dropped() {
+    let mut helper = Helper::new();
+    helper.max_pushes(2);
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (_, mut sender) = recv_req.await.expect("recv_req");
+        let promised = Request::get("https://localhost/dropped").body(()).unwrap();
+        let dropped_promise = sender.push_promise(promised).await?;
+        let promised = Request::get("https://localhost/ignored").body(()).unwrap();
+        let dropped_response = sender.push_promise(promised).await?;
+        sender
+            .send_response(
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .body(Body::from(()))
+                    .unwrap(),
+            )
+            .await?;
+        while !dropped_promise.is_cancelled() || !dropped_response.is_cancelled() {
+            delay_for(Duration::from_millis(10)).await;
+        }
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let mut incoming_pushes = conn.incoming_pushes();
+    let (req, resp) = conn.send_request(get("/"));
+    req.await.expect("request");
+    resp.await.expect("response");
+
+    // Neither future is cancelled explicitly
+    drop(incoming_pushes.next().await.expect("first push promise"));
+    let (request, recv_push) = incoming_pushes
+        .next()
+        .await
+        .expect("second push promise")
+        .await
+        .expect("promised request");
+    assert_eq!(request.uri().path(), "/ignored");
+    drop(recv_push);
+
+    assert_matches!(timeout_join(server_handle).await, Ok(Ok(())));
+}
+
+#[tokio::test]
+async fn datagrams_and_capsules() {
+    let mut helper = Helper::new();
+    helper.datagrams();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (request, mut sender) = recv_req.await?;
+        let mut datagrams = sender.datagrams()?;
+
+        let (mut send_capsules, body) = CapsuleBody::channel();
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .body(body)
+            .expect("response");
+        let send_response = sender.send_response(response);
+        let echo = async move {
+            let mut capsules = RecvCapsules::new(request.into_body());
+            let capsule = capsules.next().await.expect("capsule")?;
+            send_capsules.send(capsule).await?;
+
+            let datagram = datagrams.read_datagram().await.expect("datagram");
+            datagrams.send_datagram(datagram).await?;
+
+            assert!(capsules.next().await.is_none());
+            Ok::<_, Error>(())
+        };
+        futures::try_join!(send_response, echo)?;
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let (mut send_capsules, body) = CapsuleBody::channel();
+    let request = Request::post("https://localhost/")
+        .body(body)
+        .expect("request");
+    let (mut send_request, recv_response) = conn.send_request(request);
+    let mut datagrams = send_request.datagrams().expect("datagrams");
+    assert_matches!(send_request.datagrams(), Err(Error::Internal(_)));
+
+    let client = async move {
+        let capsule = Capsule::Datagram(Bytes::from("capsule"));
+        send_capsules.send(capsule.clone()).await.unwrap();
+        let response = recv_response.await.expect("response");
+        let mut capsules = RecvCapsules::new(response.into_body());
+        assert_eq!(capsules.next().await.unwrap().unwrap(), capsule);
+
+        datagrams
+            .send_datagram(Bytes::from("datagram"))
+            .await
+            .unwrap();
+        assert_eq!(
+            datagrams.read_datagram().await,
+            Some(Bytes::from("datagram"))
+        );
+
+        drop(send_capsules);
+        assert!(capsules.next().await.is_none());
+    };
+    let (sent, ()) = futures::join!(send_request, client);
+    sent.expect("request sent");
+
+    timeout_join(server_handle).await.expect("server");
+}
+
+#[tokio::test]
+async fn datagrams_disabled() {
+    let helper = Helper::new();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (_, mut sender) = recv_req.await?;
+        assert_matches!(sender.datagrams(), Err(Error::Internal(_)));
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from(()))
+            .expect("response");
+        sender.send_response(response).await?;
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let (mut send_request, recv_response) = conn.send_request(get("/"));
+    assert_matches!(send_request.datagrams(), Err(Error::Internal(_)));
+    send_request.await.expect("request sent");
+    recv_response.await.expect("response");
+
+    timeout_join(server_handle).await.expect("server");
+}
+
+#[tokio::test]
+async fn client_pool() {
+    let mut helper = Helper::new();
+    helper.listen_any();
+    let mut incoming = helper.make_server();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let count = connections.clone();
+    tokio::spawn(async move {
+        while let Some(connecting) = incoming.next().await {
+            count.fetch_add(1, Ordering::SeqCst);
+            let mut incoming_req = connecting.await.expect("accept");
+            tokio::spawn(async move {
+                let mut first = true;
+                while let Some(recv_req) = incoming_req.next().await {
+                    // The client retries requests rejected without being processed
+                    if first {
+                        first = false;
+                        recv_req.reject();
+                        continue;
+                    }
+                    let (request, mut sender) = recv_req.await.expect("recv_req");
+                    if request.uri().path() == "/go_away" {
+                        incoming_req.go_away();
+                    }
+                    sender
+                        .send_response(Response::new(Body::from(())))
+                        .await
+                        .expect("send_response");
+                }
+            });
+        }
+    });
+
+    let client = helper.make_client();
+    let port = helper.socket_addr().port();
+    let request = |path: &str| {
+        Request::get(format!("https://localhost:{}{}", port, path))
+            .body(Body::from(()))
+            .expect("request")
+    };
+
+    // Concurrent requests share the connection established for the first one
+    let responses = future::join_all(
+        ["/first", "/second"]
+            .iter()
+            .map(|&path| client.request(request(path))),
+    )
+    .await;
+    for response in responses {
+        assert_eq!(response.expect("response").status(), StatusCode::OK);
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    let response = client.request(request("/go_away")).await.expect("response");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    // Sent on a new connection, or retried there if the GOAWAY is still on its way
+    let response = client.request(request("/third")).await.expect("response");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn client_pool_concurrent() {
+    let mut helper = Helper::new();
+    helper.listen_any();
+    let mut incoming = helper.make_server();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let count = connections.clone();
+    tokio::spawn(async move {
+        while let Some(connecting) = incoming.next().await {
+            count.fetch_add(1, Ordering::SeqCst);
+            let mut incoming_req = connecting.await.expect("accept");
+            tokio::spawn(async move {
+                while let Some(recv_req) = incoming_req.next().await {
+                    let (_, mut sender) = recv_req.await.expect("recv_req");
+                    sender
+                        .send_response(Response::new(Body::from(())))
+                        .await
+                        .expect("send_response");
+                }
+            });
+        }
+    });
+
+    let client = helper.make_client();
+    let authority = format!("localhost:{}", helper.socket_addr().port());
+    let responses = future::join_all((0..8).map(|_| {
+        let request = Request::get(format!("https://{}/", authority))
+            .body(Body::from(()))
+            .expect("request");
+        client.request(request)
+    }))
+    .await;
+    for response in responses {
+        assert_eq!(response.expect("response").status(), StatusCode::OK);
+    }
+    assert_eq!(client.pooled(&authority), 1);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[derive(Clone)]
+struct EchoPath;
+
+impl Service<Request<RecvBody>> for EchoPath {
+    type Response = Response<Body>;
+    type Error = &'static str;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<RecvBody>) -> Self::Future {
+        match request.uri().path() {
+            "/fail" => future::err("service failure"),
+            path => future::ok(Response::new(Body::from(path))),
+        }
+    }
+}
+
+struct MakeEchoPath;
+
+impl Service<SocketAddr> for MakeEchoPath {
+    type Response = EchoPath;
+    type Error = Infallible;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: SocketAddr) -> Self::Future {
+        future::ok(EchoPath)
+    }
+}
+
+#[tokio::test]
+async fn serve_service() {
+    let helper = Helper::new();
+    let incoming = helper.make_server();
+    tokio::spawn(server::serve(incoming, MakeEchoPath));
+
+    let conn = helper.make_connection().await;
+    let (req, resp) = conn.send_request(get("/hello"));
+    req.await.expect("request");
+    let mut response = resp.await.expect("response");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        &response.body_mut().read_to_end().await.expect("body")[..],
+        b"/hello"
+    );
+
+    let (req, resp) = conn.send_request(get("/fail"));
+    req.await.expect("failing request");
+    assert_matches!(
+        resp.await,
+        Err(Error::Http(HttpError::RequestCancelled, None))
+    );
+}
+
+#[tokio::test]
+async fn serve_shutdown() {
+    let helper = Helper::new();
+    let incoming = helper.make_server();
+    let shutdown = incoming.shutdown_handle();
+    let serve_handle = tokio::spawn(server::serve(incoming, MakeEchoPath));
+
+    let conn = helper.make_connection().await;
+    let (req, resp) = conn.send_request(get("/hello"));
+    req.await.expect("request");
+    assert_eq!(resp.await.expect("response").status(), StatusCode::OK);
+
+    shutdown
+        .shutdown(Instant::now() + Duration::from_secs(1))
+        .await;
+    assert_matches!(timeout_join(serve_handle).await, Ok(()));
+}
+
+#[tokio::test]
+async fn connect_tunnel() {
+    let helper = Helper::new();
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (request, sender) = recv_req.await?;
+        assert_eq!(request.method(), Method::CONNECT);
+        assert_eq!(request.uri(), "example.com:443");
+
+        let tunnel = sender.accept_connect(request).await?;
+        let (mut recv, mut send) = tokio::io::split(tunnel);
+        tokio::io::copy(&mut recv, &mut send).await?;
+        send.shutdown().await?;
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let mut tunnel = conn
+        .connect_tunnel(Authority::from_static("example.com:443"))
+        .await
+        .expect("tunnel");
+    let payload = vec![0x42; 100_000];
+    tunnel.write_all(&payload).await.unwrap();
+    tunnel.shutdown().await.unwrap();
+    let mut echoed = Vec::new();
+    tunnel.read_to_end(&mut echoed).await.unwrap();
+    assert_eq!(echoed, payload);
+
+    timeout_join(server_handle).await.expect("server");
+}
+
+#[tokio::test]
+async fn connect_tunnel_refused() {
+    let helper = Helper::new();
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (_, mut sender) = recv_req.await?;
+        let response = Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .body(Body::from(()))
+            .unwrap();
+        sender.send_response(response).await?;
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    assert_matches!(
+        conn.connect_tunnel(Authority::from_static("example.com:443"))
+            .await
+            .map(|_| ()),
+        Err(Error::ConnectRefused(StatusCode::BAD_GATEWAY))
+    );
+
+    timeout_join(server_handle).await.expect("server");
+}
+
+async fn connect_udp_echo(datagrams: bool) {
+    let mut helper = Helper::new();
+    let mut settings = Settings::new();
+    settings
+        .set_enable_connect_protocol(true)
+        .set_h3_datagram(datagrams);
+    helper.server_settings(settings);
+    let mut settings = Settings::new();
+    settings.set_h3_datagram(datagrams);
+    helper.client_settings(settings);
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (request, sender) = recv_req.await?;
+        let template = UriTemplate::well_known("localhost").unwrap();
+        assert_eq!(template.target(request.uri()), Some(Target::new("::1", 53)));
+
+        let mut tunnel = sender.accept_connect_udp(request).await?;
+        while let Some(payload) = tunnel.recv().await {
+            tunnel.send(&payload?).await?;
+        }
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let template = UriTemplate::well_known("localhost").unwrap();
+    let mut tunnel = conn
+        .connect_udp(&template, &Target::new("::1", 53))
+        .await
+        .expect("tunnel");
+    for payload in &[&b"first"[..], &b"second"[..]] {
+        tunnel.send(payload).await.unwrap();
+        assert_eq!(&tunnel.recv().await.unwrap().unwrap()[..], *payload);
+    }
+    tunnel.close().await.unwrap();
+
+    timeout_join(server_handle).await.expect("server");
+}
+
+#[tokio::test]
+async fn connect_udp_datagrams() {
+    connect_udp_echo(true).await;
+}
+
+#[tokio::test]
+async fn connect_udp_capsules() {
+    connect_udp_echo(false).await;
+}
+
+#[tokio::test]
+async fn connect_udp_unsupported() {
+    let helper = Helper::new();
+    let incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move { serve_one(incoming).await });
+
+    let conn = helper.make_connection().await;
+    let template = UriTemplate::well_known("localhost").unwrap();
+    assert_matches!(
+        conn.connect_udp(&template, &Target::new("::1", 53))
+            .await
+            .map(|_| ()),
+        Err(Error::Unsupported(_))
+    );
+
+    conn.close();
+    timeout_join(server_handle).await.expect("server");
+}
+
+#[tokio::test]
+async fn webtransport_session() {
+    let mut helper = Helper::new();
+    helper.webtransport();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (request, sender) = recv_req.await?;
+        assert_eq!(request.method(), Method::CONNECT);
+        let session = sender.accept_webtransport(request).await?;
+
+        let (mut send, recv) = session.accept_bi().await.expect("bi stream");
+        let data = recv.read_to_end(1024).await.expect("read bi");
+        send.write_all(&data).await?;
+        send.finish().await?;
+
+        let datagram = session.read_datagram().await.expect("datagram");
+        session.send_datagram(datagram)?;
+
+        let mut send = session.open_uni().await?;
+        send.write_all(b"from server").await?;
+        send.finish().await?;
+
+        // Resolves once the client closes the session
+        assert!(session.accept_bi().await.is_none());
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let session = conn
+        .webtransport("https://localhost/echo".parse().unwrap())
+        .await
+        .expect("session");
+
+    let (mut send, recv) = session.open_bi().await.expect("open bi");
+    send.write_all(b"ping").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(&recv.read_to_end(1024).await.unwrap()[..], b"ping");
+
+    session.send_datagram(Bytes::from("datagram")).unwrap();
+    assert_eq!(session.read_datagram().await, Some(Bytes::from("datagram")));
+
+    let recv = session.accept_uni().await.expect("uni stream");
+    assert_eq!(&recv.read_to_end(1024).await.unwrap()[..], b"from server");
+
+    drop(session);
+    timeout_join(server_handle).await.expect("server");
+}
+
+#[tokio::test]
+async fn webtransport_session_gone() {
+    let mut helper = Helper::new();
+    helper.webtransport();
+
+    let mut incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move {
+        let mut incoming_req = incoming
+            .next()
+            .await
+            .expect("connecting")
+            .await
+            .expect("accept");
+        let recv_req = incoming_req.next().await.expect("wait request");
+        let (request, sender) = recv_req.await?;
+        let session = sender.accept_webtransport(request).await?;
+        session.close().await?;
+        // Keep the connection up until the client is done
+        assert!(incoming_req.next().await.is_none());
+        Ok::<_, Error>(())
+    });
+
+    let conn = helper.make_connection().await;
+    let session = conn
+        .webtransport("https://localhost/echo".parse().unwrap())
+        .await
+        .expect("session");
+    // Resolves once the server closed the session
+    assert!(session.accept_bi().await.is_none());
+
+    // The server no longer knows the session, but must not buffer its streams
+    let mut send = session.open_uni().await.expect("open uni");
+    let err = timeout(Duration::from_secs(1), async {
+        loop {
+            if let Err(e) = send.write_all(&[0; 1024]).await {
+                break e;
+            }
+        }
+    })
+    .await
+    .expect("stream not stopped");
+    assert_matches!(
+        err,
+        WriteError::Stopped(code) if code == VarInt::from(ErrorCode::WEBTRANSPORT_SESSION_GONE)
+    );
+
+    conn.close();
+    timeout_join(server_handle).await.expect("server");
+}
+
+#[tokio::test]
+async fn webtransport_disabled_by_server() {
+    let mut helper = Helper::new();
+    helper.webtransport();
+    helper.server_settings(Settings::new());
+
+    let incoming = helper.make_server();
+    let server_handle = tokio::spawn(async move { serve_one(incoming).await });
+
+    let conn = helper.make_connection().await;
+    assert_matches!(
+        conn.webtransport("https://localhost/echo".parse().unwrap())
+            .await
+            .map(|_| ()),
+        Err(Error::Unsupported(_))
+    );
+
+    conn.close();
+    timeout_join(server_handle).await.expect("server");
+}
+
 async fn serve_n_0rtt(mut incoming: IncomingConnection, n: usize) -> Result<(), crate::Error> {
     for _ in 0..n {
         let (mut incoming_req, _) = incoming
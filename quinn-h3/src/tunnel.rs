@@ -0,0 +1,191 @@
+//! Byte tunnels established by the `CONNECT` method
+//!
+//! A `CONNECT` request without a [`Protocol`] asks the server to open a connection to the
+//! authority given in its URI, `example.com:443`, and to relay bytes between it and the request
+//! stream. Once the server accepted the request, both ends exchange the tunnelled bytes in `DATA`
+//! frames through a [`Tunnel`], implementing `AsyncRead` and `AsyncWrite`.
+//!
+//! Clients open one with [`Connection::connect_tunnel()`], servers accept one with
+//! [`Sender::accept_connect()`]. Any request sent with the `CONNECT` method and an authority-only
+//! URI is a tunnel request.
+//!
+//! # Example: TCP proxy
+//!
+//! ```
+//! use anyhow::Result;
+//! use http::{Method, Request, Response, StatusCode};
+//! use quinn_h3::{server::Sender, Body, RecvBody};
+//! use tokio::{io, net::TcpStream};
+//!
+//! async fn proxy(request: Request<RecvBody>, mut sender: Sender) -> Result<()> {
+//!     let authority = match request.uri().authority() {
+//!         Some(authority) if request.method() == Method::CONNECT => authority.to_string(),
+//!         _ => return Ok(()),
+//!     };
+//!
+//!     let stream = match TcpStream::connect(authority).await {
+//!         Ok(stream) => stream,
+//!         Err(_) => {
+//!             let response = Response::builder()
+//!                 .status(StatusCode::BAD_GATEWAY)
+//!                 .body(Body::from(()))?;
+//!             sender.send_response(response).await?;
+//!             return Ok(());
+//!         }
+//!     };
+//!
+//!     let tunnel = sender.accept_connect(request).await?;
+//!     let (mut recv, mut send) = io::split(tunnel);
+//!     let (mut read, mut write) = io::split(stream);
+//!     futures::try_join!(io::copy(&mut recv, &mut write), io::copy(&mut read, &mut send))?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`Protocol`]: ../struct.Protocol.html
+//! [`Tunnel`]: struct.Tunnel.html
+//! [`Connection::connect_tunnel()`]: ../client/struct.Connection.html#method.connect_tunnel
+//! [`Sender::accept_connect()`]: ../server/struct.Sender.html#method.accept_connect
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::ready;
+use http_body::Body as HttpBody;
+use quinn::SendStream;
+use quinn_proto::{StreamId, VarInt};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    connection::ConnectionRef,
+    proto::frame::{DataFrame, FrameHeader},
+    Error, RecvBody,
+};
+
+/// Bytes relayed between a client and a server through a `CONNECT` request
+///
+/// Reading returns the bytes sent by the peer, until it shuts its side of the tunnel down.
+/// Shutting the writing side down finishes the request stream. Dropping the tunnel closes it.
+pub struct Tunnel {
+    conn: ConnectionRef,
+    stream_id: StreamId,
+    send: SendStream,
+    recv: RecvBody,
+    // Received bytes not read yet
+    read_buf: Bytes,
+    // Header of the DATA frame being written
+    header: Bytes,
+    // Payload of the DATA frame being written not sent yet
+    frame_remaining: usize,
+}
+
+impl Tunnel {
+    pub(crate) fn new(conn: ConnectionRef, send: SendStream, recv: RecvBody) -> Self {
+        Self {
+            conn,
+            stream_id: send.id(),
+            send,
+            recv,
+            read_buf: Bytes::new(),
+            header: Bytes::new(),
+            frame_remaining: 0,
+        }
+    }
+
+    /// ID of the request stream of the tunnel
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    fn poll_write_header(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while self.header.has_remaining() {
+            let n = ready!(Pin::new(&mut self.send).poll_write(cx, &self.header))?;
+            self.header.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for Tunnel {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        while self.read_buf.is_empty() {
+            match ready!(Pin::new(&mut self.recv).poll_data(cx)) {
+                Some(Ok(data)) => self.read_buf = data,
+                Some(Err(e)) => return Poll::Ready(Err(io_error(e))),
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+        let n = buf.len().min(self.read_buf.len());
+        buf[..n].copy_from_slice(&self.read_buf[..n]);
+        self.read_buf.advance(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for Tunnel {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        // Payloads are written straight from `buf`, so a frame can span several writes
+        if self.frame_remaining == 0 {
+            let len = buf.len().min(MAX_DATA_FRAME_SIZE);
+            let mut header = BytesMut::with_capacity(2 * VarInt::MAX_SIZE);
+            DataFrame {
+                payload: &buf[..len],
+            }
+            .encode_header(&mut header);
+            self.header = header.freeze();
+            self.frame_remaining = len;
+        }
+        ready!(self.poll_write_header(cx))?;
+
+        let len = buf.len().min(self.frame_remaining);
+        let n = ready!(Pin::new(&mut self.send).poll_write(cx, &buf[..len]))?;
+        self.frame_remaining -= n;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.poll_write_header(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_header(cx))?;
+        Pin::new(&mut self.send).poll_shutdown(cx)
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        let mut conn = self.conn.h3.lock().unwrap();
+        conn.inner.request_finished(self.stream_id);
+        conn.wake();
+    }
+}
+
+fn io_error(err: Error) -> io::Error {
+    match err {
+        Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, e),
+    }
+}
+
+// Largest payload written in a single DATA frame
+const MAX_DATA_FRAME_SIZE: usize = 64 * 1024;
@@ -0,0 +1,154 @@
+//! WebTransport sessions over HTTP/3
+//!
+//! A [`WebTransportSession`] is established by an extended `CONNECT` request, carrying the
+//! `webtransport` [`Protocol`]. Clients open one with [`Connection::webtransport()`], servers
+//! accept one with [`Sender::accept_webtransport()`]. Both peers need to enable WebTransport in
+//! their [`Settings`].
+//!
+//! A session multiplexes bidirectional and unidirectional streams, as well as unreliable
+//! datagrams, over the HTTP/3 connection, alongside regular requests. It ends when either peer
+//! closes the `CONNECT` request stream, which happens when the session is dropped.
+//!
+//! # Example: echo server
+//!
+//! ```
+//! use anyhow::Result;
+//! use futures::AsyncWriteExt;
+//! use http::{Method, Request};
+//! use quinn_h3::{server::Sender, Protocol, RecvBody};
+//!
+//! async fn echo(request: Request<RecvBody>, sender: Sender) -> Result<()> {
+//!     if request.method() != Method::CONNECT
+//!         || request.extensions().get::<Protocol>() != Some(&Protocol::WEB_TRANSPORT)
+//!     {
+//!         return Ok(());
+//!     }
+//!
+//!     let session = sender.accept_webtransport(request).await?;
+//!     while let Some((mut send, recv)) = session.accept_bi().await {
+//!         let data = recv.read_to_end(64 * 1024).await?;
+//!         send.write_all(&data).await?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`WebTransportSession`]: struct.WebTransportSession.html
+//! [`Protocol`]: ../struct.Protocol.html
+//! [`Connection::webtransport()`]: ../client/struct.Connection.html#method.webtransport
+//! [`Sender::accept_webtransport()`]: ../server/struct.Sender.html#method.accept_webtransport
+//! [`Settings`]: ../struct.Settings.html
+
+use bytes::{Bytes, BytesMut};
+use futures::future;
+use quinn::{RecvStream, SendStream};
+use quinn_proto::{
+    coding::{BufMutExt, Codec},
+    StreamId, VarInt,
+};
+
+use crate::{
+    connection::ConnectionRef,
+    frame::FrameStream,
+    proto::{frame::Type as FrameType, StreamType},
+    Error,
+};
+
+/// An established WebTransport session
+///
+/// Streams opened by the peer are received with [`accept_bi()`] and [`accept_uni()`], which
+/// resolve to `None` once the session is closed. Dropping the session closes it.
+///
+/// [`accept_bi()`]: #method.accept_bi
+/// [`accept_uni()`]: #method.accept_uni
+pub struct WebTransportSession {
+    conn: ConnectionRef,
+    session_id: StreamId,
+    send: SendStream,
+}
+
+impl WebTransportSession {
+    pub(crate) fn new(conn: ConnectionRef, send: SendStream, connect: FrameStream) -> Self {
+        let session_id = send.id();
+        conn.h3.lock().unwrap().open_session(session_id, connect);
+        Self {
+            conn,
+            session_id,
+            send,
+        }
+    }
+
+    /// ID of the session, the one of its `CONNECT` request stream
+    pub fn session_id(&self) -> StreamId {
+        self.session_id
+    }
+
+    /// Open a bidirectional stream within the session
+    pub async fn open_bi(&self) -> Result<(SendStream, RecvStream), Error> {
+        let (mut send, recv) = self.conn.quic.open_bi().await?;
+        let mut buf = BytesMut::with_capacity(2 * VarInt::MAX_SIZE);
+        FrameType::WEBTRANSPORT_STREAM.encode(&mut buf);
+        buf.write_var(self.session_id.0);
+        send.write_all(&buf).await?;
+        Ok((send, recv))
+    }
+
+    /// Open a unidirectional stream within the session
+    pub async fn open_uni(&self) -> Result<SendStream, Error> {
+        let mut send = self.conn.quic.open_uni().await?;
+        let mut buf = BytesMut::with_capacity(2 * VarInt::MAX_SIZE);
+        StreamType::WEBTRANSPORT_UNI.encode(&mut buf);
+        buf.write_var(self.session_id.0);
+        send.write_all(&buf).await?;
+        Ok(send)
+    }
+
+    /// Accept the next bidirectional stream opened by the peer
+    pub async fn accept_bi(&self) -> Option<(SendStream, RecvStream)> {
+        future::poll_fn(|cx| {
+            let mut conn = self.conn.h3.lock().unwrap();
+            conn.poll_session_bi(cx, self.session_id)
+        })
+        .await
+    }
+
+    /// Accept the next unidirectional stream opened by the peer
+    pub async fn accept_uni(&self) -> Option<RecvStream> {
+        future::poll_fn(|cx| {
+            let mut conn = self.conn.h3.lock().unwrap();
+            conn.poll_session_uni(cx, self.session_id)
+        })
+        .await
+    }
+
+    /// Send an unreliable datagram to the peer
+    ///
+    /// Fails if the peer did not enable HTTP datagrams, or if `data` does not fit in a QUIC
+    /// datagram once prefixed with the session ID.
+    pub fn send_datagram(&self, data: Bytes) -> Result<(), Error> {
+        self.conn.send_datagram(self.session_id, data)
+    }
+
+    /// Receive the next datagram sent by the peer for this session
+    pub async fn read_datagram(&self) -> Option<Bytes> {
+        future::poll_fn(|cx| {
+            let mut conn = self.conn.h3.lock().unwrap();
+            conn.poll_datagram(cx, self.session_id)
+        })
+        .await
+    }
+
+    /// Close the session
+    ///
+    /// Finishes the `CONNECT` request stream, resolving once the peer acknowledged it.
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.send.finish().await?;
+        Ok(())
+    }
+}
+
+impl Drop for WebTransportSession {
+    fn drop(&mut self) {
+        self.conn.h3.lock().unwrap().close_session(self.session_id);
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// Decides whether a client's attempt to send 0-RTT data may be accepted
+///
+/// 0-RTT data is not protected against replay: an attacker who captures a client's first flight can
+/// resend it to the server, causing any requests it carries to be processed again. Servers that
+/// accept 0-RTT data with side effects should configure an implementation of this trait through
+/// `ServerConfig::anti_replay`.
+pub trait AntiReplay: Send + Sync {
+    /// Record an attempt to send early data, returning whether it may be accepted
+    ///
+    /// `id` uniquely identifies the client's first handshake message. An attempt whose `id` has
+    /// already been seen must be refused. Attempts only need to be remembered until `expires`,
+    /// after which the server will no longer accept early data from the same resumption ticket.
+    fn check(&self, id: &[u8], now: SystemTime, expires: SystemTime) -> bool;
+}
+
+/// An in-memory `AntiReplay` implementation with bounded capacity
+///
+/// Each attempt is remembered until it expires. While `capacity` attempts are being remembered,
+/// further early data is refused. Only protects a single server process; deployments where
+/// multiple servers share resumption ticket keys need a strike register shared between them.
+#[derive(Debug)]
+pub struct StrikeRegister {
+    capacity: usize,
+    state: Mutex<StrikeState>,
+}
+
+impl StrikeRegister {
+    /// Construct a strike register remembering at most `capacity` attempts at once
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(StrikeState::default()),
+        }
+    }
+}
+
+impl AntiReplay for StrikeRegister {
+    fn check(&self, id: &[u8], now: SystemTime, expires: SystemTime) -> bool {
+        let state = &mut *self.state.lock().unwrap();
+        while let Some(Reverse((time, _))) = state.expiry.peek() {
+            if *time > now {
+                break;
+            }
+            let Reverse((_, id)) = state.expiry.pop().unwrap();
+            state.seen.remove(&id);
+        }
+        if expires <= now {
+            // The ticket can't be used again, so there's nothing to remember
+            return true;
+        }
+        if state.seen.contains(id) || state.seen.len() >= self.capacity {
+            return false;
+        }
+        let id = Box::<[u8]>::from(id);
+        state.seen.insert(id.clone());
+        state.expiry.push(Reverse((expires, id)));
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+struct StrikeState {
+    seen: HashSet<Box<[u8]>>,
+    expiry: BinaryHeap<Reverse<(SystemTime, Box<[u8]>)>>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn strike_register() {
+        let register = StrikeRegister::new(2);
+        let now = SystemTime::now();
+        let expires = now + Duration::from_secs(10);
+        assert!(register.check(b"a", now, expires));
+        assert!(!register.check(b"a", now, expires));
+        assert!(register.check(b"b", now, expires));
+        // Full
+        assert!(!register.check(b"c", now, expires));
+        // Expired entries are forgotten
+        let later = expires + Duration::from_secs(1);
+        assert!(register.check(b"c", later, later + Duration::from_secs(10)));
+        assert!(register.check(b"a", later, later + Duration::from_secs(10)));
+    }
+}
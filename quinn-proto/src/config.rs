@@ -1,13 +1,17 @@
 use std::{cmp, fmt, num::TryFromIntError, sync::Arc, time::Duration};
 
+use bytes::Bytes;
 use err_derive::Error;
 use rand::RngCore;
 
 #[cfg(feature = "rustls")]
 use crate::crypto::types::{Certificate, CertificateChain, PrivateKey};
+#[cfg(feature = "rustls")]
+use crate::SessionStore;
 use crate::{
     crypto::{self, ClientConfig as _, HmacKey as _, ServerConfig as _},
-    VarInt, MAX_CID_SIZE,
+    extension::FrameExtensions,
+    transport_parameters, AntiReplay, FrameExtension, IncomingFilter, VarInt, MAX_CID_SIZE,
 };
 
 /// Parameters governing the core QUIC state machine
@@ -43,10 +47,15 @@ pub struct TransportConfig {
     pub(crate) loss_reduction_factor: f32,
     pub(crate) persistent_congestion_threshold: u32,
     pub(crate) keep_alive_interval: Option<Duration>,
+    pub(crate) handshake_timeout: Option<Duration>,
     pub(crate) crypto_buffer_size: usize,
     pub(crate) allow_spin: bool,
     pub(crate) datagram_receive_buffer_size: Option<usize>,
     pub(crate) datagram_send_buffer_size: usize,
+
+    pub(crate) custom_transport_parameters: Vec<(VarInt, Bytes)>,
+    pub(crate) frame_extensions: Arc<FrameExtensions>,
+    pub(crate) frame_receive_buffer_size: usize,
 }
 
 impl TransportConfig {
@@ -193,6 +202,16 @@ impl TransportConfig {
         self
     }
 
+    /// Maximum duration to wait for the handshake to complete before giving up on the connection
+    ///
+    /// Unlike `max_idle_timeout`, this bounds the handshake regardless of whether the peer is
+    /// responding, allowing clients to quickly fall back to other transports. Expiry is reported as
+    /// `ConnectionError::HandshakeTimedOut`. `None` to disable, which is the default.
+    pub fn handshake_timeout(&mut self, value: Option<Duration>) -> &mut Self {
+        self.handshake_timeout = value;
+        self
+    }
+
     /// Maximum quantity of out-of-order crypto layer data to buffer
     pub fn crypto_buffer_size(&mut self, value: usize) -> &mut Self {
         self.crypto_buffer_size = value;
@@ -229,6 +248,51 @@ impl TransportConfig {
         self.datagram_send_buffer_size = value;
         self
     }
+
+    /// Send an application-defined transport parameter to the peer
+    ///
+    /// Allows applications to negotiate their own protocol extensions during the handshake. The
+    /// peer's values can be read with `Connection::remote_transport_parameter`. Replaces any value
+    /// previously set for `id`. Fails if `id` is used by the protocol itself.
+    pub fn custom_transport_parameter(
+        &mut self,
+        id: VarInt,
+        value: impl Into<Bytes>,
+    ) -> Result<&mut Self, ConfigError> {
+        if transport_parameters::is_reserved(id.into_inner()) {
+            return Err(ConfigError::ReservedTransportParameter);
+        }
+        let params = &mut self.custom_transport_parameters;
+        match params.binary_search_by_key(&id, |x| x.0) {
+            Ok(i) => params[i].1 = value.into(),
+            Err(i) => params.insert(i, (id, value.into())),
+        }
+        Ok(self)
+    }
+
+    /// Register application-defined frame types
+    ///
+    /// Fails if any of the extension's frame types are defined by the protocol or were already
+    /// registered. See `FrameExtension` for details.
+    pub fn frame_extension(
+        &mut self,
+        extension: Arc<dyn FrameExtension>,
+    ) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.frame_extensions).insert(extension)?;
+        Ok(self)
+    }
+
+    /// Maximum number of incoming application-defined frame bytes to buffer
+    ///
+    /// Frames received from the peer are held until the application calls
+    /// `Connection::recv_frame`. Because frames may be delivered reliably they are never dropped;
+    /// instead, if the aggregate encoded size of unconsumed frames would exceed this value, the
+    /// connection is closed with a `PROTOCOL_VIOLATION` error. Extensions that may send many frames
+    /// should apply their own flow control.
+    pub fn frame_receive_buffer_size(&mut self, value: usize) -> &mut Self {
+        self.frame_receive_buffer_size = value;
+        self
+    }
 }
 
 impl Default for TransportConfig {
@@ -260,10 +324,15 @@ impl Default for TransportConfig {
             loss_reduction_factor: 0.5,
             persistent_congestion_threshold: 3,
             keep_alive_interval: None,
+            handshake_timeout: None,
             crypto_buffer_size: 16 * 1024,
             allow_spin: true,
             datagram_receive_buffer_size: Some(STREAM_RWND as usize),
             datagram_send_buffer_size: 1024 * 1024,
+
+            custom_transport_parameters: Vec::new(),
+            frame_extensions: Arc::new(FrameExtensions::default()),
+            frame_receive_buffer_size: STREAM_RWND as usize,
         }
     }
 }
@@ -389,16 +458,36 @@ where
     /// Microseconds after a stateless retry token was issued for which it's considered valid.
     pub(crate) retry_token_lifetime: u64,
 
+    /// Number of concurrent incoming handshakes at or above which clients must prove ownership of
+    /// their address with a stateless retry.
+    pub(crate) retry_threshold: Option<u32>,
+
     /// Maximum number of incoming connections to buffer.
     ///
     /// Accepting a connection removes it from the buffer, so this does not need to be large.
     pub(crate) accept_buffer: u32,
 
+    /// Maximum number of simultaneous connections, including those still handshaking.
+    pub(crate) concurrent_connections: u32,
+
+    /// Maximum number of new connections a single IP address may initiate per
+    /// `handshake_rate_interval`.
+    pub(crate) max_handshakes_per_ip: Option<u32>,
+    /// Period over which `max_handshakes_per_ip` is enforced.
+    pub(crate) handshake_rate_interval: Duration,
+
     /// Whether to allow clients to migrate to new addresses
     ///
     /// Improves behavior for clients that move between different internet connections or suffer NAT
     /// rebinding. Enabled by default.
     pub(crate) migration: bool,
+
+    /// Strike register used to detect replayed 0-RTT data
+    pub(crate) anti_replay: Option<Arc<dyn AntiReplay>>,
+    /// Maximum age of a resumption ticket for which 0-RTT data will be accepted
+    pub(crate) early_data_window: Option<Duration>,
+    /// Screens connection attempts before any state is allocated for them
+    pub(crate) incoming_filter: Option<Arc<dyn IncomingFilter>>,
 }
 
 impl<S> ServerConfig<S>
@@ -414,10 +503,19 @@ where
             token_key: Arc::new(token_key),
             use_stateless_retry: false,
             retry_token_lifetime: 15_000_000,
+            retry_threshold: None,
 
             accept_buffer: 1024,
+            concurrent_connections: u32::max_value(),
+
+            max_handshakes_per_ip: None,
+            handshake_rate_interval: Duration::from_secs(1),
 
             migration: true,
+
+            anti_replay: None,
+            early_data_window: None,
+            incoming_filter: None,
         }
     }
 
@@ -441,6 +539,17 @@ where
         self
     }
 
+    /// Number of concurrent incoming handshakes at or above which clients must prove ownership of
+    /// their address with a stateless retry
+    ///
+    /// Allows the extra round-trip of `use_stateless_retry` to be paid only while the server is
+    /// under load, e.g. during a denial of service attack. `None`, the default, disables automatic
+    /// retries. Has no effect if `use_stateless_retry` is set.
+    pub fn retry_threshold(&mut self, value: Option<u32>) -> &mut Self {
+        self.retry_threshold = value;
+        self
+    }
+
     /// Maximum number of incoming connections to buffer.
     ///
     /// Accepting a connection removes it from the buffer, so this does not need to be large.
@@ -449,6 +558,34 @@ where
         self
     }
 
+    /// Maximum number of simultaneous incoming connections on the endpoint, including connections
+    /// still handshaking
+    ///
+    /// Connections that are closing or draining do not count towards the limit, nor do outgoing
+    /// connections. Incoming connections beyond this limit are refused with `CONNECTION_REFUSED`. Unlimited by
+    /// default.
+    pub fn concurrent_connections(&mut self, value: u32) -> &mut Self {
+        self.concurrent_connections = value;
+        self
+    }
+
+    /// Maximum number of new connections a single IP address may initiate per
+    /// `handshake_rate_interval`, or `None` for no limit
+    ///
+    /// Excess connection attempts are refused with `CONNECTION_REFUSED`. When stateless retries are
+    /// in use, only attempts from validated addresses are counted, so spoofed packets cannot
+    /// exhaust another host's allowance.
+    pub fn max_handshakes_per_ip(&mut self, value: Option<u32>) -> &mut Self {
+        self.max_handshakes_per_ip = value;
+        self
+    }
+
+    /// Period over which `max_handshakes_per_ip` is enforced. Defaults to one second.
+    pub fn handshake_rate_interval(&mut self, value: Duration) -> &mut Self {
+        self.handshake_rate_interval = value;
+        self
+    }
+
     /// Whether to allow clients to migrate to new addresses
     ///
     /// Improves behavior for clients that move between different internet connections or suffer NAT
@@ -457,6 +594,36 @@ where
         self.migration = value;
         self
     }
+
+    /// Mechanism used to refuse replayed 0-RTT data
+    ///
+    /// When `None`, the default, 0-RTT data is accepted whenever the cryptographic protocol allows,
+    /// and may be replayed by an attacker. Replays can only be detected for as long as the
+    /// resumption ticket used remains valid, so a short `early_data_window` keeps the required
+    /// state small.
+    pub fn anti_replay(&mut self, value: Option<Arc<dyn AntiReplay>>) -> &mut Self {
+        self.anti_replay = value;
+        self
+    }
+
+    /// Maximum age of a resumption ticket for which 0-RTT data will be accepted
+    ///
+    /// Connections resumed from older tickets fall back to a full 1-RTT handshake. `None`, the
+    /// default, imposes no limit beyond the ticket's lifetime.
+    pub fn early_data_window(&mut self, value: Option<Duration>) -> &mut Self {
+        self.early_data_window = value;
+        self
+    }
+
+    /// Screen incoming connection attempts before any state is allocated for them
+    ///
+    /// The filter can see the client's address, whether it has been validated, and the server name
+    /// and application protocols it requested. Configuring a filter enables validation of retry
+    /// tokens, so that it may request a Retry.
+    pub fn incoming_filter(&mut self, value: Option<Arc<dyn IncomingFilter>>) -> &mut Self {
+        self.incoming_filter = value;
+        self
+    }
 }
 
 #[cfg(feature = "rustls")]
@@ -470,6 +637,41 @@ impl ServerConfig<crypto::rustls::TlsSession> {
         Arc::make_mut(&mut self.crypto).set_single_cert(cert_chain.certs, key.inner)?;
         Ok(self)
     }
+
+    /// Choose the certificate chain presented to each client with `resolver`
+    ///
+    /// Use a `SniResolver` to serve different certificates depending on the server name requested
+    /// by the client. Replaces any certificate set with `certificate`.
+    pub fn certificate_resolver(
+        &mut self,
+        resolver: Arc<dyn rustls::ResolvesServerCert>,
+    ) -> &mut Self {
+        Arc::make_mut(&mut self.crypto).cert_resolver = resolver;
+        self
+    }
+
+    /// Verify client certificates against the certificate authorities in `roots`
+    ///
+    /// If `required` is set, clients which don't present a valid certificate are rejected.
+    /// Otherwise, anonymous clients are accepted, but any certificate presented must still be
+    /// valid. The verified chain is available from the connection's `AuthenticationData`.
+    pub fn client_auth(
+        &mut self,
+        roots: impl IntoIterator<Item = Certificate>,
+        required: bool,
+    ) -> Result<&mut Self, webpki::Error> {
+        let mut store = rustls::RootCertStore::empty();
+        for cert in roots {
+            store.add(&cert.inner)?;
+        }
+        let verifier = if required {
+            rustls::AllowAnyAuthenticatedClient::new(store)
+        } else {
+            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(store)
+        };
+        Arc::make_mut(&mut self.crypto).set_client_certificate_verifier(verifier);
+        Ok(self)
+    }
 }
 
 impl<S> fmt::Debug for ServerConfig<S>
@@ -483,8 +685,15 @@ where
             .field("token_key", &"[ elided ]")
             .field("use_stateless_retry", &self.use_stateless_retry)
             .field("retry_token_lifetime", &self.retry_token_lifetime)
+            .field("retry_threshold", &self.retry_threshold)
             .field("accept_buffer", &self.accept_buffer)
+            .field("concurrent_connections", &self.concurrent_connections)
+            .field("max_handshakes_per_ip", &self.max_handshakes_per_ip)
+            .field("handshake_rate_interval", &self.handshake_rate_interval)
             .field("migration", &self.migration)
+            .field("anti_replay", &self.anti_replay.is_some())
+            .field("early_data_window", &self.early_data_window)
+            .field("incoming_filter", &self.incoming_filter.is_some())
             .finish()
     }
 }
@@ -517,8 +726,15 @@ where
             token_key: self.token_key.clone(),
             use_stateless_retry: self.use_stateless_retry,
             retry_token_lifetime: self.retry_token_lifetime,
+            retry_threshold: self.retry_threshold,
             accept_buffer: self.accept_buffer,
+            concurrent_connections: self.concurrent_connections,
+            max_handshakes_per_ip: self.max_handshakes_per_ip,
+            handshake_rate_interval: self.handshake_rate_interval,
             migration: self.migration,
+            anti_replay: self.anti_replay.clone(),
+            early_data_window: self.early_data_window,
+            incoming_filter: self.incoming_filter.clone(),
         }
     }
 }
@@ -550,6 +766,26 @@ impl ClientConfig<crypto::rustls::TlsSession> {
             .add_server_trust_anchors(&webpki::TLSServerTrustAnchors(&[anchor]));
         Ok(self)
     }
+
+    /// Set the certificate chain that will be presented to servers requesting client authentication
+    pub fn client_certificate(
+        &mut self,
+        cert_chain: CertificateChain,
+        key: PrivateKey,
+    ) -> Result<&mut Self, rustls::TLSError> {
+        Arc::make_mut(&mut self.crypto).set_single_client_cert(cert_chain.certs, key.inner)?;
+        Ok(self)
+    }
+
+    /// Set where session tickets are kept for resuming connections
+    ///
+    /// Defaults to a `MemorySessionStore` holding 32 sessions. Use a `FileSessionStore` to resume
+    /// connections, including sending 0-RTT data, across process restarts.
+    pub fn session_store(&mut self, store: Arc<dyn SessionStore>) -> &mut Self {
+        Arc::make_mut(&mut self.crypto)
+            .set_persistence(Arc::new(crypto::rustls::SessionStoreAdapter(store)));
+        self
+    }
 }
 
 impl<S> Default for ClientConfig<S>
@@ -596,6 +832,12 @@ pub enum ConfigError {
     /// Value exceeds supported bounds
     #[error(display = "value exceeds supported bounds")]
     OutOfBounds,
+    /// Transport parameter identifier is reserved by the protocol
+    #[error(display = "transport parameter identifier is reserved")]
+    ReservedTransportParameter,
+    /// Frame type is defined by the protocol or already registered
+    #[error(display = "frame type is reserved")]
+    ReservedFrameType,
 }
 
 impl From<TryFromIntError> for ConfigError {
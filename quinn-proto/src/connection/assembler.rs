@@ -141,6 +141,24 @@ impl Assembler {
         self.data.clear();
         self.defragmented = 0;
     }
+
+    /// Discard buffered data at or beyond `end`
+    pub(crate) fn truncate(&mut self, end: u64) {
+        let old = mem::replace(&mut self.data, BinaryHeap::new());
+        for mut chunk in old.into_vec() {
+            if chunk.offset >= end {
+                continue;
+            }
+            chunk.bytes.truncate((end - chunk.offset) as usize);
+            self.data.push(chunk);
+        }
+        self.defragmented = self.defragmented.min(self.data.len());
+    }
+
+    /// Whether no data is buffered
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
 }
 
 #[derive(Debug, Eq)]
@@ -4,7 +4,7 @@ use std::{
     fmt, io, mem,
     net::SocketAddr,
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use bytes::{Bytes, BytesMut};
@@ -16,7 +16,7 @@ use crate::{
     cid_queue::CidQueue,
     coding::BufMutExt,
     config::{EndpointConfig, ServerConfig, TransportConfig},
-    crypto::{self, HeaderKey, KeyPair, Keys, PacketKey},
+    crypto::{self, HeaderKey, KeyPair, Keys, PacketKey, ServerConfig as _},
     frame,
     frame::{Close, Datagram, FrameStruct},
     packet::{Header, LongType, Packet, PacketNumber, PartialDecode, SpaceId},
@@ -26,8 +26,8 @@ use crate::{
         EndpointEventInner, IssuedCid,
     },
     transport_parameters::{self, TransportParameters},
-    Dir, Frame, Side, StreamId, Transmit, TransportError, TransportErrorCode, VarInt,
-    LOC_CID_COUNT, MAX_STREAM_COUNT, MIN_INITIAL_SIZE, MIN_MTU, RESET_TOKEN_SIZE,
+    ApplicationFrame, Dir, Frame, Side, StreamId, Transmit, TransportError, TransportErrorCode,
+    VarInt, LOC_CID_COUNT, MAX_STREAM_COUNT, MIN_INITIAL_SIZE, MIN_MTU, RESET_TOKEN_SIZE,
     TIMER_GRANULARITY,
 };
 
@@ -39,7 +39,7 @@ use spaces::{PacketSpace, Retransmits, SentPacket};
 
 mod streams;
 use streams::Streams;
-pub use streams::{FinishError, ReadError, StreamEvent, UnknownStream, WriteError};
+pub use streams::{FinishError, ReadError, ResetError, StreamEvent, UnknownStream, WriteError};
 
 mod timer;
 use timer::{Timer, TimerTable};
@@ -110,6 +110,13 @@ where
     /// Latest PATH_CHALLENGE token issued to the peer along the current path
     path_challenge: Option<u64>,
     accepted_0rtt: bool,
+    /// Start of the client's handshake, held back until its first message is complete
+    ///
+    /// Only used by servers, which read the requested server name from the whole ClientHello, and
+    /// may screen 0-RTT attempts on it, even if it spans several packets.
+    client_hello: Option<Vec<u8>>,
+    /// Server name requested by the client, once its ClientHello is complete
+    server_name: Option<String>,
     /// Whether the idle timer should be reset the next time an ack-eliciting packet is transmitted.
     permit_idle_reset: bool,
     /// Negotiated idle timeout
@@ -156,6 +163,12 @@ where
     rem_cids: CidQueue,
     /// State of the unreliable datagram extension
     datagrams: DatagramState,
+    /// Application-defined frames received but not yet delivered to the application
+    incoming_frames: VecDeque<ApplicationFrame>,
+    /// Total encoded size of `incoming_frames`
+    incoming_frames_size: usize,
+    /// Application-defined frames yet to be sent for the first time
+    outgoing_frames: VecDeque<ApplicationFrame>,
 }
 
 impl<S> Connection<S>
@@ -170,6 +183,7 @@ where
         loc_cid: ConnectionId,
         rem_cid: ConnectionId,
         remote: SocketAddr,
+        remote_validated: bool,
         crypto: S,
         now: Instant,
     ) -> Self {
@@ -187,10 +201,8 @@ where
             token: None,
             client_hello: None,
         });
+        let client_hello = server_config.as_ref().map(|_| Vec::new());
         let mut rng = StdRng::from_entropy();
-        let remote_validated = server_config
-            .as_ref()
-            .map_or(false, |c| c.use_stateless_retry);
         let mut this = Self {
             endpoint_config,
             server_config,
@@ -227,6 +239,8 @@ where
             next_crypto: None,
             path_challenge: None,
             accepted_0rtt: false,
+            client_hello,
+            server_name: None,
             permit_idle_reset: true,
             idle_timeout: config.max_idle_timeout,
             timers: TimerTable::default(),
@@ -255,10 +269,16 @@ where
                 config.stream_receive_window as u64,
             ),
             datagrams: DatagramState::new(),
+            incoming_frames: VecDeque::new(),
+            incoming_frames_size: 0,
+            outgoing_frames: VecDeque::new(),
             config,
             rem_cids: CidQueue::new(1),
             rng,
         };
+        if let Some(timeout) = this.config.handshake_timeout {
+            this.timers.set(Timer::Handshake, now + timeout);
+        }
         if side.is_client() {
             // Kick off the connection
             this.write_crypto();
@@ -306,7 +326,57 @@ where
     /// - the application performed some I/O on the connection
     /// - a call was made to `handle_event`
     /// - a call was made to `handle_timeout`
+    ///
+    /// Each `Transmit` holds a single datagram; see `poll_transmit_batch` to make use of
+    /// segmentation offload.
     pub fn poll_transmit(&mut self, now: Instant) -> Option<Transmit> {
+        self.poll_transmit_batch(now, 1)
+    }
+
+    /// Returns packets to transmit, batching up to `max_datagrams` datagrams per `Transmit`
+    ///
+    /// Like `poll_transmit`, but for use with segmentation offload. Must be at least 1.
+    pub fn poll_transmit_batch(&mut self, now: Instant, max_datagrams: usize) -> Option<Transmit> {
+        let mut contents = self.poll_datagram(now, self.mtu as usize)?;
+        let segment_size = contents.len();
+        let mut datagrams = 1;
+        // Only full-sized datagrams are batched, so that datagrams built to the same size afterwards
+        // have ample room, and every segment but the last is of equal size.
+        if self.state.is_established() && segment_size + MIN_PACKET_SPACE > self.mtu as usize {
+            while datagrams < max_datagrams
+                && contents.len() + segment_size <= MAX_SEGMENTED_TRANSMIT_SIZE
+            {
+                let datagram = match self.poll_datagram(now, segment_size) {
+                    Some(x) => x,
+                    None => break,
+                };
+                contents.extend_from_slice(&datagram);
+                datagrams += 1;
+                if datagram.len() < segment_size {
+                    // Only the last segment may be smaller
+                    break;
+                }
+            }
+        }
+
+        Some(Transmit {
+            destination: self.path.remote,
+            contents: contents.into(),
+            ecn: if self.path.sending_ecn {
+                Some(EcnCodepoint::ECT0)
+            } else {
+                None
+            },
+            segment_size: if datagrams > 1 {
+                Some(segment_size)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Assemble a single datagram of at most `max_size` bytes
+    fn poll_datagram(&mut self, now: Instant, max_size: usize) -> Option<Vec<u8>> {
         if self.state.is_handshake()
             && !self.remote_validated
             && self.side.is_server()
@@ -350,7 +420,7 @@ where
             ),
         };
 
-        let mut buf = Vec::with_capacity(self.mtu as usize);
+        let mut buf = Vec::with_capacity(max_size);
         let mut coalesce = spaces.len() > 1;
         let pad_space = if self.side.is_client() && spaces.first() == Some(&SpaceId::Initial) {
             spaces.last().cloned()
@@ -494,7 +564,7 @@ where
             }
 
             buf.resize(buf.len() + packet_crypto.tag_len(), 0);
-            debug_assert!(buf.len() < self.mtu as usize);
+            debug_assert!(buf.len() <= max_size);
             let packet_buf = &mut buf[partial_encode.start..];
             partial_encode.finish(
                 packet_buf,
@@ -538,16 +608,7 @@ where
 
         trace!("sending {} byte datagram", buf.len());
         self.total_sent = self.total_sent.wrapping_add(buf.len() as u64);
-
-        Some(Transmit {
-            destination: self.path.remote,
-            contents: buf.into(),
-            ecn: if self.path.sending_ecn {
-                Some(EcnCodepoint::ECT0)
-            } else {
-                None
-            },
-        })
+        Some(buf)
     }
 
     /// Process `ConnectionEvent`s generated by the associated `Endpoint`
@@ -613,6 +674,14 @@ where
                     self.state = State::Drained;
                     self.endpoint_events.push_back(EndpointEventInner::Drained);
                 }
+                Timer::Handshake => {
+                    debug!("handshake timed out");
+                    self.close_common();
+                    self.events
+                        .push_back(ConnectionError::HandshakeTimedOut.into());
+                    self.state = State::Drained;
+                    self.endpoint_events.push_back(EndpointEventInner::Drained);
+                }
                 Timer::KeepAlive => {
                     trace!("sending keep-alive");
                     self.ping();
@@ -731,6 +800,23 @@ where
         Ok(())
     }
 
+    /// Set the priority of a send stream
+    ///
+    /// Pending data on streams with a higher priority is transmitted first, while streams of
+    /// equal priority take turns. Streams have a priority of 0 when opened.
+    ///
+    /// Because all streams share the default priority unless this is called, streams with data
+    /// pending are served in turn by default, one STREAM frame each, in the order they first
+    /// became pending. Earlier releases instead favoured the most recently written stream.
+    pub fn set_priority(&mut self, id: StreamId, priority: i32) -> Result<(), UnknownStream> {
+        self.streams.set_priority(id, priority)
+    }
+
+    /// Get the priority of a send stream
+    pub fn priority(&self, id: StreamId) -> Result<i32, UnknownStream> {
+        self.streams.priority(id)
+    }
+
     /// Finish a send stream, signalling that no more data will be sent.
     ///
     /// If this fails, no [`Event::StreamFinished`] will be generated.
@@ -788,14 +874,46 @@ where
     /// Not necessarily the maximum size of received datagrams.
     pub fn max_datagram_size(&self) -> Option<usize> {
         // This is usually 1182 bytes, but we shouldn't document that without a doctest.
-        let max_size = self.mtu as usize
+        let max_size = self.max_payload_size() - Datagram::SIZE_BOUND;
+        let limit = self.params.max_datagram_frame_size?.into_inner();
+        Some(limit.min(max_size as u64) as usize)
+    }
+
+    /// Queue an application-defined frame for transmission
+    ///
+    /// The frame's type must have been registered with `TransportConfig::frame_extension`, and the
+    /// encoded frame must fit in a single packet. Frames are delivered reliably if the extension
+    /// calls for retransmission, but may arrive in any order.
+    pub fn send_frame(&mut self, frame: ApplicationFrame) -> Result<(), SendFrameError> {
+        if self
+            .config
+            .frame_extensions
+            .get(frame.ty.into_inner())
+            .is_none()
+        {
+            return Err(SendFrameError::UnknownType);
+        }
+        if frame.size() > self.max_payload_size() {
+            return Err(SendFrameError::TooLarge);
+        }
+        self.outgoing_frames.push_back(frame);
+        Ok(())
+    }
+
+    /// Receive an application-defined frame
+    pub fn recv_frame(&mut self) -> Option<ApplicationFrame> {
+        let frame = self.incoming_frames.pop_front()?;
+        self.incoming_frames_size -= frame.size();
+        Some(frame)
+    }
+
+    /// Number of bytes available for frames in a 1-RTT packet
+    fn max_payload_size(&self) -> usize {
+        self.mtu as usize
             - 1                 // flags byte
             - self.rem_cid.len()
             - 4                 // worst-case packet number size
             - self.space(SpaceId::Data).crypto.as_ref().map_or_else(|| &self.zero_rtt_crypto.as_ref().unwrap().packet, |x| &x.packet.local).tag_len()
-            - Datagram::SIZE_BOUND;
-        let limit = self.params.max_datagram_frame_size?.into_inner();
-        Some(limit.min(max_size as u64) as usize)
     }
 
     /// Ping the remote endpoint
@@ -820,6 +938,19 @@ where
         self.streams.send_streams()
     }
 
+    /// Current best estimate of the round trip time to the peer
+    ///
+    /// Falls back to the configured initial RTT until a sample is taken.
+    pub fn rtt(&self) -> Duration {
+        self.path.rtt.smoothed.unwrap_or(self.config.initial_rtt)
+    }
+
+    /// The number of streams of a certain directionality that can be opened before the peer's
+    /// limit is reached
+    pub fn available_streams(&self, dir: Dir) -> u64 {
+        self.streams.available(dir)
+    }
+
     /// If the connection is currently handshaking
     pub fn is_handshaking(&self) -> bool {
         self.state.is_handshake()
@@ -852,11 +983,31 @@ where
         self.side
     }
 
+    /// The server name requested by the client, if any
+    ///
+    /// Known once the client's first handshake message is received in full, without waiting for
+    /// the handshake to progress. Always `None` for clients.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_ref().map(|x| &x[..])
+    }
+
     /// The latest socket address for this connection's peer
     pub fn remote_address(&self) -> SocketAddr {
         self.path.remote
     }
 
+    /// The value of an application-defined transport parameter sent by the peer
+    ///
+    /// `None` if the peer didn't send `id`, or its transport parameters have not been received
+    /// yet. See `TransportConfig::custom_transport_parameter`.
+    pub fn remote_transport_parameter(&self, id: VarInt) -> Option<&Bytes> {
+        let custom = &self.params.custom;
+        custom
+            .binary_search_by_key(&id, |x| x.0)
+            .ok()
+            .map(|i| &custom[i].1)
+    }
+
     fn on_packet_sent(
         &mut self,
         now: Instant,
@@ -1332,6 +1483,45 @@ where
         self.reset_inner(stream_id, error_code, false);
     }
 
+    /// Abandon transmitting data on a stream, except for the first `reliable_size` bytes
+    ///
+    /// The peer is guaranteed to receive the data preceding `reliable_size` before learning of the
+    /// reset. Requires the peer to support the reliable stream reset extension.
+    ///
+    /// A stream that was already finished or reset may be reset again, but the reliable size can
+    /// only ever be lowered; a larger `reliable_size` than previously set has no effect.
+    ///
+    /// # Panics
+    /// - when applied to a receive stream
+    pub fn reset_at(
+        &mut self,
+        stream_id: StreamId,
+        error_code: VarInt,
+        reliable_size: u64,
+    ) -> Result<(), ResetError> {
+        assert!(
+            stream_id.dir() == Dir::Bi || stream_id.initiator() == self.side,
+            "only streams supporting outgoing data may be reset"
+        );
+        if !self.params.reset_stream_at {
+            return Err(ResetError::UnsupportedByPeer);
+        }
+        let written = self
+            .streams
+            .send_offset(stream_id)
+            .ok_or(ResetError::UnknownStream)?;
+        if reliable_size > written {
+            return Err(ResetError::InvalidReliableSize);
+        }
+
+        self.streams.reset(stream_id, None, Some(reliable_size));
+        self.spaces[SpaceId::Data as usize]
+            .pending
+            .reset_stream
+            .push((stream_id, error_code));
+        Ok(())
+    }
+
     /// `stopped` should be set iff this is an internal implicit reset due to `STOP_SENDING`
     fn reset_inner(&mut self, stream_id: StreamId, error_code: VarInt, stopped: bool) {
         assert!(
@@ -1340,7 +1530,7 @@ where
         );
 
         let stop_reason = if stopped { Some(error_code) } else { None };
-        self.streams.reset(stream_id, stop_reason);
+        self.streams.reset(stream_id, stop_reason, None);
 
         self.spaces[SpaceId::Data as usize]
             .pending
@@ -1437,6 +1627,7 @@ where
             ));
         }
 
+        let space_id = space;
         let space = &mut self.spaces[space as usize];
         let max = space.crypto_stream.offset() + self.config.crypto_buffer_size as u64;
         if end > max {
@@ -1447,15 +1638,75 @@ where
             .insert(crypto.offset, crypto.data.clone());
         let mut buf = [0; 8192];
         loop {
+            let space = &mut self.spaces[space_id as usize];
             let n = space.crypto_stream.read(&mut buf);
             if n == 0 {
                 return Ok(());
             }
             trace!("read {} CRYPTO bytes", n);
+            if space_id == SpaceId::Initial {
+                if let Some(ref mut hello) = self.client_hello {
+                    hello.extend_from_slice(&buf[..n]);
+                    let len = match handshake_message_len(hello) {
+                        Some(len) if len > self.config.crypto_buffer_size => {
+                            return Err(TransportError::CRYPTO_BUFFER_EXCEEDED(""));
+                        }
+                        Some(len) if hello.len() >= len => len,
+                        _ => continue,
+                    };
+                    let hello = self.client_hello.take().unwrap();
+                    let config = self.server_config.as_ref().unwrap();
+                    let screen = config.anti_replay.is_some() || config.early_data_window.is_some();
+                    self.server_name = config
+                        .crypto
+                        .client_hello(&hello[..len])
+                        .and_then(|x| x.server_name);
+                    if screen {
+                        self.check_early_data(&hello[..len]);
+                    }
+                    self.crypto.read_handshake(&hello)?;
+                    continue;
+                }
+            }
             self.crypto.read_handshake(&buf[..n])?;
         }
     }
 
+    /// Decide whether to accept the client's 0-RTT data before its hello is processed
+    fn check_early_data(&mut self, client_hello: &[u8]) {
+        let config = self.server_config.as_ref().unwrap();
+        let request = match self.crypto.early_data_request(client_hello) {
+            Ok(Some(x)) => x,
+            Ok(None) => return,
+            Err(()) => {
+                debug!("rejecting 0-RTT: unable to parse client hello");
+                self.crypto.reject_early_data();
+                return;
+            }
+        };
+
+        let now = SystemTime::now();
+        let mut expires = request.ticket_expires;
+        if let Some(window) = config.early_data_window {
+            match request.ticket_issued {
+                Some(issued) if issued + window > now => {
+                    expires = expires.min(issued + window);
+                }
+                _ => {
+                    debug!("rejecting 0-RTT: resumption ticket is too old");
+                    self.crypto.reject_early_data();
+                    return;
+                }
+            }
+        }
+        if let Some(ref anti_replay) = config.anti_replay {
+            if !anti_replay.check(&request.id, now, expires) {
+                debug!("rejecting 0-RTT: possible replay");
+                self.crypto.reject_early_data();
+            }
+        }
+    }
+
     fn write_crypto(&mut self) {
         loop {
             let space = self.highest_space;
@@ -1676,7 +1927,7 @@ where
                 ConnectionError::ApplicationClosed(reason) => State::closed(reason),
                 ConnectionError::ConnectionClosed(reason) => State::closed(reason),
                 ConnectionError::Reset => State::Drained,
-                ConnectionError::TimedOut => {
+                ConnectionError::TimedOut | ConnectionError::HandshakeTimedOut => {
                     unreachable!("timeouts aren't generated by packet processing");
                 }
                 ConnectionError::TransportError(err) => {
@@ -1853,7 +2104,10 @@ where
                             self.discard_space(SpaceId::Handshake);
                         }
 
+                        self.timers.stop(Timer::Handshake);
                         self.events.push_back(Event::Connected);
+                        self.endpoint_events
+                            .push_back(EndpointEventInner::HandshakeCompleted);
                         self.state = State::Established;
                         trace!("established");
                         Ok(())
@@ -2003,7 +2257,7 @@ where
         let is_0rtt = self.space(SpaceId::Data).crypto.is_none();
         let mut is_probing_packet = true;
         let mut close = None;
-        for frame in frame::Iter::new(payload) {
+        for frame in frame::Iter::with_extensions(payload, &self.config.frame_extensions) {
             let span = match frame {
                 Frame::Padding => None,
                 _ => Some(trace_span!("frame", ty = %frame.ty())),
@@ -2024,6 +2278,7 @@ where
             // Check for ack-eliciting frames
             match frame {
                 Frame::Ack(_) | Frame::Padding | Frame::Close(_) => {}
+                Frame::Application(ref x) if !self.config.frame_extensions.ack_eliciting(x.ty) => {}
                 _ => {
                     self.space_mut(SpaceId::Data).permit_ack_only = true;
                 }
@@ -2238,6 +2493,20 @@ where
                     self.datagrams.recv_buffered += datagram.data.len();
                     self.datagrams.incoming.push_back(datagram);
                 }
+                Frame::Application(frame) => {
+                    if self.incoming_frames_size + frame.size()
+                        > self.config.frame_receive_buffer_size
+                    {
+                        return Err(TransportError::PROTOCOL_VIOLATION(
+                            "too much application frame data buffered",
+                        ));
+                    }
+                    if self.incoming_frames.is_empty() {
+                        self.events.push_back(Event::FrameReceived);
+                    }
+                    self.incoming_frames_size += frame.size();
+                    self.incoming_frames.push_back(frame);
+                }
                 Frame::HandshakeDone => {
                     if self.side.is_server() {
                         return Err(TransportError::PROTOCOL_VIOLATION(
@@ -2502,6 +2771,24 @@ where
             sent.retire_cids.push(seq);
         }
 
+        // Application-defined frames, retransmissions first
+        if space_id == SpaceId::Data {
+            let extensions = &self.config.frame_extensions;
+            for queue in [&mut space.pending.application, &mut self.outgoing_frames].iter_mut() {
+                while let Some(frame) = queue.pop_front() {
+                    if buf.len() + frame.size() > max_size {
+                        queue.push_front(frame);
+                        break;
+                    }
+                    trace!(ty = %frame.ty, len = frame.payload.len(), "application frame");
+                    frame.encode(buf);
+                    if extensions.retransmit(frame.ty) {
+                        sent.application.push_back(frame);
+                    }
+                }
+            }
+        }
+
         // DATAGRAM
         while buf.len() + Datagram::SIZE_BOUND < max_size && space_id == SpaceId::Data {
             let datagram = match self.datagrams.outgoing.pop_front() {
@@ -2533,6 +2820,7 @@ where
         for &timer in &Timer::VALUES {
             self.timers.stop(timer);
         }
+        self.endpoint_events.push_back(EndpointEventInner::Closed);
     }
 
     fn set_close_timer(&mut self, now: Instant) {
@@ -2763,6 +3051,10 @@ where
             || self.path_challenge_pending
             || self.path_response.is_some()
             || !self.datagrams.outgoing.is_empty()
+            || self
+                .outgoing_frames
+                .iter()
+                .any(|x| self.config.frame_extensions.ack_eliciting(x.ty))
     }
 
     /// Reset state to account for 0-RTT being ignored by the server
@@ -2828,7 +3120,10 @@ where
 }
 
 /// Reasons why a connection might be lost.
+///
+/// New reasons may be added in future releases, so matches must include a wildcard arm.
 #[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ConnectionError {
     /// The peer doesn't implement any supported version.
     #[error(display = "peer doesn't implement any supported version")]
@@ -2848,6 +3143,9 @@ pub enum ConnectionError {
     /// The peer has become unreachable.
     #[error(display = "timed out")]
     TimedOut,
+    /// The handshake did not complete within the configured `handshake_timeout`.
+    #[error(display = "handshake timed out")]
+    HandshakeTimedOut,
     /// The local application closed the connection.
     #[error(display = "closed")]
     LocallyClosed,
@@ -2867,7 +3165,7 @@ impl From<ConnectionError> for io::Error {
     fn from(x: ConnectionError) -> io::Error {
         use self::ConnectionError::*;
         let kind = match x {
-            TimedOut => io::ErrorKind::TimedOut,
+            TimedOut | HandshakeTimedOut => io::ErrorKind::TimedOut,
             Reset => io::ErrorKind::ConnectionReset,
             ApplicationClosed(_) | ConnectionClosed(_) => io::ErrorKind::ConnectionAborted,
             TransportError(_) | VersionMismatch | LocallyClosed => io::ErrorKind::Other,
@@ -3073,6 +3371,8 @@ pub enum Event {
     Stream(StreamEvent),
     /// One or more application datagrams have been received
     DatagramReceived,
+    /// One or more application-defined frames have been received
+    FrameReceived,
 }
 
 impl From<ConnectionError> for Event {
@@ -3087,6 +3387,14 @@ struct PathResponse {
     token: u64,
 }
 
+/// Length of the TLS handshake message at the start of `buf`, including its header, if known
+fn handshake_message_len(buf: &[u8]) -> Option<usize> {
+    match *buf {
+        [_, a, b, c, ..] => Some(4 + (usize::from(a) << 16 | usize::from(b) << 8 | usize::from(c))),
+        _ => None,
+    }
+}
+
 fn instant_saturating_sub(x: Instant, y: Instant) -> Duration {
     if x > y {
         x - y
@@ -3099,6 +3407,8 @@ fn instant_saturating_sub(x: Instant, y: Instant) -> Duration {
 const MAX_BACKOFF_EXPONENT: u32 = 16;
 // Minimal remaining size to allow packet coalescing
 const MIN_PACKET_SPACE: usize = 40;
+// Largest UDP payload that can be sent over IPv4, bounding the size of a segmented `Transmit`
+const MAX_SEGMENTED_TRANSMIT_SIZE: usize = 65_507;
 
 /// Description of a particular network path
 struct PathData {
@@ -3130,6 +3440,17 @@ pub enum SendDatagramError {
     TooLarge,
 }
 
+/// Errors that can arise when sending an application-defined frame
+#[derive(Debug, Error, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum SendFrameError {
+    /// No `FrameExtension` is registered for the frame's type
+    #[error(display = "unregistered frame type")]
+    UnknownType,
+    /// The frame is too large to fit in a packet
+    #[error(display = "frame too large")]
+    TooLarge,
+}
+
 struct DatagramState {
     /// Number of bytes of datagrams that have been received by the local transport but not
     /// delivered to the application
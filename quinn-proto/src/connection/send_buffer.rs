@@ -21,6 +21,8 @@ pub struct SendBuffer {
     acks: RangeSet,
     /// Previously transmitted ranges deemed lost
     retransmits: RangeSet,
+    /// Offset at which transmission stops, if the stream has been reset
+    truncated: Option<u64>,
 }
 
 impl SendBuffer {
@@ -59,8 +61,9 @@ impl SendBuffer {
         }
         // Transmit new data
         let end = self
-            .offset
-            .min((max_len as u64).saturating_add(self.unsent));
+            .end()
+            .min((max_len as u64).saturating_add(self.unsent))
+            .max(self.unsent);
         let result = self.unsent..end;
         self.unsent = end;
         result
@@ -76,7 +79,31 @@ impl SendBuffer {
     /// Queue a range of sent but unacknowledged data to be retransmitted
     pub fn retransmit(&mut self, range: Range<u64>) {
         debug_assert!(range.end <= self.unsent, "unsent data can't be lost");
-        self.retransmits.insert(range);
+        let end = range.end.min(self.end());
+        if range.start < end {
+            self.retransmits.insert(range.start..end);
+        }
+    }
+
+    /// Stop transmitting data at or beyond `end`, which must not exceed `offset`
+    ///
+    /// Returns the amount of unacknowledged data abandoned. Has no effect if the buffer has already
+    /// been truncated at a lower offset.
+    pub fn truncate(&mut self, end: u64) -> u64 {
+        debug_assert!(end <= self.offset);
+        let limit = self.end();
+        if end >= limit {
+            return 0;
+        }
+        self.truncated = Some(end);
+        self.retransmits.remove(end..limit);
+        let start = end.max(self.offset - self.unacked.len() as u64);
+        let acked = self
+            .acks
+            .iter()
+            .map(|x| x.end.min(limit).saturating_sub(x.start.max(start)))
+            .sum::<u64>();
+        limit.saturating_sub(start) - acked
     }
 
     pub fn retransmit_all_for_0rtt(&mut self) {
@@ -90,16 +117,23 @@ impl SendBuffer {
         self.offset
     }
 
-    /// Whether all sent data has been acknowledged
+    /// Offset past the last byte that will be transmitted
+    ///
+    /// Equal to `offset` unless the buffer was truncated.
+    pub fn end(&self) -> u64 {
+        self.truncated.unwrap_or(self.offset)
+    }
+
+    /// Whether all data that will be transmitted has been acknowledged
     pub fn is_fully_acked(&self) -> bool {
-        self.unacked.is_empty()
+        self.offset - self.unacked.len() as u64 >= self.end()
     }
 
     /// Whether there's data to send
     ///
     /// There may be sent unacknowledged data even when this is false.
     pub fn has_unsent_data(&self) -> bool {
-        self.unsent != self.offset || !self.retransmits.is_empty()
+        self.unsent < self.end() || !self.retransmits.is_empty()
     }
 
     /// Compute the amount of data that hasn't been acknowledged
@@ -160,4 +194,28 @@ mod tests {
         assert_eq!(buf.unacked, MSG[7..]);
         assert!(buf.acks.is_empty());
     }
+
+    #[test]
+    fn truncate() {
+        let mut buf = SendBuffer::new();
+        const MSG: &[u8] = b"Hello, world!";
+        buf.write(MSG);
+        assert_eq!(buf.poll_transmit(5), 0..5);
+        assert_eq!(buf.poll_transmit(5), 5..10);
+        buf.ack(7..10);
+        // Everything past offset 3 is abandoned, of which 5 bytes were sent but unacknowledged
+        assert_eq!(buf.truncate(3), MSG.len() as u64 - 3 - 3);
+        assert_eq!(buf.offset(), MSG.len() as u64);
+        assert!(!buf.has_unsent_data());
+        // Losses are only retransmitted up to the truncation point
+        buf.retransmit(0..5);
+        buf.retransmit(5..7);
+        assert_eq!(buf.poll_transmit(42), 0..3);
+        assert!(!buf.has_unsent_data());
+        assert!(!buf.is_fully_acked());
+        buf.ack(0..3);
+        assert!(buf.is_fully_acked());
+        // Truncating further only abandons what's left
+        assert_eq!(buf.truncate(0), 0);
+    }
 }
@@ -7,7 +7,8 @@ use std::{
 
 use super::assembler::Assembler;
 use crate::{
-    crypto, crypto::Keys, frame, range_set::RangeSet, shared::IssuedCid, StreamId, VarInt,
+    crypto, crypto::Keys, frame, range_set::RangeSet, shared::IssuedCid, ApplicationFrame,
+    StreamId, VarInt,
 };
 
 pub(crate) struct PacketSpace<S>
@@ -171,6 +172,7 @@ pub struct Retransmits {
     pub(crate) new_cids: Vec<IssuedCid>,
     pub(crate) retire_cids: Vec<u64>,
     pub(crate) handshake_done: bool,
+    pub(crate) application: VecDeque<ApplicationFrame>,
 }
 
 impl Retransmits {
@@ -185,6 +187,7 @@ impl Retransmits {
             && self.new_cids.is_empty()
             && self.retire_cids.is_empty()
             && !self.handshake_done
+            && self.application.is_empty()
     }
 }
 
@@ -201,6 +204,7 @@ impl Default for Retransmits {
             new_cids: Vec::new(),
             retire_cids: Vec::new(),
             handshake_done: false,
+            application: VecDeque::new(),
         }
     }
 }
@@ -221,6 +225,7 @@ impl ::std::ops::AddAssign for Retransmits {
         self.new_cids.extend(&rhs.new_cids);
         self.retire_cids.extend(rhs.retire_cids);
         self.handshake_done |= rhs.handshake_done;
+        self.application.extend(rhs.application);
     }
 }
 
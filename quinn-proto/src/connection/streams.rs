@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map, HashMap, VecDeque},
+    collections::{hash_map, BTreeMap, HashMap, VecDeque},
     mem,
 };
 
@@ -38,8 +38,10 @@ pub(crate) struct Streams {
     /// This differs from `self.send.len()` in that it does not include streams that the peer is
     /// permitted to open but which have not yet been opened.
     send_streams: usize,
-    /// Streams with outgoing data queued
-    pending: Vec<StreamId>,
+    /// Streams with outgoing data queued, by priority
+    ///
+    /// Each queue is served round-robin, so streams of equal priority share bandwidth fairly.
+    pending: BTreeMap<i32, VecDeque<StreamId>>,
 
     events: VecDeque<StreamEvent>,
     /// Streams blocked on connection-level flow control or stream window space
@@ -82,7 +84,7 @@ impl Streams {
             opened: [false, false],
             next_reported_remote: [0, 0],
             send_streams: 0,
-            pending: Vec::new(),
+            pending: BTreeMap::new(),
             events: VecDeque::new(),
             connection_blocked: Vec::new(),
             max_data: 0,
@@ -130,6 +132,10 @@ impl Streams {
         self.send_streams
     }
 
+    pub fn available(&self, dir: Dir) -> u64 {
+        self.max[dir as usize].saturating_sub(self.next[dir as usize])
+    }
+
     pub fn alloc_remote_stream(&mut self, params: &TransportParameters, dir: Dir) {
         self.max_remote[dir as usize] += 1;
         let id = StreamId::new(!self.side, dir, self.max_remote[dir as usize] - 1);
@@ -223,6 +229,7 @@ impl Streams {
         }
 
         let was_pending = stream.is_pending();
+        let priority = stream.priority;
         let len = (data.len() as u64).min(limit) as usize;
         let len = match stream.write(&data[0..len]) {
             Ok(n) => n,
@@ -236,7 +243,7 @@ impl Streams {
         self.unacked_data += len as u64;
         trace!(stream = %id, "wrote {} bytes", len);
         if !was_pending {
-            self.pending.push(id);
+            push_pending(&mut self.pending, id, priority);
         }
         Ok(len)
     }
@@ -258,7 +265,9 @@ impl Streams {
             }
         };
 
-        if rs.is_finished() {
+        let reliable =
+            matches!(rs.state, RecvState::ResetRecvd { .. }) && frame.offset < rs.reliable_size;
+        if rs.is_finished() && !reliable {
             trace!("dropping frame for finished stream");
             return Ok(());
         }
@@ -273,12 +282,13 @@ impl Streams {
         Ok(())
     }
 
-    /// Process incoming RESET_STREAM frame
+    /// Process incoming RESET_STREAM or RESET_STREAM_AT frame
     pub fn received_reset(&mut self, frame: frame::ResetStream) -> Result<bool, TransportError> {
         let frame::ResetStream {
             id,
             error_code,
             final_offset,
+            reliable_size,
         } = frame;
         self.validate_receive_id(id).map_err(|e| {
             debug!("received illegal RESET_STREAM frame");
@@ -306,19 +316,17 @@ impl Streams {
         }
 
         // State transition
-        rs.reset(error_code, final_offset);
-        let bytes_read = rs.bytes_read;
+        let was_receiving = !rs.is_finished();
+        let released = rs.reset(error_code, final_offset, reliable_size.unwrap_or(0));
         self.on_stream_frame(true, id);
 
         // Update flow control
-        Ok(if bytes_read != final_offset {
-            // bytes_read is always <= limit, so this won't underflow.
+        if was_receiving {
+            // limit is always <= final_offset, so this won't underflow.
             self.data_recvd += final_offset - limit;
-            self.local_max_data += final_offset - bytes_read;
-            true
-        } else {
-            false
-        })
+        }
+        self.local_max_data += released;
+        Ok(released != 0)
     }
 
     /// Set the FIN bit in the next stream frame, generating an empty one if necessary
@@ -327,31 +335,63 @@ impl Streams {
         let was_pending = stream.is_pending();
         stream.finish()?;
         if !was_pending {
-            self.pending.push(id);
+            push_pending(&mut self.pending, id, stream.priority);
+        }
+        Ok(())
+    }
+
+    /// Set the priority of a send stream, requeueing its pending data accordingly
+    pub fn set_priority(&mut self, id: StreamId, priority: i32) -> Result<(), UnknownStream> {
+        let stream = self
+            .send
+            .get_mut(&id)
+            .ok_or(UnknownStream { _private: () })?;
+        let old = mem::replace(&mut stream.priority, priority);
+        if old == priority || !stream.is_pending() {
+            return Ok(());
+        }
+        if let Some(queue) = self.pending.get_mut(&old) {
+            queue.retain(|&x| x != id);
+            if queue.is_empty() {
+                self.pending.remove(&old);
+            }
         }
+        push_pending(&mut self.pending, id, priority);
         Ok(())
     }
 
+    pub fn priority(&self, id: StreamId) -> Result<i32, UnknownStream> {
+        let stream = self.send.get(&id).ok_or(UnknownStream { _private: () })?;
+        Ok(stream.priority)
+    }
+
     /// Check if the peer has finished sending data
     pub fn is_peer_finished(&self, id: StreamId) -> Result<bool, UnknownStream> {
         let stream = self.recv.get(&id).ok_or(UnknownStream { _private: () })?;
         Ok(stream.is_finished())
     }
 
-    /// Abandon pending and future transmits
+    /// Abandon pending and future transmits, except for data before `reliable_size` if set
     ///
     /// Does not cause the actual RESET_STREAM frame to be sent, just updates internal
     /// state.
-    pub fn reset(&mut self, id: StreamId, stop_reason: Option<VarInt>) {
+    pub fn reset(&mut self, id: StreamId, stop_reason: Option<VarInt>, reliable_size: Option<u64>) {
         let stream = match self.send.get_mut(&id) {
             Some(ss) => ss,
             None => return,
         };
 
+        // A reliable size can only be reduced by a subsequent reset
+        stream.reliable_size = match (stream.is_reset(), stream.reliable_size, reliable_size) {
+            (true, None, _) | (_, _, None) => None,
+            (true, Some(old), Some(new)) => Some(old.min(new)),
+            (false, _, Some(new)) => Some(new),
+        };
+
         // Restore the portion of the send window consumed by the data that we aren't about to
         // send. We leave flow control alone because the peer's responsible for issuing additional
         // credit based on the final offset communicated in the RESET_STREAM frame we send.
-        self.unacked_data -= stream.pending.unacked();
+        self.unacked_data -= stream.pending.truncate(stream.reliable_size.unwrap_or(0));
         if let Some(event) = stream.reset(id, stop_reason) {
             self.events.push_back(event);
         }
@@ -371,6 +411,20 @@ impl Streams {
             }
         };
 
+        send.reset_acked = true;
+        self.maybe_finish_reset(id);
+    }
+
+    /// Complete a reset once the peer has acknowledged both the reset itself and any data it was
+    /// guaranteed to receive
+    fn maybe_finish_reset(&mut self, id: StreamId) {
+        let send = match self.send.get_mut(&id) {
+            Some(ss) => ss,
+            None => return,
+        };
+        if !send.reset_acked || !send.pending.is_fully_acked() {
+            return;
+        }
         if let SendState::ResetSent { stop_reason } = send.state {
             send.state = SendState::ResetRecvd { stop_reason };
             if stop_reason.is_none() {
@@ -379,6 +433,11 @@ impl Streams {
         }
     }
 
+    /// Amount of data written to a send stream, if it exists
+    pub fn send_offset(&self, id: StreamId) -> Option<u64> {
+        self.send.get(&id).map(|x| x.offset())
+    }
+
     pub fn can_send(&self) -> bool {
         !self.pending.is_empty()
     }
@@ -400,12 +459,13 @@ impl Streams {
                 Some(x) => x,
                 None => continue,
             };
-            trace!(stream = %id, "RESET_STREAM");
+            trace!(stream = %id, reliable_size = ?stream.reliable_size, "RESET_STREAM");
             sent.reset_stream.push((id, error_code));
             frame::ResetStream {
                 id,
                 error_code,
                 final_offset: stream.offset(),
+                reliable_size: stream.reliable_size,
             }
             .encode(buf);
         }
@@ -496,10 +556,14 @@ impl Streams {
                     Some(x) => x,
                     None => break,
                 };
-            let id = match self.pending.pop() {
-                Some(x) => x,
+            // Serve the highest priority first, and streams of equal priority in turn
+            let (priority, id) = match self.pending.iter_mut().next_back() {
+                Some((&priority, queue)) => (priority, queue.pop_front().unwrap()),
                 None => break,
             };
+            if self.pending[&priority].is_empty() {
+                self.pending.remove(&priority);
+            }
             let stream = match self.send.get_mut(&id) {
                 Some(s) => s,
                 // Stream was reset with pending data and the reset was acknowledged
@@ -507,9 +571,9 @@ impl Streams {
             };
 
             // Reset streams aren't removed from the pending list and still exist while the peer
-            // hasn't acknowledged the reset, but should not generate STREAM frames, so we need to
-            // check for them explicitly.
-            if stream.is_reset() {
+            // hasn't acknowledged the reset, but should not generate STREAM frames beyond their
+            // reliable size, so we need to check for them explicitly.
+            if stream.is_reset() && !stream.pending.has_unsent_data() {
                 continue;
             }
             let offsets = stream.pending.poll_transmit(max_data_len);
@@ -519,7 +583,7 @@ impl Streams {
                 stream.fin_pending = false;
             }
             if stream.is_pending() {
-                self.pending.push(id);
+                push_pending(&mut self.pending, id, stream.priority);
             }
 
             let meta = frame::StreamMeta { id, offsets, fin };
@@ -558,8 +622,14 @@ impl Streams {
             Some(x) => x,
         };
         let id = frame.id;
-        self.unacked_data -= frame.offsets.end - frame.offsets.start;
+        // Data abandoned by a reset was already removed from `unacked_data`
+        let end = frame.offsets.end.min(stream.pending.end());
+        self.unacked_data -= end.saturating_sub(frame.offsets.start);
         stream.ack(frame);
+        if stream.is_reset() {
+            self.maybe_finish_reset(id);
+            return;
+        }
         if stream.state != SendState::DataRecvd {
             return;
         }
@@ -578,7 +648,7 @@ impl Streams {
             Some(x) => x,
         };
         if !stream.is_pending() {
-            self.pending.push(frame.id);
+            push_pending(&mut self.pending, frame.id, stream.priority);
         }
         stream.fin_pending |= frame.fin;
         stream.pending.retransmit(frame.offsets);
@@ -595,7 +665,7 @@ impl Streams {
                     continue;
                 }
                 if !stream.is_pending() {
-                    self.pending.push(id);
+                    push_pending(&mut self.pending, id, stream.priority);
                 }
                 stream.pending.retransmit_all_for_0rtt();
             }
@@ -767,6 +837,13 @@ impl Streams {
     }
 }
 
+fn push_pending(pending: &mut BTreeMap<i32, VecDeque<StreamId>>, id: StreamId, priority: i32) {
+    pending
+        .entry(priority)
+        .or_insert_with(VecDeque::new)
+        .push_back(id);
+}
+
 #[derive(Debug)]
 struct Send {
     max_data: u64,
@@ -776,6 +853,12 @@ struct Send {
     fin_pending: bool,
     /// Whether this stream is in the `connection_blocked` list of `Streams`
     connection_blocked: bool,
+    /// Amount of data still delivered after a reset, if reset with RESET_STREAM_AT
+    reliable_size: Option<u64>,
+    /// Whether the peer acknowledged our reset
+    reset_acked: bool,
+    /// Streams with a higher priority are sent first
+    priority: i32,
 }
 
 impl Send {
@@ -786,6 +869,9 @@ impl Send {
             pending: SendBuffer::new(),
             fin_pending: false,
             connection_blocked: false,
+            reliable_size: None,
+            reset_acked: false,
+            priority: 0,
         }
     }
 
@@ -847,6 +933,8 @@ impl Send {
     /// If it is necessary to notify the application of the new state, return a `StreamEvent`.
     fn reset(&mut self, id: StreamId, stop_reason: Option<VarInt>) -> Option<StreamEvent> {
         use SendState::*;
+        // A reset replaces the FIN
+        self.fin_pending = false;
         let event = match self.state {
             DataRecvd | ResetSent { .. } | ResetRecvd { .. } => None,
             DataSent { .. } => {
@@ -942,6 +1030,8 @@ struct Recv {
     /// Number of bytes read by the application. Equal to assembler.offset when `unordered` is
     /// false.
     bytes_read: u64,
+    /// Amount of data delivered to the application despite the stream having been reset
+    reliable_size: u64,
 }
 
 impl Recv {
@@ -970,6 +1060,20 @@ impl Recv {
             }
         }
 
+        if let RecvState::ResetRecvd { .. } = self.state {
+            // Flow control was settled by the reset, so only retain data the application will
+            // still read
+            if frame.offset < self.reliable_size {
+                let len = (self.reliable_size - frame.offset).min(frame.data.len() as u64);
+                self.recvd.insert(frame.offset..frame.offset + len);
+                if len != 0 {
+                    self.assembler
+                        .insert(frame.offset, frame.data.slice(..len as usize));
+                }
+            }
+            return Ok(0);
+        }
+
         let prev_end = self.limit();
         let new_bytes = end.saturating_sub(prev_end);
         let stream_max_data = self.bytes_read + receive_window;
@@ -1027,10 +1131,11 @@ impl Recv {
 
     fn read_blocked(&mut self) -> Result<(), ReadError> {
         match self.state {
-            RecvState::ResetRecvd { error_code, .. } => {
+            RecvState::ResetRecvd { error_code, .. } if self.reliable_data_read() => {
                 self.state = RecvState::Closed;
                 Err(ReadError::Reset(error_code))
             }
+            RecvState::ResetRecvd { .. } => Err(ReadError::Blocked),
             RecvState::Closed => panic!("tried to read from a closed stream"),
             RecvState::Recv { .. } => Err(ReadError::Blocked),
             RecvState::DataRecvd { .. } => {
@@ -1040,6 +1145,19 @@ impl Recv {
         }
     }
 
+    /// Whether the application has read all data that precedes a reset
+    fn reliable_data_read(&self) -> bool {
+        if !self.unordered {
+            return self.assembler.offset() >= self.reliable_size;
+        }
+        self.assembler.is_empty()
+            && (self.reliable_size == 0
+                || self
+                    .recvd
+                    .peek_min()
+                    .map_or(false, |x| x.start == 0 && x.end >= self.reliable_size))
+    }
+
     fn receiving_unknown_size(&self) -> bool {
         match self.state {
             RecvState::Recv { size: None } => true,
@@ -1074,19 +1192,28 @@ impl Recv {
         }
     }
 
-    fn reset(&mut self, error_code: VarInt, final_offset: u64) {
-        if self.is_closed() {
-            return;
-        }
+    /// Returns the amount of flow control credit released by the reset
+    fn reset(&mut self, error_code: VarInt, final_offset: u64, reliable_size: u64) -> u64 {
+        // Offset up to which the application would have read, or been credited for, without this
+        // reset
+        let prev_end = match self.state {
+            RecvState::Closed => return 0,
+            // Later resets may only reduce the reliable size
+            RecvState::ResetRecvd { .. } if reliable_size >= self.reliable_size => return 0,
+            RecvState::ResetRecvd { .. } => self.reliable_size.max(self.bytes_read),
+            _ => final_offset,
+        };
         self.state = RecvState::ResetRecvd {
             size: final_offset,
             error_code,
         };
-        // Nuke buffers so that future reads fail immediately, which ensures future reads don't
-        // issue flow control credit redundant to that already issued. We could instead special-case
-        // reset streams during read, but it's unclear if there's any benefit to retaining data for
-        // reset streams.
-        self.assembler.clear();
+        self.reliable_size = reliable_size;
+        // Nuke buffers past the reliable size so that future reads fail once it's reached, which
+        // ensures future reads don't issue flow control credit redundant to that already issued.
+        // We could instead special-case reset streams during read, but it's unclear if there's any
+        // benefit to retaining data for reset streams.
+        self.assembler.truncate(reliable_size);
+        prev_end - reliable_size.max(self.bytes_read)
     }
 }
 
@@ -1174,6 +1301,20 @@ pub enum FinishError {
     UnknownStream,
 }
 
+/// Reasons why attempting to reset a stream with a reliable size might fail
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ResetError {
+    /// The peer did not advertise support for reliable stream resets
+    #[error(display = "unsupported by peer")]
+    UnsupportedByPeer,
+    /// The reliable size exceeds the amount of data written to the stream
+    #[error(display = "reliable size exceeds data written")]
+    InvalidReliableSize,
+    /// The stream has not yet been created, or has already been closed
+    #[error(display = "unknown stream")]
+    UnknownStream,
+}
+
 /// Application events about streams
 #[derive(Debug)]
 pub enum StreamEvent {
@@ -8,7 +8,7 @@
 //! Note that usage of any protocol (version) other than TLS 1.3 does not conform to any
 //! published versions of the specification, and will not be supported in QUIC v1.
 
-use std::str;
+use std::{str, time::SystemTime};
 
 use bytes::BytesMut;
 
@@ -17,6 +17,9 @@ use crate::{
     ConnectError, Side, TransportError,
 };
 
+/// Parsing of the client's first TLS handshake message
+#[cfg(feature = "rustls")]
+pub(crate) mod client_hello;
 /// Cryptography interface based on *ring*
 #[cfg(feature = "ring")]
 pub(crate) mod ring;
@@ -67,6 +70,18 @@ pub trait Session: Send + Sized {
     /// Returns `true` until the connection is fully established.
     fn is_handshaking(&self) -> bool;
 
+    /// Examine the client's first handshake message for an attempt to send 0-RTT data
+    ///
+    /// Only meaningful for servers. Returns `Ok(None)` if early data is not being offered, and
+    /// `Err(())` if `client_hello` does not contain a complete, well-formed message.
+    fn early_data_request(&self, client_hello: &[u8]) -> Result<Option<EarlyDataRequest>, ()>;
+
+    /// Refuse any 0-RTT data offered by the client
+    ///
+    /// Must be called before the client's first handshake message is passed to `read_handshake`.
+    /// Has no effect on clients.
+    fn reject_early_data(&mut self);
+
     /// Read bytes of handshake data
     ///
     /// This should be called with the contents of `CRYPTO` frames. If it returns `Ok`, the
@@ -95,6 +110,19 @@ pub trait Session: Send + Sized {
     fn is_valid_retry(orig_dst_cid: &ConnectionId, header: &[u8], payload: &[u8]) -> bool;
 }
 
+/// A client's attempt to send 0-RTT data, as seen by a server
+#[derive(Debug, Clone)]
+pub struct EarlyDataRequest {
+    /// Uniquely identifies the client's first handshake message
+    ///
+    /// For TLS, this is the binder of the pre-shared key being used.
+    pub id: Vec<u8>,
+    /// When the resumption ticket being used was issued, if known
+    pub ticket_issued: Option<SystemTime>,
+    /// Time after which the resumption ticket can no longer be used
+    pub ticket_expires: SystemTime,
+}
+
 /// A pair of keys for bidirectional communication
 pub struct KeyPair<T> {
     /// Key for encrypting data
@@ -144,6 +172,24 @@ where
 
     /// Start a server session with this configuration
     fn start_session(&self, params: &TransportParameters) -> S;
+
+    /// Extract the server name and application protocols from a client's first handshake message
+    ///
+    /// Returns `None` if `client_hello` does not begin with a complete, well-formed message. The
+    /// default implementation always returns `None`.
+    fn client_hello(&self, client_hello: &[u8]) -> Option<ClientHello> {
+        let _ = client_hello;
+        None
+    }
+}
+
+/// Information from a client's first handshake message
+#[derive(Debug, Clone, Default)]
+pub struct ClientHello {
+    /// The server name requested by the client, if any
+    pub server_name: Option<String>,
+    /// Application protocols offered by the client, in descending order of preference
+    pub alpn_protocols: Vec<Vec<u8>>,
 }
 
 /// Keys used to protect packet payloads
@@ -0,0 +1,147 @@
+//! Minimal parsing of TLS 1.3 ClientHello messages
+//!
+//! Servers inspect the client's first handshake message before handing it to the TLS
+//! implementation, e.g. to route on the requested server name or to screen 0-RTT attempts. Only
+//! the fields needed for that are decoded here, so the result doesn't depend on the internals of
+//! any particular TLS library.
+
+use std::str;
+
+/// Fields of interest from a client's first handshake message
+#[derive(Debug, Default)]
+pub(crate) struct ClientHello<'a> {
+    /// First host name from the `server_name` extension
+    pub server_name: Option<&'a str>,
+    /// Protocols from the `application_layer_protocol_negotiation` extension
+    pub alpn_protocols: Vec<&'a [u8]>,
+    /// Whether the `early_data` extension is present
+    pub early_data: bool,
+    /// Identities and binders from the `pre_shared_key` extension
+    pub psks: Vec<Psk<'a>>,
+}
+
+/// A pre-shared key offered by the client
+#[derive(Debug)]
+pub(crate) struct Psk<'a> {
+    /// Opaque label for the key, e.g. an encrypted resumption ticket
+    pub identity: &'a [u8],
+    /// HMAC over the hello proving possession of the key
+    pub binder: &'a [u8],
+}
+
+impl<'a> ClientHello<'a> {
+    /// Parse a ClientHello from the start of `buf`, ignoring any subsequent data
+    ///
+    /// Returns `None` if `buf` does not begin with a complete, well-formed ClientHello.
+    pub fn parse(mut buf: &'a [u8]) -> Option<Self> {
+        if u8(&mut buf)? != CLIENT_HELLO {
+            return None;
+        }
+        let len = u24(&mut buf)?;
+        let mut body = bytes(&mut buf, len)?;
+        let buf = &mut body;
+
+        bytes(buf, 2 + 32)?; // legacy_version, random
+        vec8(buf)?; // legacy_session_id
+        vec16(buf)?; // cipher_suites
+        vec8(buf)?; // legacy_compression_methods
+        let mut extensions = vec16(buf)?;
+
+        let mut hello = Self::default();
+        while !extensions.is_empty() {
+            let ty = u16(&mut extensions)?;
+            let mut data = vec16(&mut extensions)?;
+            match ty {
+                SERVER_NAME => hello.server_name = server_name(&mut data)?,
+                ALPN => {
+                    let mut list = vec16(&mut data)?;
+                    while !list.is_empty() {
+                        hello.alpn_protocols.push(vec8(&mut list)?);
+                    }
+                }
+                EARLY_DATA => hello.early_data = true,
+                PRE_SHARED_KEY => hello.psks = psks(&mut data)?,
+                _ => {}
+            }
+        }
+        Some(hello)
+    }
+}
+
+fn server_name<'a>(buf: &mut &'a [u8]) -> Option<Option<&'a str>> {
+    let mut list = vec16(buf)?;
+    while !list.is_empty() {
+        let ty = u8(&mut list)?;
+        let name = vec16(&mut list)?;
+        if ty == HOST_NAME {
+            return str::from_utf8(name).ok().map(Some);
+        }
+    }
+    Some(None)
+}
+
+fn psks<'a>(buf: &mut &'a [u8]) -> Option<Vec<Psk<'a>>> {
+    let mut identities = vec16(buf)?;
+    let mut binders = vec16(buf)?;
+    let mut psks = Vec::new();
+    while !identities.is_empty() {
+        let identity = vec16(&mut identities)?;
+        u32(&mut identities)?; // obfuscated_ticket_age
+        let binder = vec8(&mut binders)?;
+        psks.push(Psk { identity, binder });
+    }
+    if !binders.is_empty() {
+        return None;
+    }
+    Some(psks)
+}
+
+fn bytes<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if buf.len() < n {
+        return None;
+    }
+    let (x, rest) = buf.split_at(n);
+    *buf = rest;
+    Some(x)
+}
+
+fn u8(buf: &mut &[u8]) -> Option<u8> {
+    Some(bytes(buf, 1)?[0])
+}
+
+fn u16(buf: &mut &[u8]) -> Option<u16> {
+    let x = bytes(buf, 2)?;
+    Some(u16::from_be_bytes([x[0], x[1]]))
+}
+
+fn u24(buf: &mut &[u8]) -> Option<usize> {
+    let x = bytes(buf, 3)?;
+    Some(usize::from(x[0]) << 16 | usize::from(x[1]) << 8 | usize::from(x[2]))
+}
+
+fn u32(buf: &mut &[u8]) -> Option<u32> {
+    let x = bytes(buf, 4)?;
+    Some(u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+}
+
+fn vec8<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let n = u8(buf)?;
+    bytes(buf, n.into())
+}
+
+fn vec16<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let n = u16(buf)?;
+    bytes(buf, n.into())
+}
+
+// Handshake message type of a ClientHello
+const CLIENT_HELLO: u8 = 1;
+
+// Extension types
+const SERVER_NAME: u16 = 0;
+const ALPN: u16 = 16;
+const PRE_SHARED_KEY: u16 = 41;
+const EARLY_DATA: u16 = 42;
+
+// `NameType` of a DNS host name in the `server_name` extension
+const HOST_NAME: u8 = 0;
@@ -1,8 +1,10 @@
 use std::{
+    collections::HashMap,
     io,
     ops::{Deref, DerefMut},
     str,
-    sync::Arc,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use bytes::BytesMut;
@@ -11,15 +13,17 @@ pub use rustls::TLSError;
 use rustls::{
     self,
     quic::{ClientQuicExt, PacketKey, ServerQuicExt},
-    Session,
+    sign::{self, CertifiedKey},
+    ProducesTickets, Session, StoresServerSessions,
 };
 use webpki::DNSNameRef;
 
 use crate::{
     crypto,
-    crypto::{KeyPair, Keys},
+    crypto::{client_hello::ClientHello, EarlyDataRequest, KeyPair, Keys},
     transport_parameters::TransportParameters,
-    CertificateChain, ConnectError, ConnectionId, Side, TransportError, TransportErrorCode,
+    CertificateChain, ConnectError, ConnectionId, MemorySessionStore, PrivateKey, SessionStore,
+    Side, TransportError, TransportErrorCode,
 };
 
 /// A rustls TLS session
@@ -29,14 +33,14 @@ pub struct TlsSession {
 
 enum SessionKind {
     Client(rustls::ClientSession),
-    Server(rustls::ServerSession),
+    Server(rustls::ServerSession, Arc<rustls::ServerConfig>),
 }
 
 impl TlsSession {
     fn side(&self) -> Side {
         match self.inner {
             SessionKind::Client(_) => Side::Client,
-            SessionKind::Server(_) => Side::Server,
+            SessionKind::Server(..) => Side::Server,
         }
     }
 }
@@ -75,7 +79,7 @@ impl crypto::Session for TlsSession {
             protocol: self.get_alpn_protocol().map(|p| p.into()),
             server_name: match self.inner {
                 SessionKind::Client(_) => None,
-                SessionKind::Server(ref session) => session.get_sni_hostname().map(|s| s.into()),
+                SessionKind::Server(ref session, _) => session.get_sni_hostname().map(|s| s.into()),
             },
         }
     }
@@ -95,7 +99,54 @@ impl crypto::Session for TlsSession {
     fn is_handshaking(&self) -> bool {
         match self.inner {
             SessionKind::Client(ref session) => session.is_handshaking(),
-            SessionKind::Server(ref session) => session.is_handshaking(),
+            SessionKind::Server(ref session, _) => session.is_handshaking(),
+        }
+    }
+
+    fn early_data_request(&self, client_hello: &[u8]) -> Result<Option<EarlyDataRequest>, ()> {
+        let config = match self.inner {
+            SessionKind::Client(_) => return Ok(None),
+            SessionKind::Server(_, ref config) => config,
+        };
+        let hello = ClientHello::parse(client_hello).ok_or(())?;
+        if !hello.early_data {
+            return Ok(None);
+        }
+
+        // Find the resumption ticket rustls will select, mirroring its lookup. Its issue time is
+        // read from what `start_session` stored next to it, without decrypting it.
+        for psk in &hello.psks {
+            let (issued, lifetime) = if config.ticketer.enabled() {
+                (
+                    read_issue_time(psk.identity),
+                    Duration::from_secs(config.ticketer.get_lifetime().into()),
+                )
+            } else {
+                // Stateful sessions are removed from storage when they're resumed
+                (
+                    config
+                        .session_storage
+                        .get(psk.identity)
+                        .and_then(|x| read_issue_time(&x)),
+                    Duration::from_secs(0),
+                )
+            };
+            let issued = match issued {
+                Some(x) => x,
+                None => continue,
+            };
+            return Ok(Some(EarlyDataRequest {
+                id: psk.binder.to_vec(),
+                ticket_issued: Some(issued),
+                ticket_expires: issued + lifetime,
+            }));
+        }
+        Ok(None)
+    }
+
+    fn reject_early_data(&mut self) {
+        if let SessionKind::Server(ref mut session, _) = self.inner {
+            session.reject_early_data();
         }
     }
 
@@ -193,7 +244,7 @@ impl Deref for TlsSession {
     fn deref(&self) -> &Self::Target {
         match self.inner {
             SessionKind::Client(ref session) => session,
-            SessionKind::Server(ref session) => session,
+            SessionKind::Server(ref session, _) => session,
         }
     }
 }
@@ -202,7 +253,7 @@ impl DerefMut for TlsSession {
     fn deref_mut(&mut self) -> &mut (dyn rustls::Session + 'static) {
         match self.inner {
             SessionKind::Client(ref mut session) => session,
-            SessionKind::Server(ref mut session) => session,
+            SessionKind::Server(ref mut session, _) => session,
         }
     }
 }
@@ -234,11 +285,121 @@ pub struct AuthenticationData {
     pub server_name: Option<String>,
 }
 
+/// Selects the certificate presented to each client by the server name it requested
+///
+/// Certificates may be added or removed at any time, taking effect for subsequent handshakes.
+/// Clients which request an unknown name, or which don't use SNI, are presented with the default
+/// certificate if one is set, and fail the handshake otherwise. Names are matched exactly, ignoring
+/// case.
+#[derive(Default)]
+pub struct SniResolver {
+    state: RwLock<SniState>,
+}
+
+#[derive(Default)]
+struct SniState {
+    by_name: HashMap<String, CertifiedKey>,
+    default: Option<CertifiedKey>,
+}
+
+impl SniResolver {
+    /// Construct a resolver with no certificates
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Present `cert_chain` to clients requesting `name`, replacing any previous certificate
+    ///
+    /// Fails if `name` is not a valid DNS name, or the certificate is not valid for it.
+    pub fn insert(
+        &self,
+        name: &str,
+        cert_chain: CertificateChain,
+        key: PrivateKey,
+    ) -> Result<(), TLSError> {
+        let dns_name = DNSNameRef::try_from_ascii_str(name)
+            .map_err(|_| TLSError::General(format!("invalid DNS name: {}", name)))?;
+        let key = certified_key(cert_chain, key)?;
+        key.cross_check_end_entity_cert(Some(dns_name))?;
+        self.state
+            .write()
+            .unwrap()
+            .by_name
+            .insert(name.to_ascii_lowercase(), key);
+        Ok(())
+    }
+
+    /// Stop presenting a specific certificate to clients requesting `name`
+    ///
+    /// Returns whether a certificate was registered for `name`.
+    pub fn remove(&self, name: &str) -> bool {
+        self.state
+            .write()
+            .unwrap()
+            .by_name
+            .remove(&name.to_ascii_lowercase())
+            .is_some()
+    }
+
+    /// Set the certificate presented when no other certificate matches the requested name
+    pub fn set_default(
+        &self,
+        cert_chain: CertificateChain,
+        key: PrivateKey,
+    ) -> Result<(), TLSError> {
+        let key = certified_key(cert_chain, key)?;
+        key.cross_check_end_entity_cert(None)?;
+        self.state.write().unwrap().default = Some(key);
+        Ok(())
+    }
+
+    /// Remove the default certificate, failing handshakes that don't match a specific name
+    pub fn clear_default(&self) {
+        self.state.write().unwrap().default = None;
+    }
+}
+
+impl rustls::ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: rustls::ClientHello) -> Option<CertifiedKey> {
+        let state = self.state.read().unwrap();
+        client_hello
+            .server_name()
+            .and_then(|name| {
+                let name: &str = name.into();
+                state.by_name.get(&name.to_ascii_lowercase())
+            })
+            .or_else(|| state.default.as_ref())
+            .cloned()
+    }
+}
+
+fn certified_key(cert_chain: CertificateChain, key: PrivateKey) -> Result<CertifiedKey, TLSError> {
+    let key = sign::any_supported_type(&key.inner)
+        .map_err(|()| TLSError::General("unsupported private key type".into()))?;
+    Ok(CertifiedKey::new(cert_chain.certs, Arc::new(key)))
+}
+
+/// Exposes a `SessionStore` as a rustls client session cache
+pub(crate) struct SessionStoreAdapter(pub(crate) Arc<dyn SessionStore>);
+
+impl rustls::StoresClientSessions for SessionStoreAdapter {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.0.put(key, value)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+}
+
 impl crypto::ClientConfig<TlsSession> for Arc<rustls::ClientConfig> {
     fn new() -> Self {
         let mut cfg = rustls::ClientConfig::new();
         cfg.versions = vec![rustls::ProtocolVersion::TLSv1_3];
         cfg.enable_early_data = true;
+        cfg.set_persistence(Arc::new(SessionStoreAdapter(Arc::new(
+            MemorySessionStore::new(32),
+        ))));
         #[cfg(feature = "native-certs")]
         match rustls_native_certs::load_native_certs() {
             Ok(x) => {
@@ -285,12 +446,108 @@ impl crypto::ServerConfig<TlsSession> for Arc<rustls::ServerConfig> {
     }
 
     fn start_session(&self, params: &TransportParameters) -> TlsSession {
+        // Record when resumption tickets are issued so the age of 0-RTT attempts can be checked
+        let mut config = (**self).clone();
+        config.ticketer = Arc::new(TimestampedTickets(self.ticketer.clone()));
+        config.session_storage = Arc::new(TimestampedSessions(self.session_storage.clone()));
+        let session = rustls::ServerSession::new_quic(&Arc::new(config), to_vec(params));
         TlsSession {
-            inner: SessionKind::Server(rustls::ServerSession::new_quic(self, to_vec(params))),
+            inner: SessionKind::Server(session, self.clone()),
         }
     }
+
+    fn client_hello(&self, client_hello: &[u8]) -> Option<crypto::ClientHello> {
+        let hello = ClientHello::parse(client_hello)?;
+        Some(crypto::ClientHello {
+            server_name: hello.server_name.map(|x| x.into()),
+            alpn_protocols: hello.alpn_protocols.iter().map(|x| x.to_vec()).collect(),
+        })
+    }
 }
 
+/// Prefixes tickets with their issue time, which is also encrypted to authenticate it
+///
+/// The time can then be read from a ClientHello before it is handed to rustls. A ticket whose
+/// prefix was tampered with fails to decrypt, so its 0-RTT data is rejected anyway.
+struct TimestampedTickets(Arc<dyn ProducesTickets>);
+
+impl ProducesTickets for TimestampedTickets {
+    fn enabled(&self) -> bool {
+        self.0.enabled()
+    }
+
+    fn get_lifetime(&self) -> u32 {
+        self.0.get_lifetime()
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let issued = issue_time();
+        let mut stamped = issued.to_vec();
+        stamped.extend_from_slice(plain);
+        let mut ticket = issued.to_vec();
+        ticket.extend_from_slice(&self.0.encrypt(&stamped)?);
+        Some(ticket)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        if cipher.len() < ISSUE_TIME_LEN {
+            return None;
+        }
+        let (issued, cipher) = cipher.split_at(ISSUE_TIME_LEN);
+        let mut plain = self.0.decrypt(cipher)?;
+        if plain.len() < ISSUE_TIME_LEN || plain[..ISSUE_TIME_LEN] != *issued {
+            return None;
+        }
+        plain.drain(..ISSUE_TIME_LEN);
+        Some(plain)
+    }
+}
+
+/// Stores sessions along with their issue time
+struct TimestampedSessions(Arc<dyn StoresServerSessions>);
+
+impl StoresServerSessions for TimestampedSessions {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        let mut stamped = issue_time().to_vec();
+        stamped.extend_from_slice(&value);
+        self.0.put(key, stamped)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key).and_then(strip_issue_time)
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.take(key).and_then(strip_issue_time)
+    }
+}
+
+fn issue_time() -> [u8; ISSUE_TIME_LEN] {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+        .to_be_bytes()
+}
+
+fn read_issue_time(stamped: &[u8]) -> Option<SystemTime> {
+    let mut secs = [0; ISSUE_TIME_LEN];
+    if stamped.len() < secs.len() {
+        return None;
+    }
+    secs.copy_from_slice(&stamped[..ISSUE_TIME_LEN]);
+    Some(UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(secs)))
+}
+
+fn strip_issue_time(mut stamped: Vec<u8>) -> Option<Vec<u8>> {
+    if stamped.len() < ISSUE_TIME_LEN {
+        return None;
+    }
+    stamped.drain(..ISSUE_TIME_LEN);
+    Some(stamped)
+}
+
+const ISSUE_TIME_LEN: usize = 8;
+
 fn to_vec(params: &TransportParameters) -> Vec<u8> {
     let mut bytes = Vec::new();
     params.write(&mut bytes);
@@ -2,7 +2,7 @@ use std::{
     collections::{HashMap, VecDeque},
     convert::TryFrom,
     fmt, iter,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     ops::{Index, IndexMut},
     sync::Arc,
     time::{Duration, Instant, SystemTime},
@@ -22,13 +22,15 @@ use crate::{
         self, ClientConfig as ClientCryptoConfig, Keys, PacketKey,
         ServerConfig as ServerCryptoConfig,
     },
+    frame::{self, Frame},
     packet::{Header, Packet, PacketDecodeError, PartialDecode},
     shared::{
         ConnectionEvent, ConnectionEventInner, ConnectionId, EcnCodepoint, EndpointEvent,
         EndpointEventInner, IssuedCid, ResetToken,
     },
     transport_parameters::TransportParameters,
-    Side, Transmit, TransportError, MAX_CID_SIZE, MIN_INITIAL_SIZE, RESET_TOKEN_SIZE, VERSION,
+    ConnectionAttempt, FilterAction, Side, Transmit, TransportError, MAX_CID_SIZE,
+    MIN_INITIAL_SIZE, RESET_TOKEN_SIZE, VERSION,
 };
 
 /// The main entry point to the library
@@ -55,6 +57,13 @@ where
     config: Arc<EndpointConfig<S>>,
     server_config: Option<Arc<ServerConfig<S>>>,
     incoming_handshakes: usize,
+    /// Number of incoming connections whose handshake has not yet completed
+    pending_handshakes: usize,
+    /// Number of incoming connections that have not begun closing
+    live_incoming: usize,
+    /// Recent connection attempts from each remote IP, for `ServerConfig::max_handshakes_per_ip`
+    handshake_rates: HandshakeRateTable,
+    stats: EndpointStats,
     /// Whether incoming connections should be unconditionally rejected by a server
     ///
     /// Equivalent to a `ServerConfig.accept_buffer` of `0`, but can be changed after the endpoint is constructed.
@@ -81,6 +90,10 @@ where
             connection_reset_tokens: ResetTokenTable::default(),
             connections: Slab::new(),
             incoming_handshakes: 0,
+            pending_handshakes: 0,
+            live_incoming: 0,
+            handshake_rates: HandshakeRateTable::default(),
+            stats: EndpointStats::default(),
             reject_new_connections: false,
             config,
             server_config,
@@ -93,7 +106,9 @@ where
 
     /// Get the next packet to transmit
     pub fn poll_transmit(&mut self) -> Option<Transmit> {
-        self.transmits.pop_front()
+        let x = self.transmits.pop_front()?;
+        self.stats.stateless_datagrams_sent += 1;
+        Some(x)
     }
 
     /// Process `EndpointEvent`s emitted from related `Connection`s
@@ -124,7 +139,19 @@ where
                     return Some(self.send_new_identifiers(ch, 1));
                 }
             }
+            HandshakeCompleted => {
+                if self.handshake_finished(ch) {
+                    self.stats.handshakes_completed += 1;
+                }
+            }
+            Closed => {
+                self.incoming_closed(ch);
+            }
             Drained => {
+                if self.handshake_finished(ch) {
+                    self.stats.handshakes_failed += 1;
+                }
+                self.incoming_closed(ch);
                 let conn = self.connections.remove(ch.0);
                 if conn.init_cid.len() > 0 {
                     self.connection_ids_initial.remove(&conn.init_cid);
@@ -141,6 +168,26 @@ where
         None
     }
 
+    /// Stop tracking an incoming handshake, returning whether it was in progress
+    fn handshake_finished(&mut self, ch: ConnectionHandle) -> bool {
+        let meta = &mut self.connections[ch];
+        if !meta.handshaking {
+            return false;
+        }
+        meta.handshaking = false;
+        self.pending_handshakes -= 1;
+        true
+    }
+
+    /// Stop counting an incoming connection towards `ServerConfig::concurrent_connections`
+    fn incoming_closed(&mut self, ch: ConnectionHandle) {
+        let meta = &mut self.connections[ch];
+        if meta.live_incoming {
+            meta.live_incoming = false;
+            self.live_incoming -= 1;
+        }
+    }
+
     /// Process an incoming UDP datagram
     pub fn handle(
         &mut self,
@@ -149,6 +196,7 @@ where
         ecn: Option<EcnCodepoint>,
         data: BytesMut,
     ) -> Option<(ConnectionHandle, DatagramEvent<S>)> {
+        self.stats.datagrams_received += 1;
         let datagram_len = data.len();
         let (first_decode, remaining) = match PartialDecode::new(data, self.config.local_cid_len) {
             Ok(x) => x,
@@ -158,8 +206,10 @@ where
             }) => {
                 if !self.is_server() {
                     debug!("dropping packet with unsupported version");
+                    self.stats.dropped_unsupported_version += 1;
                     return None;
                 }
+                self.stats.version_negotiations_sent += 1;
                 trace!("sending version negotiation");
                 // Negotiate versions
                 let mut buf = Vec::<u8>::new();
@@ -175,11 +225,13 @@ where
                     destination: remote,
                     ecn: None,
                     contents: buf.into(),
+                    segment_size: None,
                 });
                 return None;
             }
             Err(e) => {
                 trace!("malformed header: {}", e);
+                self.stats.dropped_malformed += 1;
                 return None;
             }
         };
@@ -248,10 +300,12 @@ where
                     "ignoring non-initial packet for unknown connection {}",
                     dst_cid
                 );
+                self.stats.dropped_unknown_connection += 1;
                 return None;
             }
             if datagram_len < MIN_INITIAL_SIZE {
                 debug!("ignoring short initial for connection {}", dst_cid);
+                self.stats.invalid_initials += 1;
                 return None;
             }
 
@@ -262,6 +316,7 @@ where
                     .map(|(ch, conn)| (ch, DatagramEvent::NewConnection(conn))),
                 Err(e) => {
                     trace!("unable to decode initial packet: {}", e);
+                    self.stats.invalid_initials += 1;
                     None
                 }
             };
@@ -276,6 +331,7 @@ where
             self.stateless_reset(datagram_len, remote, &dst_cid);
         } else {
             trace!("dropping unrecognized short packet without ID");
+            self.stats.dropped_unknown_connection += 1;
         }
         None
     }
@@ -295,11 +351,13 @@ where
             Some(headroom) if headroom > MIN_PADDING_LEN => headroom - 1,
             _ => {
                 debug!("ignoring unexpected {} byte packet: not larger than minimum stateless reset size", inciting_dgram_len);
+                self.stats.dropped_unknown_connection += 1;
                 return;
             }
         };
 
         debug!("sending stateless reset for {} to {}", dst_cid, remote);
+        self.stats.stateless_resets_sent += 1;
         let mut buf = Vec::<u8>::new();
         // Resets with at least this much padding can't possibly be distinguished from real packets
         const IDEAL_MIN_PADDING_LEN: usize = MIN_PADDING_LEN + MAX_CID_SIZE;
@@ -320,6 +378,7 @@ where
             destination: remote,
             ecn: None,
             contents: buf.into(),
+            segment_size: None,
         });
     }
 
@@ -385,7 +444,7 @@ where
         now: Instant,
     ) -> Result<(ConnectionHandle, Connection<S>), ConnectError> {
         let loc_cid = self.new_cid();
-        let (server_config, tls, transport_config) = match opts {
+        let (server_config, tls, transport_config, remote_validated) = match opts {
             ConnectionOpts::Client {
                 config,
                 server_name,
@@ -395,6 +454,7 @@ where
                     None,
                     config.crypto.start_session(&server_name, &params)?,
                     config.transport,
+                    false,
                 )
             }
             ConnectionOpts::Server { orig_dst_cid } => {
@@ -410,6 +470,8 @@ where
                     Some(config.clone()),
                     config.crypto.start_session(&server_params),
                     config.transport.clone(),
+                    // A retry token proves the client can receive packets at this address
+                    orig_dst_cid.is_some(),
                 )
             }
         };
@@ -422,6 +484,7 @@ where
             loc_cid,
             rem_cid,
             remote,
+            remote_validated,
             tls,
             now,
         );
@@ -431,6 +494,8 @@ where
             loc_cids: iter::once((0, loc_cid)).collect(),
             initial_remote: remote,
             reset_token: None,
+            handshaking: false,
+            live_incoming: false,
         });
         let ch = ConnectionHandle(id);
 
@@ -473,11 +538,13 @@ where
             .is_err()
         {
             debug!(packet_number, "failed to authenticate initial packet");
+            self.stats.invalid_initials += 1;
             return None;
         };
 
         if !packet.reserved_bits_valid() {
             debug!("dropping connection attempt with invalid reserved bits");
+            self.stats.invalid_initials += 1;
             return None;
         }
 
@@ -490,6 +557,7 @@ where
             || self.is_full()
         {
             debug!("rejecting connection due to full accept buffer");
+            self.stats.connections_refused += 1;
             self.transmits.push_back(Transmit {
                 destination: remote,
                 ecn: None,
@@ -498,19 +566,45 @@ where
                     &src_cid,
                     &temp_loc_cid,
                     0,
-                    TransportError::SERVER_BUSY(""),
+                    TransportError::CONNECTION_REFUSED(""),
                 ),
+                segment_size: None,
             });
             return None;
         }
 
-        if dst_cid.len() < 8
-            && (!server_config.use_stateless_retry || dst_cid.len() != self.config.local_cid_len)
-        {
+        if self.live_incoming >= server_config.concurrent_connections as usize {
+            debug!("rejecting connection due to concurrent connection limit");
+            self.stats.connections_refused += 1;
+            self.transmits.push_back(Transmit {
+                destination: remote,
+                ecn: None,
+                contents: initial_close(
+                    crypto,
+                    &src_cid,
+                    &temp_loc_cid,
+                    0,
+                    TransportError::CONNECTION_REFUSED(""),
+                ),
+                segment_size: None,
+            });
+            return None;
+        }
+
+        let retry_enabled = server_config.use_stateless_retry
+            || server_config.retry_threshold.is_some()
+            || server_config.incoming_filter.is_some();
+        let retry_required = server_config.use_stateless_retry
+            || server_config
+                .retry_threshold
+                .map_or(false, |x| self.pending_handshakes >= x as usize);
+
+        if dst_cid.len() < 8 && (!retry_enabled || dst_cid.len() != self.config.local_cid_len) {
             debug!(
                 "rejecting connection due to invalid DCID length {}",
                 dst_cid.len()
             );
+            self.stats.invalid_initials += 1;
             self.transmits.push_back(Transmit {
                 destination: remote,
                 ecn: None,
@@ -521,49 +615,22 @@ where
                     0,
                     TransportError::PROTOCOL_VIOLATION("invalid destination CID length"),
                 ),
+                segment_size: None,
             });
             return None;
         }
 
-        let retry_cid = if server_config.use_stateless_retry {
-            if token.is_empty() {
-                // First Initial
-                let token = token::generate(
-                    &*server_config.token_key,
-                    &remote,
-                    &dst_cid,
-                    SystemTime::now(),
-                );
-                let mut buf = Vec::new();
-                let header = Header::Retry {
-                    src_cid: temp_loc_cid,
-                    dst_cid: src_cid,
-                };
-                let encode = header.encode(&mut buf);
-                buf.put_slice(&token);
-                buf.extend_from_slice(&S::retry_tag(&dst_cid, &buf));
-                encode.finish::<S::PacketKey, S::HeaderKey>(&mut buf, &crypto.header.local, None);
-
-                self.transmits.push_back(Transmit {
-                    destination: remote,
-                    ecn: None,
-                    contents: buf.into(),
-                });
-                return None;
-            }
-
+        let retry_cid = if retry_enabled && !token.is_empty() {
             match token::check(&*server_config.token_key, &remote, &token) {
                 Some((cid, issued))
-                    if issued
-                        + Duration::from_micros(
-                            self.server_config.as_ref().unwrap().retry_token_lifetime,
-                        )
+                    if issued + Duration::from_micros(server_config.retry_token_lifetime)
                         > SystemTime::now() =>
                 {
                     Some(cid)
                 }
                 _ => {
                     debug!("rejecting invalid stateless retry token");
+                    self.stats.invalid_tokens += 1;
                     self.transmits.push_back(Transmit {
                         destination: remote,
                         ecn: None,
@@ -574,14 +641,88 @@ where
                             0,
                             TransportError::INVALID_TOKEN(""),
                         ),
+                        segment_size: None,
                     });
                     return None;
                 }
             }
+        } else if retry_required {
+            // First Initial
+            self.send_retry(remote, src_cid, dst_cid, temp_loc_cid, crypto);
+            return None;
         } else {
             None
         };
 
+        if let Some(ref filter) = server_config.incoming_filter {
+            let attempt = ConnectionAttempt {
+                remote,
+                validated: retry_cid.is_some(),
+                client_hello: server_config
+                    .crypto
+                    .client_hello(&initial_crypto_data(&packet.payload)),
+            };
+            match filter.filter(&attempt) {
+                FilterAction::Accept => {}
+                FilterAction::Retry if retry_cid.is_some() => {}
+                FilterAction::Retry => {
+                    debug!("filter requested retry for connection from {}", remote);
+                    self.send_retry(remote, src_cid, dst_cid, temp_loc_cid, crypto);
+                    return None;
+                }
+                FilterAction::Refuse => {
+                    debug!("filter refused connection from {}", remote);
+                    self.stats.connections_refused += 1;
+                    self.transmits.push_back(Transmit {
+                        destination: remote,
+                        ecn: None,
+                        contents: initial_close(
+                            crypto,
+                            &src_cid,
+                            &temp_loc_cid,
+                            0,
+                            TransportError::CONNECTION_REFUSED(""),
+                        ),
+                        segment_size: None,
+                    });
+                    return None;
+                }
+                FilterAction::Drop => {
+                    debug!("filter dropped connection from {}", remote);
+                    self.stats.connections_dropped += 1;
+                    return None;
+                }
+            }
+        }
+
+        if let Some(limit) = server_config.max_handshakes_per_ip {
+            if !self.handshake_rates.admit(
+                remote.ip(),
+                now,
+                limit,
+                server_config.handshake_rate_interval,
+            ) {
+                debug!(
+                    "rejecting connection from {} due to handshake rate limit",
+                    remote
+                );
+                self.stats.connections_refused += 1;
+                self.transmits.push_back(Transmit {
+                    destination: remote,
+                    ecn: None,
+                    contents: initial_close(
+                        crypto,
+                        &src_cid,
+                        &temp_loc_cid,
+                        0,
+                        TransportError::CONNECTION_REFUSED(""),
+                    ),
+                    segment_size: None,
+                });
+                return None;
+            }
+        }
+
         let (ch, mut conn) = self
             .add_connection(
                 dst_cid,
@@ -596,20 +737,28 @@ where
         if dst_cid.len() != 0 {
             self.connection_ids_initial.insert(dst_cid, ch);
         }
+        self.stats.handshakes_started += 1;
         match conn.handle_first_packet(now, remote, ecn, packet_number as u64, packet, rest) {
             Ok(()) => {
                 trace!(id = ch.0, icid = %dst_cid, "connection incoming");
                 self.incoming_handshakes += 1;
+                self.pending_handshakes += 1;
+                self.live_incoming += 1;
+                let meta = &mut self.connections[ch];
+                meta.handshaking = true;
+                meta.live_incoming = true;
                 Some((ch, conn))
             }
             Err(e) => {
                 debug!("handshake failed: {}", e);
+                self.stats.handshakes_failed += 1;
                 self.handle_event(ch, EndpointEvent(EndpointEventInner::Drained));
                 if let ConnectionError::TransportError(e) = e {
                     self.transmits.push_back(Transmit {
                         destination: remote,
                         ecn: None,
                         contents: initial_close(crypto, &src_cid, &temp_loc_cid, 0, e),
+                        segment_size: None,
                     });
                 }
                 None
@@ -617,6 +766,41 @@ where
         }
     }
 
+    /// Send a Retry packet in response to a client's first Initial
+    fn send_retry(
+        &mut self,
+        remote: SocketAddr,
+        src_cid: ConnectionId,
+        dst_cid: ConnectionId,
+        temp_loc_cid: ConnectionId,
+        crypto: &Keys<S>,
+    ) {
+        let server_config = self.server_config.as_ref().unwrap();
+        let token = token::generate(
+            &*server_config.token_key,
+            &remote,
+            &dst_cid,
+            SystemTime::now(),
+        );
+        let mut buf = Vec::new();
+        let header = Header::Retry {
+            src_cid: temp_loc_cid,
+            dst_cid: src_cid,
+        };
+        let encode = header.encode(&mut buf);
+        buf.put_slice(&token);
+        buf.extend_from_slice(&S::retry_tag(&dst_cid, &buf));
+        encode.finish::<S::PacketKey, S::HeaderKey>(&mut buf, &crypto.header.local, None);
+        self.stats.retries_sent += 1;
+
+        self.transmits.push_back(Transmit {
+            destination: remote,
+            ecn: None,
+            contents: buf.into(),
+            segment_size: None,
+        });
+    }
+
     /// Free a handshake slot for reuse
     ///
     /// Every time an [`DatagramEvent::NewConnection`] is yielded by `Endpoint::handle`, a slot is
@@ -637,6 +821,11 @@ where
         &self.config
     }
 
+    /// Statistics about the endpoint's activity since it was created
+    pub fn stats(&self) -> EndpointStats {
+        self.stats
+    }
+
     #[cfg(test)]
     pub(crate) fn known_connections(&self) -> usize {
         let x = self.connections.len();
@@ -681,6 +870,10 @@ where
             .field("config", &self.config)
             .field("server_config", &self.server_config)
             .field("incoming_handshakes", &self.incoming_handshakes)
+            .field("pending_handshakes", &self.pending_handshakes)
+            .field("live_incoming", &self.live_incoming)
+            .field("handshake_rates", &self.handshake_rates)
+            .field("stats", &self.stats)
             .field("reject_new_connections", &self.reject_new_connections)
             .finish()
     }
@@ -700,6 +893,33 @@ pub(crate) struct ConnectionMeta {
     /// Reset token provided by the peer for the CID we're currently sending to, and the address
     /// being sent to
     reset_token: Option<(SocketAddr, ResetToken)>,
+    /// Whether this is an incoming connection that has yet to complete its handshake
+    handshaking: bool,
+    /// Whether this is an incoming connection counted in `Endpoint::live_incoming`
+    live_incoming: bool,
+}
+
+/// Reassemble the start of the crypto stream carried by a client's first Initial packet
+fn initial_crypto_data(payload: &BytesMut) -> Vec<u8> {
+    let mut frames = frame::Iter::new(payload.clone().freeze())
+        .filter_map(|frame| match frame {
+            Frame::Crypto(x) => Some(x),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    frames.sort_by_key(|x| x.offset);
+    let mut data = Vec::new();
+    for frame in frames {
+        let end = data.len() as u64;
+        if frame.offset > end {
+            break;
+        }
+        let skip = (end - frame.offset) as usize;
+        if skip < frame.data.len() {
+            data.extend_from_slice(&frame.data[skip..]);
+        }
+    }
+    data
 }
 
 fn reset_token_for<H>(key: &H, id: &ConnectionId) -> ResetToken
@@ -895,6 +1115,81 @@ impl ResetTokenTable {
     }
 }
 
+/// Counters describing an `Endpoint`'s activity
+///
+/// Apart from `datagrams_sent`, covers only datagrams handled by the endpoint itself; traffic on
+/// established connections is not counted here.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct EndpointStats {
+    /// Datagrams passed to `Endpoint::handle`
+    pub datagrams_received: u64,
+    /// Datagrams sent, by the endpoint and its connections alike
+    ///
+    /// Connections' transmits do not go through the `Endpoint`, so this is counted by the I/O
+    /// layer writing them to the socket, such as `quinn`, and left at 0 by `Endpoint::stats`.
+    pub datagrams_sent: u64,
+    /// Datagrams generated by the endpoint itself rather than by a connection, such as version
+    /// negotiation packets, retries, stateless resets and handshake rejections
+    pub stateless_datagrams_sent: u64,
+    /// Datagrams dropped because their header could not be decoded
+    pub dropped_malformed: u64,
+    /// Datagrams dropped by a client because they used an unsupported QUIC version
+    pub dropped_unsupported_version: u64,
+    /// Datagrams dropped because they were addressed to an unknown connection and could not
+    /// start a new one or be answered with a stateless reset
+    pub dropped_unknown_connection: u64,
+    /// Initial packets that were too short, could not be authenticated, or were otherwise
+    /// unacceptable as the start of a new connection
+    pub invalid_initials: u64,
+    /// Incoming connection attempts rejected due to an invalid or expired retry token
+    pub invalid_tokens: u64,
+    /// Incoming connection attempts refused with `CONNECTION_REFUSED`
+    pub connections_refused: u64,
+    /// Incoming connection attempts silently dropped by the `IncomingFilter`
+    pub connections_dropped: u64,
+    /// Incoming handshakes begun
+    pub handshakes_started: u64,
+    /// Incoming handshakes that completed successfully
+    pub handshakes_completed: u64,
+    /// Incoming handshakes that failed or were abandoned before completion
+    pub handshakes_failed: u64,
+    /// Retry packets sent
+    pub retries_sent: u64,
+    /// Stateless resets sent
+    pub stateless_resets_sent: u64,
+    /// Version negotiation packets sent
+    pub version_negotiations_sent: u64,
+}
+
+/// Fixed-window counters of recent connection attempts from each remote IP
+#[derive(Default, Debug)]
+struct HandshakeRateTable {
+    windows: HashMap<IpAddr, (Instant, u32)>,
+    /// When expired windows should next be discarded
+    next_prune: Option<Instant>,
+}
+
+impl HandshakeRateTable {
+    /// Record a connection attempt from `ip`, returning whether it's within `limit`
+    fn admit(&mut self, ip: IpAddr, now: Instant, limit: u32, interval: Duration) -> bool {
+        if self.next_prune.map_or(true, |t| t <= now) {
+            self.windows
+                .retain(|_, &mut (start, _)| start + interval > now);
+            self.next_prune = Some(now + interval);
+        }
+        let (start, count) = self.windows.entry(ip).or_insert((now, 0));
+        if *start + interval <= now {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= limit {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
 #[cfg(test)]
 mod test {
     #[cfg(feature = "ring")]
@@ -0,0 +1,180 @@
+use std::{fmt, ops::RangeInclusive, sync::Arc};
+
+use bytes::Bytes;
+
+use crate::{frame, ConfigError, VarInt};
+
+/// Application-defined frame types, for experimenting with protocol extensions
+///
+/// Registered through `TransportConfig::frame_extension`. Frames of the types an extension claims
+/// are accepted in 0-RTT and 1-RTT packets, delivered to the application through
+/// `Event::FrameReceived`, and may be sent with `Connection::send_frame`.
+///
+/// Frame bodies are encoded by the application, and are opaque to the transport except for their
+/// length, which `decode` determines. Receiving an extension frame that the peer doesn't
+/// understand is a protocol violation, so extensions should only be used once the peer is known to
+/// support them, e.g. by exchanging a custom transport parameter.
+pub trait FrameExtension: Send + Sync {
+    /// Frame types handled by this extension
+    ///
+    /// Must not overlap types defined by the protocol or claimed by other extensions.
+    fn frame_types(&self) -> Vec<RangeInclusive<u64>>;
+
+    /// Determine the length of the body of a frame of type `ty`
+    ///
+    /// `buf` holds the remainder of the packet following the frame type. Returns `None` if the
+    /// body is malformed, which closes the connection with a `FRAME_ENCODING_ERROR`.
+    fn decode(&self, ty: VarInt, buf: &[u8]) -> Option<usize>;
+
+    /// Whether frames of type `ty` require the receiver to send an acknowledgement
+    ///
+    /// Frames which don't are only sent in packets that would be sent anyway.
+    fn ack_eliciting(&self, ty: VarInt) -> bool {
+        let _ = ty;
+        true
+    }
+
+    /// Whether frames of type `ty` should be sent again if the packet carrying them is lost
+    fn retransmit(&self, ty: VarInt) -> bool {
+        let _ = ty;
+        true
+    }
+}
+
+/// A frame of an application-defined type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplicationFrame {
+    /// Frame type, as claimed by a `FrameExtension`
+    pub ty: VarInt,
+    /// Encoded frame body, not including the type
+    pub payload: Bytes,
+}
+
+impl ApplicationFrame {
+    pub(crate) fn size(&self) -> usize {
+        self.ty.size() + self.payload.len()
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        use crate::coding::BufMutExt;
+        out.write(self.ty);
+        out.extend_from_slice(&self.payload);
+    }
+}
+
+/// The set of frame extensions registered with a `TransportConfig`
+#[derive(Default, Clone)]
+pub(crate) struct FrameExtensions {
+    /// Sorted by range start
+    types: Vec<(RangeInclusive<u64>, Arc<dyn FrameExtension>)>,
+}
+
+impl FrameExtensions {
+    pub(crate) fn insert(&mut self, extension: Arc<dyn FrameExtension>) -> Result<(), ConfigError> {
+        let ranges = extension.frame_types();
+        for (i, range) in ranges.iter().enumerate() {
+            if range.start() > range.end()
+                || *range.end() > VarInt::MAX.into_inner()
+                || frame::is_builtin(range)
+                || self.types.iter().any(|(x, _)| overlaps(x, range))
+                || ranges[..i].iter().any(|x| overlaps(x, range))
+            {
+                return Err(ConfigError::ReservedFrameType);
+            }
+        }
+        for range in ranges {
+            let i = self
+                .types
+                .binary_search_by_key(range.start(), |(x, _)| *x.start())
+                .unwrap_err();
+            self.types.insert(i, (range, extension.clone()));
+        }
+        Ok(())
+    }
+
+    /// Look up the extension handling `ty`
+    pub(crate) fn get(&self, ty: u64) -> Option<&dyn FrameExtension> {
+        let i = match self.types.binary_search_by_key(&ty, |(x, _)| *x.start()) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (range, extension) = &self.types[i];
+        if range.contains(&ty) {
+            Some(&**extension)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn ack_eliciting(&self, ty: VarInt) -> bool {
+        self.get(ty.into_inner())
+            .map_or(true, |x| x.ack_eliciting(ty))
+    }
+
+    pub(crate) fn retransmit(&self, ty: VarInt) -> bool {
+        self.get(ty.into_inner())
+            .map_or(false, |x| x.retransmit(ty))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+}
+
+impl fmt::Debug for FrameExtensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.types.iter().map(|(x, _)| x))
+            .finish()
+    }
+}
+
+fn overlaps(a: &RangeInclusive<u64>, b: &RangeInclusive<u64>) -> bool {
+    a.start() <= b.end() && b.start() <= a.end()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Fixed(Vec<RangeInclusive<u64>>);
+
+    impl FrameExtension for Fixed {
+        fn frame_types(&self) -> Vec<RangeInclusive<u64>> {
+            self.0.clone()
+        }
+
+        fn decode(&self, _: VarInt, _: &[u8]) -> Option<usize> {
+            Some(0)
+        }
+    }
+
+    #[test]
+    fn registration() {
+        let mut exts = FrameExtensions::default();
+        exts.insert(Arc::new(Fixed(vec![0x40..=0x4f, 0x100..=0x100])))
+            .unwrap();
+        assert!(exts.get(0x3f).is_none());
+        assert!(exts.get(0x40).is_some());
+        assert!(exts.get(0x4f).is_some());
+        assert!(exts.get(0x50).is_none());
+        assert!(exts.get(0x100).is_some());
+        assert!(exts.get(0x101).is_none());
+        // Conflicts with the protocol or an existing extension
+        for ranges in &[
+            &[0x06..=0x06][..],
+            &[0x31..=0x40],
+            &[0x48..=0x50],
+            &[0x60..=0x61, 0x61..=0x62],
+        ] {
+            assert_eq!(
+                exts.insert(Arc::new(Fixed(ranges.to_vec()))).unwrap_err(),
+                ConfigError::ReservedFrameType
+            );
+        }
+        exts.insert(Arc::new(Fixed(vec![0x50..=0x50]))).unwrap();
+        assert!(exts.get(0x50).is_some());
+        assert!(exts.get(0x48).is_some());
+    }
+}
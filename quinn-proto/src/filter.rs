@@ -0,0 +1,50 @@
+use std::net::SocketAddr;
+
+use crate::crypto::ClientHello;
+
+/// Screens incoming connection attempts before any connection state is allocated for them
+///
+/// Configured through `ServerConfig::incoming_filter`. Consulted for every acceptable Initial
+/// packet that would otherwise start a new connection, after any retry token has been validated
+/// and before per-address rate limits are applied.
+pub trait IncomingFilter: Send + Sync {
+    /// Decide how to handle `attempt`
+    fn filter(&self, attempt: &ConnectionAttempt) -> FilterAction;
+}
+
+impl<F> IncomingFilter for F
+where
+    F: Fn(&ConnectionAttempt) -> FilterAction + Send + Sync,
+{
+    fn filter(&self, attempt: &ConnectionAttempt) -> FilterAction {
+        self(attempt)
+    }
+}
+
+/// An incoming connection attempt, as seen by an `IncomingFilter`
+#[derive(Debug, Clone)]
+pub struct ConnectionAttempt {
+    /// The address the attempt was received from
+    pub remote: SocketAddr,
+    /// Whether the client proved it can receive packets at `remote` by echoing a retry token
+    pub validated: bool,
+    /// Contents of the client's first handshake message
+    ///
+    /// `None` if the message did not fit in the client's first packet, or could not be parsed.
+    pub client_hello: Option<ClientHello>,
+}
+
+/// How an `IncomingFilter` would like a connection attempt to be handled
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FilterAction {
+    /// Proceed with the handshake
+    Accept,
+    /// Close the connection with a `CONNECTION_REFUSED` error
+    Refuse,
+    /// Require the client to prove it can receive packets at its address before proceeding
+    ///
+    /// Equivalent to `Accept` if the address has already been validated.
+    Retry,
+    /// Ignore the attempt without responding
+    Drop,
+}
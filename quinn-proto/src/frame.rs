@@ -1,12 +1,14 @@
 use std::{
     fmt, io, mem,
     ops::{Range, RangeInclusive},
+    sync::Arc,
 };
 
 use bytes::{Buf, BufMut, Bytes};
 
 use crate::{
     coding::{self, BufExt, BufMutExt, UnexpectedEnd},
+    extension::{ApplicationFrame, FrameExtensions},
     range_set::RangeSet,
     shared::{ConnectionId, EcnCodepoint, ResetToken},
     Dir, StreamId, TransportError, TransportErrorCode, VarInt, MAX_CID_SIZE, RESET_TOKEN_SIZE,
@@ -123,12 +125,20 @@ frame_types! {
     CONNECTION_CLOSE = 0x1c,
     APPLICATION_CLOSE = 0x1d,
     HANDSHAKE_DONE = 0x1e,
+    RESET_STREAM_AT = 0x24,
     // DATAGRAM
 }
 
 const STREAM_TYS: RangeInclusive<u64> = RangeInclusive::new(0x08, 0x0f);
 const DATAGRAM_TYS: RangeInclusive<u64> = RangeInclusive::new(0x30, 0x31);
 
+/// Whether any type in `tys` is defined by the protocol
+pub(crate) fn is_builtin(tys: &RangeInclusive<u64>) -> bool {
+    *tys.start() <= Type::HANDSHAKE_DONE.0
+        || tys.contains(&Type::RESET_STREAM_AT.0)
+        || (tys.start() <= DATAGRAM_TYS.end() && DATAGRAM_TYS.start() <= tys.end())
+}
+
 #[derive(Debug)]
 pub enum Frame {
     Padding,
@@ -153,6 +163,7 @@ pub enum Frame {
     Datagram(Datagram),
     Invalid { ty: Type, reason: &'static str },
     HandshakeDone,
+    Application(ApplicationFrame),
 }
 
 impl Frame {
@@ -160,7 +171,11 @@ impl Frame {
         use self::Frame::*;
         match *self {
             Padding => Type::PADDING,
-            ResetStream(_) => Type::RESET_STREAM,
+            ResetStream(self::ResetStream {
+                reliable_size: None,
+                ..
+            }) => Type::RESET_STREAM,
+            ResetStream(_) => Type::RESET_STREAM_AT,
             Close(self::Close::Connection(_)) => Type::CONNECTION_CLOSE,
             Close(self::Close::Application(_)) => Type::APPLICATION_CLOSE,
             MaxData(_) => Type::MAX_DATA,
@@ -193,6 +208,7 @@ impl Frame {
             Datagram(_) => Type(*DATAGRAM_TYS.start()),
             Invalid { ty, .. } => ty,
             HandshakeDone => Type::HANDSHAKE_DONE,
+            Application(ref x) => Type(x.ty.into_inner()),
         }
     }
 }
@@ -469,6 +485,7 @@ pub struct Iter {
     // TODO: ditch io::Cursor after bytes 0.5
     bytes: io::Cursor<Bytes>,
     last_ty: Option<Type>,
+    extensions: Option<Arc<FrameExtensions>>,
 }
 
 enum IterErr {
@@ -499,6 +516,19 @@ impl Iter {
         Iter {
             bytes: io::Cursor::new(payload),
             last_ty: None,
+            extensions: None,
+        }
+    }
+
+    /// Additionally accept frames of application-defined types
+    pub(crate) fn with_extensions(payload: Bytes, extensions: &Arc<FrameExtensions>) -> Self {
+        Iter {
+            extensions: if extensions.is_empty() {
+                None
+            } else {
+                Some(extensions.clone())
+            },
+            ..Self::new(payload)
         }
     }
 
@@ -521,7 +551,20 @@ impl Iter {
                 id: self.bytes.get()?,
                 error_code: self.bytes.get()?,
                 final_offset: self.bytes.get_var()?,
+                reliable_size: None,
             }),
+            Type::RESET_STREAM_AT => {
+                let frame = ResetStream {
+                    id: self.bytes.get()?,
+                    error_code: self.bytes.get()?,
+                    final_offset: self.bytes.get_var()?,
+                    reliable_size: Some(self.bytes.get_var()?),
+                };
+                if frame.reliable_size > Some(frame.final_offset) {
+                    return Err(IterErr::Malformed);
+                }
+                Frame::ResetStream(frame)
+            }
             Type::CONNECTION_CLOSE => Frame::Close(Close::Connection(ConnectionClose {
                 error_code: self.bytes.get()?,
                 frame_type: {
@@ -655,6 +698,20 @@ impl Iter {
                             self.take_remaining()
                         },
                     })
+                } else if let Some(ext) = self.extensions.as_ref().and_then(|x| x.get(ty.0)) {
+                    let ty = VarInt::from_u64(ty.0).unwrap();
+                    let len = ext
+                        .decode(ty, self.bytes.bytes())
+                        .ok_or(IterErr::Malformed)?;
+                    if len > self.bytes.remaining() {
+                        return Err(IterErr::UnexpectedEnd);
+                    }
+                    let start = self.bytes.position() as usize;
+                    self.bytes.advance(len);
+                    Frame::Application(ApplicationFrame {
+                        ty,
+                        payload: self.bytes.get_ref().slice(start..(start + len)),
+                    })
                 } else {
                     return Err(IterErr::InvalidFrameId);
                 }
@@ -736,18 +793,26 @@ pub struct ResetStream {
     pub id: StreamId,
     pub error_code: VarInt,
     pub final_offset: u64,
+    /// Amount of data which is still delivered, if sent as RESET_STREAM_AT
+    pub reliable_size: Option<u64>,
 }
 
 impl FrameStruct for ResetStream {
-    const SIZE_BOUND: usize = 1 + 8 + 8 + 8;
+    const SIZE_BOUND: usize = 1 + 8 + 8 + 8 + 8;
 }
 
 impl ResetStream {
     pub fn encode<W: BufMut>(&self, out: &mut W) {
-        out.write(Type::RESET_STREAM); // 1 byte
+        match self.reliable_size {
+            None => out.write(Type::RESET_STREAM),       // 1 byte
+            Some(_) => out.write(Type::RESET_STREAM_AT), // 1 byte
+        }
         out.write(self.id); // <= 8 bytes
         out.write(self.error_code); // <= 8 bytes
         out.write_var(self.final_offset); // <= 8 bytes
+        if let Some(x) = self.reliable_size {
+            out.write_var(x); // <= 8 bytes
+        }
     }
 }
 
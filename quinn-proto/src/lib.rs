@@ -34,22 +34,39 @@ mod varint;
 pub use varint::{VarInt, VarIntBoundsExceeded};
 
 mod connection;
-pub use crate::connection::{ConnectionError, Event, SendDatagramError};
-pub use crate::connection::{FinishError, ReadError, StreamEvent, UnknownStream, WriteError};
+pub use crate::connection::{ConnectionError, Event, SendDatagramError, SendFrameError};
+pub use crate::connection::{
+    FinishError, ReadError, ResetError, StreamEvent, UnknownStream, WriteError,
+};
 
 mod config;
 pub use config::{ConfigError, TransportConfig};
 
+mod anti_replay;
+pub use crate::anti_replay::{AntiReplay, StrikeRegister};
+
+mod session_store;
+pub use crate::session_store::{FileSessionStore, MemorySessionStore, SessionStore};
+
+mod filter;
+pub use crate::filter::{ConnectionAttempt, FilterAction, IncomingFilter};
+
+mod extension;
+pub use crate::extension::{ApplicationFrame, FrameExtension};
+
 pub mod crypto;
 #[cfg(feature = "rustls")]
-pub use crypto::{rustls::AuthenticationData, types::*};
+pub use crypto::{
+    rustls::{AuthenticationData, SniResolver},
+    types::*,
+};
 
 mod frame;
 use crate::frame::Frame;
 pub use crate::frame::{ApplicationClose, ConnectionClose, Datagram};
 
 mod endpoint;
-pub use crate::endpoint::{ConnectError, ConnectionHandle, DatagramEvent};
+pub use crate::endpoint::{ConnectError, ConnectionHandle, DatagramEvent, EndpointStats};
 
 mod shared;
 pub use crate::shared::{ConnectionEvent, ConnectionId, EcnCodepoint, EndpointEvent};
@@ -214,6 +231,11 @@ pub struct Transmit {
     pub ecn: Option<EcnCodepoint>,
     /// Contents of the datagram
     pub contents: Box<[u8]>,
+    /// The segment size if this transmission contains multiple datagrams
+    ///
+    /// `None` if the transmit only contains a single datagram. Otherwise, `contents` holds
+    /// consecutive datagrams of exactly this size, except for the last, which may be smaller.
+    pub segment_size: Option<usize>,
 }
 
 //
@@ -0,0 +1,389 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use tracing::warn;
+
+/// Storage for the session state clients use to resume connections
+///
+/// Values are opaque to the store. For TLS 1.3 they hold a resumption ticket together with the
+/// transport parameters the server sent on the original connection, which must be remembered for
+/// 0-RTT data to be sent on the resumed connection.
+///
+/// Methods are called from within the handshake, typically on the task driving the endpoint, so
+/// implementations must not block, e.g. on disk or network I/O. Slow storage should be written to
+/// in the background, as `FileSessionStore` does.
+pub trait SessionStore: Send + Sync {
+    /// Store `value` under `key`, replacing any previous value, returning whether it was stored
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool;
+    /// Look up the most recent value stored under `key`
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// An in-memory `SessionStore` which evicts the least recently used entry when full
+///
+/// Sessions are lost when the process exits; see `FileSessionStore` for a persistent alternative.
+#[derive(Debug)]
+pub struct MemorySessionStore {
+    entries: Mutex<Lru>,
+}
+
+impl MemorySessionStore {
+    /// Construct a store holding at most `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(Lru::new(capacity)),
+        }
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.entries.lock().unwrap().insert(key, value);
+        true
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries.lock().unwrap().get(key)
+    }
+}
+
+/// A `SessionStore` persisted to a file
+///
+/// The file is read once when the store is opened and rewritten whenever a new entry is stored,
+/// allowing short-lived processes to resume connections established by their predecessors. When
+/// several processes share a file, the last one to store an entry wins.
+///
+/// Writes happen on a background thread so that storing a session never blocks the caller. Pending
+/// writes are completed when the store is dropped, or on demand with `flush`.
+///
+/// The file contains secrets that allow sessions to be resumed; on unix platforms it is created
+/// readable by the current user only.
+#[derive(Debug)]
+pub struct FileSessionStore {
+    file: Arc<StoreFile>,
+    entries: Mutex<Lru>,
+    /// Snapshots of `entries` awaiting the writer thread
+    writer: Option<(Mutex<mpsc::Sender<Snapshot>>, thread::JoinHandle<()>)>,
+}
+
+impl FileSessionStore {
+    /// Open the store at `path`, holding at most `capacity` entries
+    ///
+    /// A missing file is treated as an empty store and created on the first write.
+    pub fn open(path: impl Into<PathBuf>, capacity: usize) -> io::Result<Self> {
+        let path = path.into();
+        let mut entries = Lru::new(capacity);
+        match fs::read(&path) {
+            Ok(data) => {
+                for (key, value) in decode(&data).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "malformed session store")
+                })? {
+                    entries.insert(key, value);
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let file = Arc::new(StoreFile {
+            path,
+            saved: Mutex::new(entries.clock),
+        });
+        let (send, recv) = mpsc::channel::<Snapshot>();
+        let writer = {
+            let file = file.clone();
+            thread::Builder::new()
+                .name("quinn-session-store".into())
+                .spawn(move || {
+                    while let Ok(mut snapshot) = recv.recv() {
+                        // Only the latest state matters
+                        while let Ok(newer) = recv.try_recv() {
+                            snapshot = newer;
+                        }
+                        if let Err(e) = file.save(snapshot) {
+                            warn!(
+                                "failed to write session store {}: {}",
+                                file.path.display(),
+                                e
+                            );
+                        }
+                    }
+                })?
+        };
+        Ok(Self {
+            file,
+            entries: Mutex::new(entries),
+            writer: Some((Mutex::new(send), writer)),
+        })
+    }
+
+    /// The path the store is persisted to
+    pub fn path(&self) -> &Path {
+        &self.file.path
+    }
+
+    /// Write the current entries to the file, blocking until they are durably stored
+    ///
+    /// Useful before handing the file to another process while this store is still alive.
+    pub fn flush(&self) -> io::Result<()> {
+        let snapshot = self.entries.lock().unwrap().snapshot();
+        self.file.save(snapshot)
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        let snapshot = {
+            let entries = &mut *self.entries.lock().unwrap();
+            entries.insert(key, value);
+            entries.snapshot()
+        };
+        let (ref send, _) = *self.writer.as_ref().unwrap();
+        // The writer thread only exits once the sender is dropped
+        send.lock().unwrap().send(snapshot).unwrap();
+        true
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries.lock().unwrap().get(key)
+    }
+}
+
+impl Drop for FileSessionStore {
+    fn drop(&mut self) {
+        if let Some((send, writer)) = self.writer.take() {
+            // Closing the channel lets the writer finish any pending write, then exit
+            drop(send);
+            let _ = writer.join();
+        }
+    }
+}
+
+/// The file backing a `FileSessionStore`, shared with its writer thread
+#[derive(Debug)]
+struct StoreFile {
+    path: PathBuf,
+    /// Version of the most recent snapshot written
+    saved: Mutex<u64>,
+}
+
+impl StoreFile {
+    /// Write `snapshot` unless a more recent one has already been written
+    fn save(&self, snapshot: Snapshot) -> io::Result<()> {
+        let saved = &mut *self.saved.lock().unwrap();
+        if snapshot.version <= *saved {
+            return Ok(());
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        file.write_all(&snapshot.data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        *saved = snapshot.version;
+        Ok(())
+    }
+}
+
+/// Encoded entries of a store at a point in time
+#[derive(Debug)]
+struct Snapshot {
+    /// Value of `Lru::clock` when the snapshot was taken
+    version: u64,
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Lru {
+    capacity: usize,
+    /// Incremented on every access
+    clock: u64,
+    entries: HashMap<Vec<u8>, (u64, Vec<u8>)>,
+    /// Keys by time of last access
+    recency: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            clock: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let time = self.tick();
+        if let Some((old, _)) = self.entries.insert(key.clone(), (time, value)) {
+            self.recency.remove(&old);
+        }
+        self.recency.insert(time, key);
+        while self.entries.len() > self.capacity {
+            let (&oldest, _) = self.recency.iter().next().unwrap();
+            let key = self.recency.remove(&oldest).unwrap();
+            self.entries.remove(&key);
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let time = self.tick();
+        let (last, value) = self.entries.get_mut(key)?;
+        let key = self.recency.remove(last).unwrap();
+        self.recency.insert(time, key);
+        *last = time;
+        Some(value.clone())
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: self.clock,
+            data: self.encode(),
+        }
+    }
+
+    /// Serialize entries from least to most recently used, so that loading them back in order
+    /// preserves their recency
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for key in self.recency.values() {
+            let value = &self.entries[key].1;
+            // Entries whose length can't be encoded are kept in memory only
+            let (key_len, value_len) = match (u16::try_from(key.len()), u32::try_from(value.len()))
+            {
+                (Ok(key_len), Ok(value_len)) => (key_len, value_len),
+                _ => continue,
+            };
+            buf.extend_from_slice(&key_len.to_be_bytes());
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(&value_len.to_be_bytes());
+            buf.extend_from_slice(value);
+        }
+        buf
+    }
+}
+
+fn decode(mut data: &[u8]) -> Option<Vec<(Vec<u8>, Vec<u8>)>> {
+    fn take<'a>(data: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        if data.len() < n {
+            return None;
+        }
+        let (x, rest) = data.split_at(n);
+        *data = rest;
+        Some(x)
+    }
+
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let mut len = [0; 2];
+        len.copy_from_slice(take(&mut data, 2)?);
+        let key = take(&mut data, u16::from_be_bytes(len) as usize)?.to_vec();
+        let mut len = [0; 4];
+        len.copy_from_slice(take(&mut data, 4)?);
+        let value = take(&mut data, u32::from_be_bytes(len) as usize)?.to_vec();
+        entries.push((key, value));
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lru_eviction() {
+        let store = MemorySessionStore::new(2);
+        store.put(b"a".to_vec(), b"1".to_vec());
+        store.put(b"b".to_vec(), b"2".to_vec());
+        // Touch `a` so `b` is the least recently used
+        assert_eq!(store.get(b"a"), Some(b"1".to_vec()));
+        store.put(b"c".to_vec(), b"3".to_vec());
+        assert_eq!(store.get(b"b"), None);
+        assert_eq!(store.get(b"a"), Some(b"1".to_vec()));
+        assert_eq!(store.get(b"c"), Some(b"3".to_vec()));
+        // Replacing an entry doesn't evict anything
+        store.put(b"c".to_vec(), b"4".to_vec());
+        assert_eq!(store.get(b"a"), Some(b"1".to_vec()));
+        assert_eq!(store.get(b"c"), Some(b"4".to_vec()));
+    }
+
+    #[test]
+    fn file_roundtrip() {
+        let path = std::env::temp_dir().join(format!("quinn-sessions-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let store = FileSessionStore::open(&path, 2).unwrap();
+            assert_eq!(store.get(b"a"), None);
+            assert!(store.put(b"a".to_vec(), b"1".to_vec()));
+            assert!(store.put(b"b".to_vec(), vec![0xff; 300]));
+            assert!(store.put(b"c".to_vec(), b"3".to_vec()));
+        }
+        let store = FileSessionStore::open(&path, 2).unwrap();
+        assert_eq!(store.get(b"a"), None);
+        assert_eq!(store.get(b"b"), Some(vec![0xff; 300]));
+        assert_eq!(store.get(b"c"), Some(b"3".to_vec()));
+        fs::write(&path, b"\x00\x05abc").unwrap();
+        assert_eq!(
+            FileSessionStore::open(&path, 2).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_oversized_key() {
+        let path = std::env::temp_dir().join(format!("quinn-oversized-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let long = vec![b'k'; 70_000];
+        {
+            let store = FileSessionStore::open(&path, 3).unwrap();
+            assert!(store.put(b"a".to_vec(), b"1".to_vec()));
+            assert!(store.put(long.clone(), b"2".to_vec()));
+            assert!(store.put(b"c".to_vec(), b"3".to_vec()));
+            assert_eq!(store.get(&long), Some(b"2".to_vec()));
+        }
+        // The oversized entry is not persisted, without corrupting the others
+        let store = FileSessionStore::open(&path, 3).unwrap();
+        assert_eq!(store.get(b"a"), Some(b"1".to_vec()));
+        assert_eq!(store.get(&long), None);
+        assert_eq!(store.get(b"c"), Some(b"3".to_vec()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_flush() {
+        let path = std::env::temp_dir().join(format!("quinn-flush-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = FileSessionStore::open(&path, 2).unwrap();
+        assert!(store.put(b"a".to_vec(), b"1".to_vec()));
+        store.flush().unwrap();
+        // Visible to other readers while the original store is still alive
+        let other = FileSessionStore::open(&path, 2).unwrap();
+        assert_eq!(other.get(b"a"), Some(b"1".to_vec()));
+        drop(other);
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -54,6 +54,10 @@ pub(crate) enum EndpointEventInner {
     NeedIdentifiers(u64),
     /// Stop routing connection ID for this sequence number to the connection
     RetireConnectionId(u64),
+    /// The cryptographic handshake has completed successfully
+    HandshakeCompleted,
+    /// The connection has begun closing or draining
+    Closed,
 }
 
 /// Protocol-level identifier for a connection.
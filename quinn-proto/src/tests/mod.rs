@@ -43,6 +43,7 @@ fn version_negotiate_server() {
             .any(|x| u32::from_be_bytes(x.try_into().unwrap()) == VERSION));
     }
     assert_matches!(server.poll_transmit(), None);
+    assert_eq!(server.stats().version_negotiations_sent, 1);
 }
 
 #[test]
@@ -123,6 +124,27 @@ fn stateless_retry() {
     pair.connect();
 }
 
+#[test]
+fn endpoint_stats() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            use_stateless_retry: true,
+            ..server_config()
+        },
+    );
+    pair.connect();
+    let stats = pair.server.stats();
+    assert_eq!(stats.retries_sent, 1);
+    assert_eq!(stats.handshakes_started, 1);
+    assert_eq!(stats.handshakes_completed, 1);
+    assert_eq!(stats.handshakes_failed, 0);
+    assert_eq!(stats.stateless_datagrams_sent, 1);
+    assert!(stats.datagrams_received >= 2);
+    assert_eq!(pair.client.stats().handshakes_started, 0);
+}
+
 #[test]
 fn server_stateless_reset() {
     let _guard = subscribe();
@@ -217,6 +239,77 @@ fn finish_stream_simple() {
     assert_matches!(pair.server_conn_mut(server_ch).read_unordered(s), Ok(None));
 }
 
+#[test]
+fn stream_priority() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s1 = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    let s2 = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    pair.client_conn_mut(client_ch).set_priority(s2, 1).unwrap();
+    assert_matches!(pair.client_conn_mut(client_ch).priority(s1), Ok(0));
+    assert_matches!(pair.client_conn_mut(client_ch).priority(s2), Ok(1));
+
+    const MSG: &[u8] = &[0xab; 4000];
+    pair.client_conn_mut(client_ch).write(s1, MSG).unwrap();
+    pair.client_conn_mut(client_ch).write(s2, MSG).unwrap();
+
+    // Only deliver the first datagram, which should be filled by the higher priority stream
+    pair.drive_client();
+    pair.server.inbound.truncate(1);
+    pair.drive_server();
+
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).accept(Dir::Uni), Some(stream) if stream == s1);
+    assert_matches!(pair.server_conn_mut(server_ch).accept(Dir::Uni), Some(stream) if stream == s2);
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s1),
+        Err(ReadError::Blocked)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s2),
+        Ok(Some((ref data, 0))) if !data.is_empty()
+    );
+}
+
+#[test]
+fn stream_default_priority() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s1 = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    let s2 = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+
+    const MSG: &[u8] = &[0xab; 4000];
+    pair.client_conn_mut(client_ch).write(s1, MSG).unwrap();
+    pair.client_conn_mut(client_ch).write(s2, MSG).unwrap();
+
+    // Streams of equal priority take turns, so the first two datagrams carry one each
+    pair.drive_client();
+    pair.server.inbound.truncate(2);
+    pair.drive_server();
+
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).accept(Dir::Uni), Some(stream) if stream == s1);
+    assert_matches!(pair.server_conn_mut(server_ch).accept(Dir::Uni), Some(stream) if stream == s2);
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s1),
+        Ok(Some((ref data, 0))) if !data.is_empty()
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s2),
+        Ok(Some((ref data, 0))) if !data.is_empty()
+    );
+}
+
 #[test]
 fn reset_stream() {
     let _guard = subscribe();
@@ -246,6 +339,91 @@ fn reset_stream() {
     assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
 }
 
+#[test]
+fn reset_stream_at() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+
+    const MSG: &[u8] = b"hello world";
+    pair.client_conn_mut(client_ch).write(s, MSG).unwrap();
+    pair.drive_client();
+    pair.server.inbound.clear(); // Lose the data
+
+    info!("resetting stream");
+    const ERROR: VarInt = VarInt(42);
+    assert_matches!(
+        pair.client_conn_mut(client_ch)
+            .reset_at(s, ERROR, MSG.len() as u64 + 1),
+        Err(ResetError::InvalidReliableSize)
+    );
+    pair.client_conn_mut(client_ch)
+        .reset_at(s, ERROR, 5)
+        .unwrap();
+    pair.drive();
+
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    // Only the reliable prefix is delivered, despite being lost
+    let mut buf = [0; 32];
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read(s, &mut buf),
+        Ok(Some(5))
+    );
+    assert_eq!(&buf[..5], &MSG[..5]);
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read(s, &mut buf),
+        Err(ReadError::Reset(ERROR))
+    );
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+}
+
+#[test]
+fn segmentation_offload() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    let msg = vec![0xab; 8000];
+    assert_eq!(
+        pair.client_conn_mut(client_ch).write(s, &msg).unwrap(),
+        msg.len()
+    );
+    let now = pair.time;
+    let transmit = pair
+        .client_conn_mut(client_ch)
+        .poll_transmit_batch(now, 10)
+        .unwrap();
+    let segment_size = transmit.segment_size.expect("datagrams weren't batched");
+    assert!(transmit.contents.len() > segment_size);
+    for datagram in split_transmit(transmit) {
+        assert!(datagram.contents.len() <= segment_size);
+        pair.server
+            .inbound
+            .push_back((now, datagram.ecn, datagram.contents));
+    }
+    pair.client.max_datagrams = 10;
+    pair.drive();
+
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut received = Vec::new();
+    let mut buf = [0; 1024];
+    while let Ok(Some(n)) = pair.server_conn_mut(server_ch).read(s, &mut buf) {
+        received.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(received, msg);
+}
+
 #[test]
 fn stop_stream() {
     let _guard = subscribe();
@@ -357,10 +535,263 @@ fn zero_rtt_happypath() {
         .close(pair.time, VarInt(0), [][..].into());
     pair.drive();
 
-    pair.client.addr = SocketAddr::new(
-        Ipv6Addr::LOCALHOST.into(),
-        CLIENT_PORTS.lock().unwrap().next().unwrap(),
-    );
+    pair.client.addr = SocketAddr::new(
+        Ipv6Addr::LOCALHOST.into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_conn_mut(client_ch).write(s, MSG).unwrap();
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s),
+        Ok(Some((ref data, 0))) if data == MSG
+    );
+    assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
+}
+
+#[test]
+fn zero_rtt_persistent_session() {
+    let _guard = subscribe();
+    let path = std::env::temp_dir().join(format!("quinn-test-sessions-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut pair = Pair::default();
+
+    // Establish normal connection, saving the session to disk
+    let mut config = client_config();
+    let store = Arc::new(FileSessionStore::open(&path, 8).unwrap());
+    config.session_store(store.clone());
+    let client_ch = pair.begin_connect(config);
+    pair.drive();
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+    store.flush().unwrap();
+
+    // Resume from a fresh configuration, as a new process would
+    pair.client.addr = SocketAddr::new(
+        Ipv6Addr::LOCALHOST.into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    info!("resuming session");
+    let mut config = client_config();
+    config.session_store(Arc::new(FileSessionStore::open(&path, 8).unwrap()));
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_conn_mut(client_ch).write(s, MSG).unwrap();
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s),
+        Ok(Some((ref data, 0))) if data == MSG
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn zero_rtt_replay() {
+    let _guard = subscribe();
+    let mut server_config = ServerConfig {
+        anti_replay: Some(Arc::new(StrikeRegister::new(16))),
+        ..server_config()
+    };
+    // Stateless tickets may be used repeatedly, unlike rustls's default session cache
+    Arc::make_mut(&mut server_config.crypto).ticketer = rustls::Ticketer::new();
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = client_config();
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_conn_mut(client_ch).write(s, MSG).unwrap();
+    // Capture the client's first flight
+    pair.client.drive(pair.time, pair.server.addr);
+    let first_flight = pair
+        .client
+        .outbound
+        .iter()
+        .map(|x| x.contents.clone())
+        .collect::<Vec<_>>();
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s),
+        Ok(Some((ref data, 0))) if data == MSG
+    );
+    pair.client_conn_mut(client_ch)
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+    assert_eq!(pair.server.known_connections(), 0);
+
+    info!("replaying first flight");
+    for packet in first_flight {
+        pair.server.inbound.push_back((pair.time, None, packet));
+    }
+    pair.drive_server();
+    let server_ch = pair.server.assert_accept();
+    assert_eq!(
+        pair.server_conn_mut(server_ch).read_unordered(s),
+        Err(ReadError::Blocked)
+    );
+}
+
+#[test]
+fn zero_rtt_split_client_hello() {
+    let _guard = subscribe();
+    let mut server_config = ServerConfig {
+        anti_replay: Some(Arc::new(StrikeRegister::new(16))),
+        ..server_config()
+    };
+    Arc::make_mut(&mut server_config.crypto).ticketer = rustls::Ticketer::new();
+    let mut pair = Pair::new(Default::default(), server_config);
+    let mut config = client_config();
+    // Pad the ClientHello out so it can't fit in a single Initial packet
+    Arc::make_mut(&mut config.crypto).alpn_protocols =
+        (0..16).map(|i| vec![b'a' + i; 200]).collect();
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_conn_mut(client_ch).write(s, MSG).unwrap();
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).read_unordered(s),
+        Ok(Some((ref data, 0))) if data == MSG
+    );
+}
+
+#[test]
+fn server_name_split_client_hello() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let mut config = client_config();
+    // Pad the ClientHello out so it can't fit in a single Initial packet
+    Arc::make_mut(&mut config.crypto).alpn_protocols =
+        (0..16).map(|i| vec![b'a' + i; 200]).collect();
+
+    pair.begin_connect(config);
+    pair.drive_client();
+    let rest = pair.server.inbound.split_off(1);
+    assert!(!rest.is_empty());
+    pair.drive_server();
+    let server_ch = pair.server.assert_accept();
+    assert_eq!(pair.server_conn_mut(server_ch).server_name(), None);
+
+    pair.server.inbound.extend(rest);
+    pair.drive_server();
+    assert_eq!(
+        pair.server_conn_mut(server_ch).server_name(),
+        Some("localhost")
+    );
+    pair.drive();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+}
+
+#[test]
+fn zero_rtt_stale_ticket() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            early_data_window: Some(Duration::from_secs(0)),
+            ..server_config()
+        },
+    );
+    let config = client_config();
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_conn_mut(client_ch).open(Dir::Uni).unwrap();
+    pair.client_conn_mut(client_ch)
+        .write(s, b"Hello, 0-RTT!")
+        .unwrap();
+    pair.drive();
+    assert!(!pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert_eq!(
+        pair.server_conn_mut(server_ch).read_unordered(s),
+        Err(ReadError::Blocked)
+    );
+}
+
+#[test]
+fn zero_rtt_fresh_ticket() {
+    let _guard = subscribe();
+    let mut server_config = ServerConfig {
+        early_data_window: Some(Duration::from_secs(60)),
+        ..server_config()
+    };
+    Arc::make_mut(&mut server_config.crypto).ticketer = rustls::Ticketer::new();
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = client_config();
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
     info!("resuming session");
     let client_ch = pair.begin_connect(config);
     assert!(pair.client_conn_mut(client_ch).has_0rtt());
@@ -374,7 +805,6 @@ fn zero_rtt_happypath() {
         pair.server_conn_mut(server_ch).read_unordered(s),
         Ok(Some((ref data, 0))) if data == MSG
     );
-    assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
 }
 
 #[test]
@@ -727,6 +1157,35 @@ fn idle_timeout() {
 }
 
 #[test]
+fn handshake_timeout_expires() {
+    let _guard = subscribe();
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(100);
+    let mut pair = Pair::default();
+    let mut transport = TransportConfig::default();
+    transport.handshake_timeout(Some(HANDSHAKE_TIMEOUT));
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+    let start = pair.time;
+
+    while !pair.client_conn_mut(client_ch).is_closed() {
+        pair.drive_client();
+        pair.server.inbound.clear(); // Simulate an unreachable server
+        pair.time = pair.client.next_wakeup().unwrap();
+    }
+
+    assert!(pair.time - start < 2 * HANDSHAKE_TIMEOUT);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::HandshakeTimedOut,
+        })
+    );
+}
+
+#[test]
+#[allow(deprecated)]
 fn server_busy() {
     let _guard = subscribe();
     let mut pair = Pair::new(
@@ -753,6 +1212,401 @@ fn server_busy() {
     assert_eq!(pair.server.known_cids(), 0);
 }
 
+#[test]
+fn concurrent_connections_limit() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            concurrent_connections: 1,
+            ..server_config()
+        },
+    );
+    pair.connect();
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason:
+                ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                    error_code: TransportErrorCode::CONNECTION_REFUSED,
+                    ..
+                }),
+        })
+    );
+    assert_eq!(pair.server.known_connections(), 1);
+}
+
+#[test]
+fn concurrent_connections_closing() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            concurrent_connections: 1,
+            ..server_config()
+        },
+    );
+    let (client_ch, _) = pair.connect();
+    pair.client_conn_mut(client_ch)
+        .close(pair.time, VarInt(0), Bytes::new());
+    // Let the server see the close without advancing time far enough for it to finish draining
+    pair.drive_client();
+    pair.drive_server();
+    assert_eq!(pair.server.known_connections(), 1);
+
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    pair.server.assert_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+}
+
+#[test]
+fn retry_threshold() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            retry_threshold: Some(1),
+            ..server_config()
+        },
+    );
+    let client_a = pair.begin_connect(client_config());
+    let client_b = pair.begin_connect(client_config());
+    pair.drive_client();
+    pair.drive_server();
+    // The second handshake arrived while the first was pending, so it was sent a retry instead
+    assert_eq!(pair.server.known_connections(), 1);
+    pair.drive();
+    assert_eq!(pair.server.known_connections(), 2);
+    assert_matches!(
+        pair.client_conn_mut(client_a).poll(),
+        Some(Event::Connected { .. })
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_b).poll(),
+        Some(Event::Connected { .. })
+    );
+}
+
+#[test]
+fn handshake_rate_limit() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            max_handshakes_per_ip: Some(1),
+            handshake_rate_interval: Duration::from_secs(10),
+            ..server_config()
+        },
+    );
+    pair.connect();
+
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason:
+                ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                    error_code: TransportErrorCode::CONNECTION_REFUSED,
+                    ..
+                }),
+        })
+    );
+
+    pair.time += Duration::from_secs(10);
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    pair.server.assert_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+}
+
+#[test]
+fn incoming_filter() {
+    let _guard = subscribe();
+    let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let filter = {
+        let attempts = attempts.clone();
+        move |attempt: &ConnectionAttempt| {
+            attempts.lock().unwrap().push(attempt.clone());
+            let hello = attempt.client_hello.as_ref().unwrap();
+            match hello.server_name.as_ref().map(|x| &x[..]) {
+                Some("localhost") if !attempt.validated => FilterAction::Retry,
+                Some("localhost") => FilterAction::Accept,
+                Some("refused.example") => FilterAction::Refuse,
+                _ => FilterAction::Drop,
+            }
+        }
+    };
+    let mut server_config = server_config();
+    server_config.incoming_filter(Some(Arc::new(filter)));
+    let mut pair = Pair::new(Default::default(), server_config);
+
+    // Accepted after a Retry
+    let mut config = client_config();
+    Arc::make_mut(&mut config.crypto).alpn_protocols = vec![b"foo".to_vec()];
+    let client_ch = pair.begin_connect(config);
+    pair.drive();
+    pair.server.assert_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+    {
+        let attempts = attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert!(!attempts[0].validated);
+        assert!(attempts[1].validated);
+        assert_eq!(attempts[1].remote, pair.client.addr);
+        let hello = attempts[1].client_hello.as_ref().unwrap();
+        assert_eq!(hello.alpn_protocols, vec![b"foo".to_vec()]);
+    }
+    assert_eq!(pair.server.stats().retries_sent, 1);
+
+    // Refused
+    let (client_ch, client_conn) = pair
+        .client
+        .connect(client_config(), pair.server.addr, "refused.example")
+        .unwrap();
+    pair.client.connections.insert(client_ch, client_conn);
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason:
+                ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                    error_code: TransportErrorCode::CONNECTION_REFUSED,
+                    ..
+                }),
+        })
+    );
+    assert_eq!(pair.server.stats().connections_refused, 1);
+
+    // Dropped without a response
+    let (client_ch, client_conn) = pair
+        .client
+        .connect(client_config(), pair.server.addr, "dropped.example")
+        .unwrap();
+    pair.client.connections.insert(client_ch, client_conn);
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::TimedOut
+        })
+    );
+    assert!(pair.server.stats().connections_dropped > 0);
+    assert_eq!(pair.server.stats().handshakes_started, 1);
+}
+
+#[test]
+fn custom_transport_parameters() {
+    let _guard = subscribe();
+    let mut server_transport = TransportConfig::default();
+    server_transport
+        .custom_transport_parameter(VarInt(0x1234), &b"server"[..])
+        .unwrap();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            transport: Arc::new(server_transport),
+            ..server_config()
+        },
+    );
+    let mut client_transport = TransportConfig::default();
+    client_transport
+        .custom_transport_parameter(VarInt(0x1234), &b"client"[..])
+        .unwrap()
+        .custom_transport_parameter(VarInt(0x5678), Vec::new())
+        .unwrap();
+    assert_eq!(
+        client_transport
+            .custom_transport_parameter(VarInt(0x0c), Vec::new())
+            .unwrap_err(),
+        ConfigError::ReservedTransportParameter
+    );
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport: Arc::new(client_transport),
+        ..client_config()
+    });
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+
+    let client = pair.client_conn_mut(client_ch);
+    assert_eq!(
+        client.remote_transport_parameter(VarInt(0x1234)).unwrap(),
+        &b"server"[..]
+    );
+    assert!(client.remote_transport_parameter(VarInt(0x5678)).is_none());
+    let server = pair.server_conn_mut(server_ch);
+    assert_eq!(
+        server.remote_transport_parameter(VarInt(0x1234)).unwrap(),
+        &b"client"[..]
+    );
+    assert!(server
+        .remote_transport_parameter(VarInt(0x5678))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn application_frames() {
+    /// Frames carrying a length-prefixed string, of which only type 0x40 is retransmitted
+    struct LengthPrefixed;
+
+    impl FrameExtension for LengthPrefixed {
+        fn frame_types(&self) -> Vec<std::ops::RangeInclusive<u64>> {
+            vec![0x40..=0x41]
+        }
+
+        fn decode(&self, _: VarInt, buf: &[u8]) -> Option<usize> {
+            Some(1 + *buf.first()? as usize)
+        }
+
+        fn retransmit(&self, ty: VarInt) -> bool {
+            ty == VarInt(0x40)
+        }
+    }
+
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.frame_extension(Arc::new(LengthPrefixed)).unwrap();
+    assert_eq!(
+        transport
+            .frame_extension(Arc::new(LengthPrefixed))
+            .unwrap_err(),
+        ConfigError::ReservedFrameType
+    );
+    let transport = Arc::new(transport);
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            transport: transport.clone(),
+            ..server_config()
+        },
+    );
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport,
+        ..client_config()
+    });
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+
+    let reliable = ApplicationFrame {
+        ty: VarInt(0x40),
+        payload: Bytes::from_static(b"\x05hello"),
+    };
+    let unreliable = ApplicationFrame {
+        ty: VarInt(0x41),
+        payload: Bytes::from_static(b"\x05world"),
+    };
+    let client = pair.client_conn_mut(client_ch);
+    assert_eq!(
+        client.send_frame(ApplicationFrame {
+            ty: VarInt(0x42),
+            payload: Bytes::new(),
+        }),
+        Err(SendFrameError::UnknownType)
+    );
+    client.send_frame(reliable.clone()).unwrap();
+    client.send_frame(unreliable).unwrap();
+
+    // Lose the packet carrying both frames
+    pair.client.drive(pair.time, pair.server.addr);
+    assert!(!pair.client.outbound.is_empty());
+    pair.client.outbound.clear();
+    pair.drive();
+
+    let server = pair.server_conn_mut(server_ch);
+    assert_matches!(server.poll(), Some(Event::FrameReceived));
+    assert_eq!(server.recv_frame(), Some(reliable));
+    assert_eq!(server.recv_frame(), None);
+}
+
+#[test]
+fn application_frame_flood() {
+    /// Length-prefixed frames, always retransmitted
+    struct LengthPrefixed;
+
+    impl FrameExtension for LengthPrefixed {
+        fn frame_types(&self) -> Vec<std::ops::RangeInclusive<u64>> {
+            vec![0x40..=0x40]
+        }
+
+        fn decode(&self, _: VarInt, buf: &[u8]) -> Option<usize> {
+            Some(1 + *buf.first()? as usize)
+        }
+
+        fn retransmit(&self, _: VarInt) -> bool {
+            true
+        }
+    }
+
+    let _guard = subscribe();
+    let mut client_transport = TransportConfig::default();
+    client_transport
+        .frame_extension(Arc::new(LengthPrefixed))
+        .unwrap();
+    let mut server_transport = TransportConfig::default();
+    server_transport
+        .frame_extension(Arc::new(LengthPrefixed))
+        .unwrap()
+        .frame_receive_buffer_size(1024);
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            transport: Arc::new(server_transport),
+            ..server_config()
+        },
+    );
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport: Arc::new(client_transport),
+        ..client_config()
+    });
+    pair.drive();
+    pair.server.assert_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+
+    // The server application never reads these
+    let mut payload = vec![0; 100];
+    payload[0] = 99;
+    let flood = ApplicationFrame {
+        ty: VarInt(0x40),
+        payload: payload.into(),
+    };
+    for _ in 0..20 {
+        pair.client_conn_mut(client_ch)
+            .send_frame(flood.clone())
+            .unwrap();
+    }
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason:
+                ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                    error_code: TransportErrorCode::PROTOCOL_VIOLATION,
+                    ..
+                }),
+        })
+    );
+}
+
 #[test]
 fn server_hs_retransmit() {
     let _guard = subscribe();
@@ -177,6 +177,8 @@ pub struct TestEndpoint {
     accepted: Option<ConnectionHandle>,
     pub connections: HashMap<ConnectionHandle, Connection>,
     conn_events: HashMap<ConnectionHandle, VecDeque<ConnectionEvent>>,
+    /// Maximum number of datagrams to request per `Transmit`
+    pub max_datagrams: usize,
 }
 
 impl TestEndpoint {
@@ -201,6 +203,7 @@ impl TestEndpoint {
             accepted: None,
             connections: HashMap::default(),
             conn_events: HashMap::default(),
+            max_datagrams: 1,
         }
     }
 
@@ -256,8 +259,8 @@ impl TestEndpoint {
                 endpoint_events.push((*ch, event));
             }
 
-            while let Some(x) = conn.poll_transmit(now) {
-                self.outbound.push_back(x);
+            while let Some(x) = conn.poll_transmit_batch(now, self.max_datagrams) {
+                self.outbound.extend(split_transmit(x));
             }
             self.timeout = conn.poll_timeout();
         }
@@ -369,6 +372,24 @@ pub fn client_config() -> ClientConfig {
     }
 }
 
+/// Split a segmented `Transmit` into its individual datagrams
+pub fn split_transmit(transmit: Transmit) -> Vec<Transmit> {
+    let segment_size = match transmit.segment_size {
+        Some(x) => x,
+        None => return vec![transmit],
+    };
+    transmit
+        .contents
+        .chunks(segment_size)
+        .map(|contents| Transmit {
+            destination: transmit.destination,
+            ecn: transmit.ecn,
+            contents: contents.into(),
+            segment_size: None,
+        })
+        .collect()
+}
+
 pub fn min_opt<T: Ord>(x: Option<T>, y: Option<T>) -> Option<T> {
     match (x, y) {
         (Some(x), Some(y)) => Some(cmp::min(x, y)),
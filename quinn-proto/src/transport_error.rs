@@ -52,6 +52,10 @@ impl Code {
     pub(crate) fn crypto(code: u8) -> Self {
         Code(0x100 | u64::from(code))
     }
+
+    /// Former name of `CONNECTION_REFUSED`, from drafts of QUIC prior to 25
+    #[deprecated(since = "0.6.2", note = "renamed to `CONNECTION_REFUSED`")]
+    pub const SERVER_BUSY: Self = Code(0x2);
 }
 
 impl coding::Codec for Code {
@@ -114,7 +118,7 @@ macro_rules! errors {
 errors! {
     NO_ERROR(0x0) "the connection is being closed abruptly in the absence of any error";
     INTERNAL_ERROR(0x1) "the endpoint encountered an internal error and cannot continue with the connection";
-    SERVER_BUSY(0x2) "the server is currently busy and does not accept any new connections";
+    CONNECTION_REFUSED(0x2) "the server refused to accept a new connection";
     FLOW_CONTROL_ERROR(0x3) "received more data than permitted in advertised data limits";
     STREAM_LIMIT_ERROR(0x4) "received a frame for a stream identifier that exceeded advertised the stream limit for the corresponding stream type";
     STREAM_STATE_ERROR(0x5) "received a frame for a stream that was not in a state that permitted that frame";
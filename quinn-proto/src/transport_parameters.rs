@@ -11,7 +11,7 @@ use std::{
     net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
 };
 
-use bytes::{buf::ext::BufExt as _, Buf, BufMut};
+use bytes::{buf::ext::BufExt as _, Buf, BufMut, Bytes};
 use err_derive::Error;
 
 use crate::{
@@ -65,7 +65,7 @@ macro_rules! apply_params {
 macro_rules! make_struct {
     {$($(#[$doc:meta])* $name:ident ($code:expr) = $default:expr,)*} => {
         /// Transport parameters used to negotiate connection-level preferences between peers
-        #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+        #[derive(Debug, Clone, Eq, PartialEq)]
         pub struct TransportParameters {
             $($(#[$doc])* pub(crate) $name : u64,)*
 
@@ -73,6 +73,8 @@ macro_rules! make_struct {
             pub(crate) disable_active_migration: bool,
             /// Maximum size for datagram frames
             pub(crate) max_datagram_frame_size: Option<VarInt>,
+            /// Whether the endpoint understands RESET_STREAM_AT frames
+            pub(crate) reset_stream_at: bool,
 
             // Server-only
             /// The DCID from the first Initial packet; must be included if a Retry packet was sent
@@ -81,6 +83,9 @@ macro_rules! make_struct {
             pub(crate) stateless_reset_token: Option<ResetToken>,
             /// The server's preferred address for communication after handshake completion
             pub(crate) preferred_address: Option<PreferredAddress>,
+
+            /// Parameters defined by the application, sorted by identifier
+            pub(crate) custom: Vec<(VarInt, Bytes)>,
         }
 
         impl Default for TransportParameters {
@@ -91,10 +96,13 @@ macro_rules! make_struct {
 
                     disable_active_migration: false,
                     max_datagram_frame_size: None,
+                    reset_stream_at: false,
 
                     original_connection_id: None,
                     stateless_reset_token: None,
                     preferred_address: None,
+
+                    custom: Vec::new(),
                 }
             }
         }
@@ -136,6 +144,8 @@ impl TransportParameters {
             max_datagram_frame_size: config
                 .datagram_receive_buffer_size
                 .map(|x| (x.min(u16::max_value().into()) as u16).into()),
+            reset_stream_at: true,
+            custom: config.custom_transport_parameters.clone(),
             ..Self::default()
         }
     }
@@ -150,6 +160,7 @@ impl TransportParameters {
             || cached.initial_max_streams_bidi < self.initial_max_streams_bidi
             || cached.initial_max_streams_uni < self.initial_max_streams_uni
             || cached.max_datagram_frame_size < self.max_datagram_frame_size
+            || (cached.reset_stream_at && !self.reset_stream_at)
         {
             return Err(TransportError::PROTOCOL_VIOLATION(
                 "0-RTT accepted with incompatible transport parameters",
@@ -159,6 +170,17 @@ impl TransportParameters {
     }
 }
 
+/// Whether `id` identifies a parameter which can't be defined by the application
+///
+/// Covers the parameters known to this implementation and those reserved to exercise the
+/// requirement that unknown parameters be ignored.
+pub(crate) fn is_reserved(id: u64) -> bool {
+    id <= 0x0e || id == 0x20 || id == RESET_STREAM_AT || id % 31 == 27
+}
+
+/// Identifies support for the reliable stream reset extension
+const RESET_STREAM_AT: u64 = 0x17f7_586d_2cb5_71;
+
 /// A server's preferred address
 ///
 /// This is communicated as a transport parameter during TLS session establishment.
@@ -293,11 +315,22 @@ impl TransportParameters {
             w.write(x);
         }
 
+        if self.reset_stream_at {
+            w.write_var(RESET_STREAM_AT);
+            w.write_var(0);
+        }
+
         if let Some(ref x) = self.preferred_address {
             w.write_var(0x000d);
             w.write_var(x.wire_size() as u64);
             x.write(w);
         }
+
+        for &(id, ref value) in &self.custom {
+            w.write(id);
+            w.write_var(value.len() as u64);
+            w.put_slice(value);
+        }
     }
 
     /// Decode `TransportParameters` from buffer
@@ -363,6 +396,12 @@ impl TransportParameters {
                     }
                     params.max_datagram_frame_size = Some(r.get().unwrap());
                 }
+                RESET_STREAM_AT => {
+                    if len != 0 || params.reset_stream_at {
+                        return Err(Error::Malformed);
+                    }
+                    params.reset_stream_at = true;
+                }
                 _ => {
                     macro_rules! parse {
                         {$($(#[$doc:meta])* $name:ident ($code:expr) = $default:expr,)*} => {
@@ -372,7 +411,17 @@ impl TransportParameters {
                                     if len != VarInt::from_u64(params.$name).unwrap().size() as u64 || got.$name { return Err(Error::Malformed); }
                                     got.$name = true;
                                 })*
-                                _ => r.advance(len as usize),
+                                _ if is_reserved(id) => r.advance(len as usize),
+                                _ => {
+                                    let id = VarInt::from_u64(id).unwrap();
+                                    let i = match params.custom.binary_search_by_key(&id, |x| x.0) {
+                                        Ok(_) => return Err(Error::Malformed),
+                                        Err(i) => i,
+                                    };
+                                    let mut value = vec![0; len as usize];
+                                    r.copy_to_slice(&mut value);
+                                    params.custom.insert(i, (id, value.into()));
+                                }
                             }
                         }
                     }
@@ -410,6 +459,7 @@ mod test {
             initial_max_streams_uni: 16,
             ack_delay_exponent: 2,
             max_udp_payload_size: 1200,
+            reset_stream_at: true,
             preferred_address: Some(PreferredAddress {
                 address_v4: Some(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 42)),
                 address_v6: None,
@@ -424,4 +474,28 @@ mod test {
             params
         );
     }
+
+    #[test]
+    fn custom_parameters() {
+        let mut buf = Vec::new();
+        let params = TransportParameters {
+            custom: vec![
+                (VarInt(0x21), Bytes::new()),
+                (VarInt(0x1234), Bytes::from_static(b"hello")),
+            ],
+            ..TransportParameters::default()
+        };
+        params.write(&mut buf);
+        assert_eq!(
+            TransportParameters::read(Side::Client, &mut buf.as_slice()).unwrap(),
+            params
+        );
+
+        // Duplicates are rejected
+        buf.extend_from_slice(&[0x40, 0x21, 0x00]);
+        assert_eq!(
+            TransportParameters::read(Side::Client, &mut buf.as_slice()),
+            Err(Error::Malformed)
+        );
+    }
 }
@@ -7,9 +7,10 @@ use tracing::error;
 use crate::{
     endpoint::{Endpoint, EndpointDriver, EndpointRef, Incoming},
     udp::UdpSocket,
+    IncomingFilter,
 };
 #[cfg(feature = "rustls")]
-use crate::{Certificate, CertificateChain, PrivateKey};
+use crate::{Certificate, CertificateChain, PrivateKey, SessionStore};
 
 /// A helper for constructing an `Endpoint`.
 ///
@@ -141,6 +142,15 @@ where
         self.config.use_stateless_retry(enabled);
         self
     }
+
+    /// Screen incoming connection attempts before any state is allocated for them
+    ///
+    /// The filter may accept, refuse, or silently drop each attempt, or require the client to
+    /// validate its address with a Retry first.
+    pub fn incoming_filter(&mut self, filter: Arc<dyn IncomingFilter>) -> &mut Self {
+        self.config.incoming_filter(Some(filter));
+        self
+    }
 }
 
 #[cfg(feature = "rustls")]
@@ -163,6 +173,33 @@ impl ServerConfigBuilder<proto::crypto::rustls::TlsSession> {
         Ok(self)
     }
 
+    /// Choose the certificate chain presented to each client with `resolver`.
+    ///
+    /// Use a `SniResolver` to serve different certificates depending on the server name requested
+    /// by the client. The resolver may be updated while the endpoint is running. Replaces any
+    /// certificate set with `certificate`.
+    pub fn certificate_resolver(
+        &mut self,
+        resolver: Arc<dyn rustls::ResolvesServerCert>,
+    ) -> &mut Self {
+        self.config.certificate_resolver(resolver);
+        self
+    }
+
+    /// Verify client certificates against the certificate authorities in `roots`.
+    ///
+    /// If `required` is set, clients which don't present a valid certificate are rejected.
+    /// Otherwise, anonymous clients are accepted, but any certificate presented must still be
+    /// valid. The verified chain is available from `Connection::authentication_data`.
+    pub fn client_auth(
+        &mut self,
+        roots: impl IntoIterator<Item = Certificate>,
+        required: bool,
+    ) -> Result<&mut Self, webpki::Error> {
+        self.config.client_auth(roots, required)?;
+        Ok(self)
+    }
+
     /// Set the application-layer protocols to accept, in order of descending preference.
     ///
     /// When set, clients which don't declare support for at least one of the supplied protocols will be rejected.
@@ -245,6 +282,16 @@ impl ClientConfigBuilder<proto::crypto::rustls::TlsSession> {
         Ok(self)
     }
 
+    /// Set the certificate chain that will be presented to servers requesting client authentication.
+    pub fn client_certificate(
+        &mut self,
+        cert_chain: CertificateChain,
+        key: PrivateKey,
+    ) -> Result<&mut Self, rustls::TLSError> {
+        self.config.client_certificate(cert_chain, key)?;
+        Ok(self)
+    }
+
     /// Enable NSS-compatible cryptographic key logging to the `SSLKEYLOGFILE` environment variable.
     ///
     /// Useful for debugging encrypted communications with protocol analyzers such as Wireshark.
@@ -271,6 +318,15 @@ impl ClientConfigBuilder<proto::crypto::rustls::TlsSession> {
         Arc::make_mut(&mut self.config.crypto).enable_early_data = true;
         self
     }
+
+    /// Set where session tickets are kept for resuming connections.
+    ///
+    /// Defaults to an in-memory store. Use a `FileSessionStore` to let short-lived processes
+    /// resume connections, including sending 0-RTT data, established by earlier runs.
+    pub fn session_store(&mut self, store: Arc<dyn SessionStore>) -> &mut Self {
+        self.config.session_store(store);
+        self
+    }
 }
 
 impl<S> Clone for ClientConfigBuilder<S>
@@ -7,7 +7,7 @@ use std::{
     pin::Pin,
     sync::{Arc, Mutex},
     task::{Context, Poll, Waker},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
@@ -16,12 +16,13 @@ use futures::{
     channel::{mpsc, oneshot},
     FutureExt, StreamExt,
 };
-use proto::{ConnectionError, ConnectionHandle, Dir, StreamEvent, StreamId};
+use proto::{ApplicationFrame, ConnectionError, ConnectionHandle, Dir, StreamEvent, StreamId};
 use tokio::time::{delay_until, Delay, Instant as TokioInstant};
 use tracing::info_span;
 
 use crate::{
     broadcast::{self, Broadcast},
+    platform::UdpState,
     streams::{RecvStream, SendStream, WriteError},
     ConnectionEvent, EndpointEvent, VarInt,
 };
@@ -133,6 +134,19 @@ where
         let conn_ref: &ConnectionRef<S> = &self.conn.as_ref().expect("used after yielding Ready");
         conn_ref.lock().unwrap().inner.remote_address()
     }
+
+    /// The server name requested by the client, if any
+    ///
+    /// Read from the client's first handshake message, so it is known as soon as an incoming
+    /// connection is yielded, allowing it to be dropped before the handshake completes. If that
+    /// message spans several packets, this is `None` until they are all received. Always `None` for
+    /// outgoing connections.
+    ///
+    /// Will panic if called after `poll` has returned `Ready`.
+    pub fn server_name(&self) -> Option<String> {
+        let conn_ref: &ConnectionRef<S> = &self.conn.as_ref().expect("used after yielding Ready");
+        conn_ref.lock().unwrap().inner.server_name().map(Into::into)
+    }
 }
 
 /// Future that completes when a connection is fully established
@@ -187,6 +201,8 @@ where
     pub bi_streams: IncomingBiStreams<S>,
     /// Unordered, unreliable datagrams sent by the peer
     pub datagrams: Datagrams<S>,
+    /// Application-defined frames sent by the peer
+    pub frames: ApplicationFrames<S>,
 }
 
 impl<S> NewConnection<S>
@@ -198,7 +214,8 @@ where
             connection: Connection(conn.clone()),
             uni_streams: IncomingUniStreams(conn.clone()),
             bi_streams: IncomingBiStreams(conn.clone()),
-            datagrams: Datagrams(conn),
+            datagrams: Datagrams(conn.clone()),
+            frames: ApplicationFrames(conn),
         }
     }
 }
@@ -310,6 +327,34 @@ where
         conn.close(error_code, Bytes::copy_from_slice(reason));
     }
 
+    /// Wait for the connection to be closed for any reason
+    ///
+    /// Resolves immediately if the connection is already closed. Unlike other handles to the
+    /// connection, the returned future does not by itself keep the connection open.
+    pub fn closed(&self) -> Closed<S> {
+        Closed {
+            conn: (self.0).0.clone(),
+            state: broadcast::State::default(),
+        }
+    }
+
+    /// Wait for the endpoint to begin a graceful shutdown
+    ///
+    /// Resolves once `Endpoint::shutdown` has been called, indicating that the application should
+    /// finish up its work and close the connection, or once the connection is closed. Like
+    /// `closed`, the returned future does not by itself keep the connection open.
+    pub fn shutting_down(&self) -> ShuttingDown<S> {
+        ShuttingDown {
+            conn: (self.0).0.clone(),
+            state: broadcast::State::default(),
+        }
+    }
+
+    /// The reason the connection was closed, or `None` if it is still open
+    pub fn close_reason(&self) -> Option<ConnectionError> {
+        self.0.lock().unwrap().error.clone()
+    }
+
     /// Transmit `data` as an unreliable, unordered application datagram
     ///
     /// Application datagrams are a low-level primitive. They may be lost or delivered out of order,
@@ -347,6 +392,42 @@ where
         self.0.lock().unwrap().inner.max_datagram_size()
     }
 
+    /// Current best estimate of the round trip time to the peer
+    pub fn rtt(&self) -> Duration {
+        self.0.lock().unwrap().inner.rtt()
+    }
+
+    /// Number of streams of a certain directionality that can be opened right away
+    ///
+    /// Once it reaches 0, [`open_bi()`] or [`open_uni()`] wait for the peer to raise its limit.
+    ///
+    /// [`open_bi()`]: #method.open_bi
+    /// [`open_uni()`]: #method.open_uni
+    pub fn available_streams(&self, dir: Dir) -> u64 {
+        self.0.lock().unwrap().inner.available_streams(dir)
+    }
+
+    /// Transmit an application-defined frame
+    ///
+    /// The frame's type must have been registered with `TransportConfig::frame_extension`. See
+    /// `FrameExtension` for details.
+    pub fn send_frame(&self, frame: ApplicationFrame) -> Result<(), SendFrameError> {
+        let conn = &mut *self.0.lock().unwrap();
+        if let Some(ref x) = conn.error {
+            return Err(SendFrameError::ConnectionClosed(x.clone()));
+        }
+        match conn.inner.send_frame(frame) {
+            Ok(()) => {
+                conn.wake();
+                Ok(())
+            }
+            Err(e) => Err(match e {
+                proto::SendFrameError::UnknownType => SendFrameError::UnknownType,
+                proto::SendFrameError::TooLarge => SendFrameError::TooLarge,
+            }),
+        }
+    }
+
     /// The peer's UDP address
     ///
     /// If `ServerConfig::migration` is `true`, clients may change addresses at will, e.g. when
@@ -355,6 +436,18 @@ where
         self.0.lock().unwrap().inner.remote_address()
     }
 
+    /// The value of an application-defined transport parameter sent by the peer
+    ///
+    /// `None` if the peer didn't send `id`. See `TransportConfig::custom_transport_parameter`.
+    pub fn remote_transport_parameter(&self, id: VarInt) -> Option<Bytes> {
+        self.0
+            .lock()
+            .unwrap()
+            .inner
+            .remote_transport_parameter(id)
+            .cloned()
+    }
+
     /// Data conveyed by the peer during the handshake, including cryptographic identity
     pub fn authentication_data(&self) -> S::AuthenticationData {
         self.0
@@ -475,6 +568,31 @@ where
     }
 }
 
+/// Stream of application-defined frames sent by the peer
+#[derive(Debug)]
+pub struct ApplicationFrames<S: proto::crypto::Session>(ConnectionRef<S>);
+
+impl<S> futures::Stream for ApplicationFrames<S>
+where
+    S: proto::crypto::Session,
+{
+    type Item = Result<ApplicationFrame, ConnectionError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut conn = self.0.lock().unwrap();
+        if let Some(x) = conn.inner.recv_frame() {
+            Poll::Ready(Some(Ok(x)))
+        } else if let Some(ConnectionError::LocallyClosed) = conn.error {
+            Poll::Ready(None)
+        } else if let Some(ref e) = conn.error {
+            Poll::Ready(Some(Err(e.clone())))
+        } else {
+            conn.frame_reader = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
 /// A future that will resolve into an opened outgoing unidirectional stream
 pub struct OpenUni<S>
 where
@@ -540,6 +658,58 @@ where
     }
 }
 
+/// A future that resolves when a connection is closed, yielding the reason why
+pub struct Closed<S>
+where
+    S: proto::crypto::Session,
+{
+    conn: Arc<Mutex<ConnectionInner<S>>>,
+    state: broadcast::State,
+}
+
+impl<S> Future for Closed<S>
+where
+    S: proto::crypto::Session,
+{
+    type Output = ConnectionError;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut conn = this.conn.lock().unwrap();
+        if let Some(ref e) = conn.error {
+            return Poll::Ready(e.clone());
+        }
+        conn.closed.register(cx, &mut this.state);
+        Poll::Pending
+    }
+}
+
+/// A future that resolves when the endpoint begins a graceful shutdown
+pub struct ShuttingDown<S>
+where
+    S: proto::crypto::Session,
+{
+    conn: Arc<Mutex<ConnectionInner<S>>>,
+    state: broadcast::State,
+}
+
+impl<S> Future for ShuttingDown<S>
+where
+    S: proto::crypto::Session,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut conn = this.conn.lock().unwrap();
+        if conn.shutting_down || conn.error.is_some() {
+            return Poll::Ready(());
+        }
+        conn.shutdown.register(cx, &mut this.state);
+        Poll::Pending
+    }
+}
+
 #[derive(Debug)]
 pub struct ConnectionRef<S: proto::crypto::Session>(Arc<Mutex<ConnectionInner<S>>>);
 
@@ -553,9 +723,11 @@ where
         endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
         conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
         on_connected: oneshot::Sender<bool>,
+        udp_state: Arc<UdpState>,
     ) -> Self {
         Self(Arc::new(Mutex::new(ConnectionInner {
             inner: conn,
+            udp_state,
             driver: None,
             handle,
             on_connected: Some(on_connected),
@@ -567,9 +739,13 @@ where
             blocked_readers: HashMap::new(),
             uni_opening: Broadcast::new(),
             bi_opening: Broadcast::new(),
+            closed: Broadcast::new(),
+            shutting_down: false,
+            shutdown: Broadcast::new(),
             incoming_uni_streams_reader: None,
             incoming_bi_streams_reader: None,
             datagram_reader: None,
+            frame_reader: None,
             finishing: HashMap::new(),
             error: None,
             ref_count: 0,
@@ -621,6 +797,7 @@ where
     S: proto::crypto::Session,
 {
     pub(crate) inner: proto::generic::Connection<S>,
+    udp_state: Arc<UdpState>,
     driver: Option<Waker>,
     handle: ConnectionHandle,
     on_connected: Option<oneshot::Sender<bool>>,
@@ -632,9 +809,14 @@ where
     pub(crate) blocked_readers: HashMap<StreamId, Waker>,
     uni_opening: Broadcast,
     bi_opening: Broadcast,
+    closed: Broadcast,
+    /// Whether the endpoint has begun a graceful shutdown
+    shutting_down: bool,
+    shutdown: Broadcast,
     incoming_uni_streams_reader: Option<Waker>,
     incoming_bi_streams_reader: Option<Waker>,
     datagram_reader: Option<Waker>,
+    frame_reader: Option<Waker>,
     pub(crate) finishing: HashMap<StreamId, oneshot::Sender<Option<WriteError>>>,
     /// Always set to Some before the connection becomes drained
     pub(crate) error: Option<ConnectionError>,
@@ -648,7 +830,8 @@ where
 {
     fn drive_transmit(&mut self) {
         let now = Instant::now();
-        while let Some(t) = self.inner.poll_transmit(now) {
+        let max_datagrams = self.udp_state.max_gso_segments();
+        while let Some(t) = self.inner.poll_transmit_batch(now, max_datagrams) {
             // If the endpoint driver is gone, noop.
             let _ = self
                 .endpoint_events
@@ -675,6 +858,10 @@ where
                 Poll::Ready(Some(ConnectionEvent::Close { reason, error_code })) => {
                     self.close(error_code, reason);
                 }
+                Poll::Ready(Some(ConnectionEvent::ShuttingDown)) => {
+                    self.shutting_down = true;
+                    self.shutdown.wake();
+                }
                 Poll::Ready(None) => {
                     return Err(ConnectionError::TransportError(proto::TransportError {
                         code: proto::TransportErrorCode::INTERNAL_ERROR,
@@ -723,6 +910,11 @@ where
                         x.wake();
                     }
                 }
+                FrameReceived => {
+                    if let Some(x) = self.frame_reader.take() {
+                        x.wake();
+                    }
+                }
                 Stream(StreamEvent::Readable { id }) => {
                     if let Some(reader) = self.blocked_readers.remove(&id) {
                         reader.wake();
@@ -797,6 +989,8 @@ where
         }
         self.uni_opening.wake();
         self.bi_opening.wake();
+        self.closed.wake();
+        self.shutdown.wake();
         if let Some(x) = self.incoming_uni_streams_reader.take() {
             x.wake();
         }
@@ -806,6 +1000,9 @@ where
         if let Some(x) = self.datagram_reader.take() {
             x.wake();
         }
+        if let Some(x) = self.frame_reader.take() {
+            x.wake();
+        }
         for (_, x) in self.finishing.drain() {
             let _ = x.send(Some(WriteError::ConnectionClosed(reason.clone())));
         }
@@ -882,3 +1079,17 @@ pub enum SendDatagramError {
     #[error(display = "connection closed: {}", _0)]
     ConnectionClosed(ConnectionError),
 }
+
+/// Errors that can arise when sending an application-defined frame
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum SendFrameError {
+    /// No `FrameExtension` is registered for the frame's type
+    #[error(display = "unregistered frame type")]
+    UnknownType,
+    /// The frame is too large to fit in a packet
+    #[error(display = "frame too large")]
+    TooLarge,
+    /// The connection was closed
+    #[error(display = "connection closed: {}", _0)]
+    ConnectionClosed(ConnectionError),
+}
@@ -1,13 +1,13 @@
 use std::{
     collections::{HashMap, VecDeque},
     future::Future,
-    io,
+    io, mem,
     net::{SocketAddr, SocketAddrV6},
     pin::Pin,
     str,
     sync::{Arc, Mutex},
     task::{Context, Poll, Waker},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
@@ -15,13 +15,17 @@ use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
-use proto::{self as proto, generic::ClientConfig, ConnectError, ConnectionHandle, DatagramEvent};
+use proto::{
+    self as proto, generic::ClientConfig, ConnectError, ConnectionHandle, DatagramEvent,
+    EcnCodepoint, EndpointStats,
+};
 
 use crate::{
     broadcast::{self, Broadcast},
     builders::EndpointBuilder,
     connection::{Connecting, ConnectionDriver, ConnectionRef},
-    udp::UdpSocket,
+    platform::{RecvMeta, UdpState},
+    udp::{UdpSocket, BATCH_SIZE},
     ConnectionEvent, EndpointEvent, VarInt, IO_LOOP_BOUND,
 };
 
@@ -75,7 +79,7 @@ where
         server_name: &str,
     ) -> Result<Connecting<S>, ConnectError> {
         let mut endpoint = self.inner.lock().unwrap();
-        if endpoint.driver_lost {
+        if endpoint.driver_lost || endpoint.shutting_down {
             return Err(ConnectError::EndpointStopping);
         }
         let addr = if endpoint.ipv6 {
@@ -107,6 +111,15 @@ where
         self.inner.lock().unwrap().socket.local_addr()
     }
 
+    /// Statistics about the endpoint's activity since it was created
+    pub fn stats(&self) -> EndpointStats {
+        let endpoint = self.inner.lock().unwrap();
+        EndpointStats {
+            datagrams_sent: endpoint.datagrams_sent,
+            ..endpoint.inner.stats()
+        }
+    }
+
     /// Close all of this endpoint's connections immediately and cease accepting new connections.
     ///
     /// See `Connection::close` for details.
@@ -147,6 +160,50 @@ where
         })
         .await;
     }
+
+    /// Gracefully shut down the endpoint
+    ///
+    /// New connections are refused immediately, and the `Incoming` stream ends once connections
+    /// that were already being established have been yielded. Every open connection is notified via
+    /// `Connection::shutting_down` and given until `timeout` elapses to wrap up and close itself.
+    /// Connections still open at that point are closed with `error_code` and `reason`, as if by
+    /// `Endpoint::close`.
+    ///
+    /// Completes once every connection has been drained and all outstanding packets, including
+    /// those informing peers of closes, have been handed to the socket.
+    pub async fn shutdown(&self, timeout: Duration, error_code: VarInt, reason: &[u8]) {
+        {
+            let endpoint = &mut *self.inner.lock().unwrap();
+            endpoint.shutting_down = true;
+            endpoint.inner.reject_new_connections();
+            for sender in endpoint.connections.values() {
+                // Ignoring errors from dropped connections
+                let _ = sender.unbounded_send(ConnectionEvent::ShuttingDown);
+            }
+            if let Some(task) = endpoint.incoming_reader.take() {
+                task.wake();
+            }
+        }
+        if tokio::time::timeout(timeout, self.wait_idle())
+            .await
+            .is_err()
+        {
+            self.close(error_code, reason);
+        }
+
+        let mut state = broadcast::State::default();
+        futures::future::poll_fn(|cx| {
+            let endpoint = &mut *self.inner.lock().unwrap();
+            if endpoint.driver_lost
+                || (endpoint.connections.is_empty() && endpoint.outgoing.is_empty())
+            {
+                return Poll::Ready(());
+            }
+            endpoint.idle.register(cx, &mut state);
+            Poll::Pending
+        })
+        .await;
+    }
 }
 
 impl<S> Clone for Endpoint<S>
@@ -225,8 +282,12 @@ where
     S: proto::crypto::Session,
 {
     socket: UdpSocket,
+    /// Capabilities of the platform's UDP stack, shared with connections
+    udp_state: Arc<UdpState>,
     inner: proto::generic::Endpoint<S>,
     outgoing: VecDeque<proto::Transmit>,
+    /// Datagrams written to the socket, segments of a batch being counted individually
+    datagrams_sent: u64,
     incoming: VecDeque<Connecting<S>>,
     incoming_reader: Option<Waker>,
     /// Whether the `Incoming` stream has not yet been dropped
@@ -241,8 +302,13 @@ where
     ref_count: usize,
     /// Set if the endpoint has been manually closed
     close: Option<(VarInt, Bytes)>,
+    /// Set if a graceful shutdown is in progress
+    shutting_down: bool,
     driver_lost: bool,
+    /// Holds `recv_slots` equally sized slots for received messages
     recv_buf: Box<[u8]>,
+    /// Number of messages that may be received at once, at most `BATCH_SIZE`
+    recv_slots: usize,
     idle: Broadcast,
 }
 
@@ -252,32 +318,21 @@ where
 {
     fn drive_recv(&mut self, cx: &mut Context, now: Instant) -> Result<bool, io::Error> {
         let mut recvd = 0;
+        let mut meta = [RecvMeta::default(); BATCH_SIZE];
+        let meta = &mut meta[..self.recv_slots];
         loop {
-            match self.socket.poll_recv(cx, &mut self.recv_buf) {
-                Poll::Ready(Ok((n, addr, ecn))) => {
-                    match self
-                        .inner
-                        .handle(now, addr, ecn, (&self.recv_buf[0..n]).into())
-                    {
-                        Some((handle, DatagramEvent::NewConnection(conn))) => {
-                            let conn = self.create_connection(handle, conn);
-                            if self.incoming_live {
-                                self.incoming.push_back(conn);
-                                if let Some(task) = self.incoming_reader.take() {
-                                    task.wake();
-                                }
-                            }
+            match self.socket.poll_recv(cx, &mut self.recv_buf, meta) {
+                Poll::Ready(Ok(msgs)) => {
+                    // Taken so that datagrams can be borrowed while handling them
+                    let buf = mem::take(&mut self.recv_buf);
+                    let slot_len = buf.len() / meta.len();
+                    for (meta, slot) in meta.iter().zip(buf.chunks_exact(slot_len)).take(msgs) {
+                        for datagram in meta.datagrams(slot) {
+                            self.handle_datagram(now, meta.addr, meta.ecn, datagram);
+                            recvd += 1;
                         }
-                        Some((handle, DatagramEvent::ConnectionEvent(event))) => {
-                            // Ignoring errors from dropped connections that haven't yet been cleaned up
-                            let _ = self
-                                .connections
-                                .get_mut(&handle)
-                                .unwrap()
-                                .unbounded_send(ConnectionEvent::Proto(event));
-                        }
-                        None => {}
                     }
+                    self.recv_buf = buf;
                 }
                 Poll::Pending => {
                     break;
@@ -291,7 +346,6 @@ where
                     return Err(e);
                 }
             }
-            recvd += 1;
             if recvd >= IO_LOOP_BOUND {
                 return Ok(true);
             }
@@ -299,10 +353,39 @@ where
         Ok(false)
     }
 
+    fn handle_datagram(
+        &mut self,
+        now: Instant,
+        addr: SocketAddr,
+        ecn: Option<EcnCodepoint>,
+        datagram: &[u8],
+    ) {
+        match self.inner.handle(now, addr, ecn, datagram.into()) {
+            Some((handle, DatagramEvent::NewConnection(conn))) => {
+                let conn = self.create_connection(handle, conn);
+                if self.incoming_live {
+                    self.incoming.push_back(conn);
+                    if let Some(task) = self.incoming_reader.take() {
+                        task.wake();
+                    }
+                }
+            }
+            Some((handle, DatagramEvent::ConnectionEvent(event))) => {
+                // Ignoring errors from dropped connections that haven't yet been cleaned up
+                let _ = self
+                    .connections
+                    .get_mut(&handle)
+                    .unwrap()
+                    .unbounded_send(ConnectionEvent::Proto(event));
+            }
+            None => {}
+        }
+    }
+
     fn drive_send(&mut self, cx: &mut Context) -> Result<bool, io::Error> {
         let mut calls = 0;
         loop {
-            while self.outgoing.len() < crate::udp::BATCH_SIZE {
+            while self.outgoing.len() < BATCH_SIZE {
                 match self.inner.poll_transmit() {
                     Some(x) => self.outgoing.push_back(x),
                     None => break,
@@ -311,9 +394,23 @@ where
             if self.outgoing.is_empty() {
                 return Ok(false);
             }
-            match self.socket.poll_send(cx, self.outgoing.as_slices().0) {
+            match self
+                .socket
+                .poll_send(cx, &self.udp_state, self.outgoing.as_slices().0)
+            {
                 Poll::Ready(Ok(n)) => {
-                    self.outgoing.drain(..n);
+                    self.datagrams_sent += self
+                        .outgoing
+                        .drain(..n)
+                        .map(|t| match t.segment_size {
+                            Some(size) => t.contents.chunks(size).count() as u64,
+                            None => 1,
+                        })
+                        .sum::<u64>();
+                    if self.outgoing.is_empty() && self.connections.is_empty() {
+                        // Wake anyone waiting for the final packets to be flushed
+                        self.idle.wake();
+                    }
                     calls += 1;
                     if calls == IO_LOOP_BOUND {
                         return Ok(true);
@@ -376,9 +473,19 @@ where
             })
             .unwrap();
         }
+        if self.shutting_down {
+            send.unbounded_send(ConnectionEvent::ShuttingDown).unwrap();
+        }
         self.connections.insert(handle, send);
         let (connected_send, connected_recv) = oneshot::channel();
-        let conn = ConnectionRef::new(handle, conn, self.sender.clone(), recv, connected_send);
+        let conn = ConnectionRef::new(
+            handle,
+            conn,
+            self.sender.clone(),
+            recv,
+            connected_send,
+            self.udp_state.clone(),
+        );
         tokio::spawn(ConnectionDriver(conn.clone()));
         Connecting::new(conn, connected_recv)
     }
@@ -416,7 +523,7 @@ where
         } else if let Some(conn) = endpoint.incoming.pop_front() {
             endpoint.inner.accept();
             Poll::Ready(Some(conn))
-        } else if endpoint.close.is_some() {
+        } else if endpoint.close.is_some() || endpoint.shutting_down {
             Poll::Ready(None)
         } else {
             endpoint.incoming_reader = Some(cx.waker().clone());
@@ -445,15 +552,29 @@ where
     S: proto::crypto::Session,
 {
     pub(crate) fn new(socket: UdpSocket, inner: proto::generic::Endpoint<S>, ipv6: bool) -> Self {
-        let recv_buf = vec![0; inner.config().get_max_udp_payload_size().min(64 * 1024) as usize];
+        let udp_state = Arc::new(UdpState::default());
+        let max_udp_payload_size = inner
+            .config()
+            .get_max_udp_payload_size()
+            .min(MAX_RECV_MESSAGE_SIZE) as usize;
+        // Each slot must fit a message coalesced from as many datagrams as receive offload allows,
+        // which the kernel never makes larger than `MAX_RECV_MESSAGE_SIZE`.
+        let slot_len =
+            (max_udp_payload_size * udp_state.gro_segments()).min(MAX_RECV_MESSAGE_SIZE as usize);
+        // Coalesced messages carry many datagrams each, so fewer slots suffice to receive about
+        // `BATCH_SIZE` datagrams at once.
+        let recv_slots = (BATCH_SIZE * max_udp_payload_size / slot_len).max(1);
+        let recv_buf = vec![0; slot_len * recv_slots];
         let (sender, events) = mpsc::unbounded();
         Self(Arc::new(Mutex::new(EndpointInner {
             socket,
+            udp_state,
             inner,
             ipv6,
             sender,
             events,
             outgoing: VecDeque::new(),
+            datagrams_sent: 0,
             incoming: VecDeque::new(),
             incoming_live: true,
             incoming_reader: None,
@@ -461,8 +582,10 @@ where
             connections: HashMap::new(),
             ref_count: 0,
             close: None,
+            shutting_down: false,
             driver_lost: false,
             recv_buf: recv_buf.into(),
+            recv_slots,
             idle: Broadcast::new(),
         })))
     }
@@ -506,3 +629,6 @@ where
         &self.0
     }
 }
+
+/// Largest message the kernel delivers, even when coalescing datagrams with receive offload
+const MAX_RECV_MESSAGE_SIZE: u64 = 64 * 1024;
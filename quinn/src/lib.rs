@@ -59,20 +59,22 @@ mod streams;
 mod udp;
 
 pub use proto::{
-    crypto, ApplicationClose, Certificate, CertificateChain, ConnectError, ConnectionClose,
-    ConnectionError, ParseError, PrivateKey, Transmit, TransportConfig, VarInt,
+    crypto, ApplicationClose, ApplicationFrame, Certificate, CertificateChain, ConnectError,
+    ConnectionAttempt, ConnectionClose, ConnectionError, Dir, EndpointStats, FileSessionStore,
+    FilterAction, FrameExtension, IncomingFilter, MemorySessionStore, ParseError, PrivateKey,
+    ResetError, SessionStore, SniResolver, Transmit, TransportConfig, VarInt,
 };
 
 pub use crate::builders::EndpointError;
-pub use crate::connection::{SendDatagramError, ZeroRttAccepted};
+pub use crate::connection::{SendDatagramError, SendFrameError, ZeroRttAccepted};
 pub use crate::streams::{ReadError, ReadExactError, ReadToEndError, WriteError};
 
 /// Types that are generic over the crypto protocol implementation
 pub mod generic {
     pub use crate::builders::{ClientConfigBuilder, EndpointBuilder, ServerConfigBuilder};
     pub use crate::connection::{
-        Connecting, Connection, Datagrams, IncomingBiStreams, IncomingUniStreams, NewConnection,
-        OpenBi, OpenUni,
+        ApplicationFrames, Closed, Connecting, Connection, Datagrams, IncomingBiStreams,
+        IncomingUniStreams, NewConnection, OpenBi, OpenUni, ShuttingDown,
     };
     pub use crate::endpoint::{Endpoint, Incoming};
     pub use crate::streams::{Read, ReadExact, ReadToEnd, RecvStream, SendStream};
@@ -96,6 +98,10 @@ mod rustls_impls {
     /// A `ServerConfigBuilder` using rustls for the cryptography protocol
     pub type ServerConfigBuilder = generic::ServerConfigBuilder<TlsSession>;
 
+    /// An `ApplicationFrames` using rustls for the cryptography protocol
+    pub type ApplicationFrames = generic::ApplicationFrames<TlsSession>;
+    /// A `Closed` using rustls for the cryptography protocol
+    pub type Closed = generic::Closed<TlsSession>;
     /// A `Connecting` using rustls for the cryptography protocol
     pub type Connecting = generic::Connecting<TlsSession>;
     /// A `Connection` using rustls for the cryptography protocol
@@ -112,6 +118,8 @@ mod rustls_impls {
     pub type OpenBi = generic::OpenBi<TlsSession>;
     /// An `OpenUni` using rustls for the cryptography protocol
     pub type OpenUni = generic::OpenUni<TlsSession>;
+    /// A `ShuttingDown` using rustls for the cryptography protocol
+    pub type ShuttingDown = generic::ShuttingDown<TlsSession>;
 
     /// An `Endpoint` using rustls for the cryptography protocol
     pub type Endpoint = generic::Endpoint<TlsSession>;
@@ -142,6 +150,8 @@ enum ConnectionEvent {
         error_code: VarInt,
         reason: bytes::Bytes,
     },
+    /// The endpoint has begun a graceful shutdown
+    ShuttingDown,
     Proto(proto::ConnectionEvent),
 }
 
@@ -1,18 +1,25 @@
-use std::{io, net::SocketAddr};
+use std::io;
 
 use mio::net::UdpSocket;
 
-use proto::{EcnCodepoint, Transmit};
+use proto::Transmit;
+
+use super::RecvMeta;
 
 impl super::UdpExt for UdpSocket {
     fn init_ext(&self) -> io::Result<()> {
         Ok(())
     }
 
-    fn send_ext(&self, transmits: &[Transmit]) -> io::Result<usize> {
+    fn send_ext(&self, _state: &super::UdpState, transmits: &[Transmit]) -> io::Result<usize> {
         let mut sent = 0;
         for transmit in transmits {
-            match self.send_to(&transmit.contents, &transmit.destination) {
+            let segment_size = transmit.segment_size.unwrap_or(transmit.contents.len());
+            let result = transmit
+                .contents
+                .chunks(segment_size)
+                .try_for_each(|datagram| self.send_to(datagram, &transmit.destination).map(|_| ()));
+            match result {
                 Ok(_) => {
                     sent += 1;
                 }
@@ -30,7 +37,25 @@ impl super::UdpExt for UdpSocket {
         Ok(sent)
     }
 
-    fn recv_ext(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Option<EcnCodepoint>)> {
-        self.recv_from(buf).map(|(x, y)| (x, y, None))
+    fn recv_ext(&self, buf: &mut [u8], meta: &mut [RecvMeta]) -> io::Result<usize> {
+        let buf = &mut buf[..buf.len() / meta.len()];
+        let (len, addr) = self.recv_from(buf)?;
+        meta[0] = RecvMeta {
+            addr,
+            len,
+            stride: len,
+            ecn: None,
+        };
+        Ok(1)
     }
 }
+
+/// Segmentation offload is unsupported
+pub fn max_gso_segments() -> usize {
+    1
+}
+
+/// Receive offload is unsupported
+pub fn gro_segments() -> usize {
+    1
+}
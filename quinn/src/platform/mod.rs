@@ -1,6 +1,10 @@
 //! Uniform interface to send/recv UDP packets with ECN information.
 use proto::{EcnCodepoint, Transmit};
-use std::{io, net::SocketAddr};
+use std::{
+    io,
+    net::{Ipv6Addr, SocketAddr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 #[cfg(unix)]
 mod cmsg;
@@ -11,8 +15,95 @@ mod unix;
 #[cfg(not(unix))]
 mod fallback;
 
+#[cfg(unix)]
+use unix::{gro_segments, max_gso_segments};
+
+#[cfg(not(unix))]
+use fallback::{gro_segments, max_gso_segments};
+
 pub trait UdpExt {
     fn init_ext(&self) -> io::Result<()>;
-    fn send_ext(&self, transmits: &[Transmit]) -> io::Result<usize>;
-    fn recv_ext(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Option<EcnCodepoint>)>;
+    fn send_ext(&self, state: &UdpState, transmits: &[Transmit]) -> io::Result<usize>;
+    /// Receive up to `meta.len()` messages, returning how many were received
+    ///
+    /// `buf` is divided into `meta.len()` equally sized slots, the `i`th of which receives the
+    /// message described by `meta[i]`.
+    fn recv_ext(&self, buf: &mut [u8], meta: &mut [RecvMeta]) -> io::Result<usize>;
+}
+
+/// Metadata describing a received message
+#[derive(Debug, Copy, Clone)]
+pub struct RecvMeta {
+    /// The address the message was received from
+    pub addr: SocketAddr,
+    /// Total length of the message, 0 if it was truncated and should be ignored
+    pub len: usize,
+    /// Size of each datagram coalesced into the message, all but the last of which are full-sized
+    pub stride: usize,
+    /// Explicit congestion notification bits set on the message
+    pub ecn: Option<EcnCodepoint>,
+}
+
+impl RecvMeta {
+    /// Split the message received into `buf` into the datagrams coalesced into it
+    pub fn datagrams<'a>(&self, buf: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+        let stride = if self.stride == 0 {
+            self.len
+        } else {
+            self.stride
+        };
+        buf[..self.len].chunks(stride.max(1))
+    }
+}
+
+impl Default for RecvMeta {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+            len: 0,
+            stride: 0,
+            ecn: None,
+        }
+    }
+}
+
+/// Platform capabilities shared by an endpoint's socket and connections
+#[derive(Debug)]
+pub struct UdpState {
+    max_gso_segments: AtomicUsize,
+    gro_segments: usize,
+}
+
+impl Default for UdpState {
+    fn default() -> Self {
+        Self {
+            max_gso_segments: AtomicUsize::new(max_gso_segments()),
+            gro_segments: gro_segments(),
+        }
+    }
+}
+
+impl UdpState {
+    /// Maximum number of datagrams that may be sent in a single segmented `Transmit`
+    ///
+    /// 1 if segmentation offload is unsupported.
+    pub fn max_gso_segments(&self) -> usize {
+        self.max_gso_segments.load(Ordering::Relaxed)
+    }
+
+    /// Maximum number of datagrams the kernel may coalesce into a single received message
+    ///
+    /// 1 if receive offload is unsupported.
+    pub fn gro_segments(&self) -> usize {
+        self.gro_segments
+    }
+
+    /// Stop producing segmented transmits, e.g. because the network interface can't send them
+    #[cfg_attr(
+        any(not(unix), target_os = "macos", target_os = "ios"),
+        allow(dead_code)
+    )]
+    fn disable_gso(&self) {
+        self.max_gso_segments.store(1, Ordering::Relaxed);
+    }
 }
@@ -8,8 +8,9 @@ use std::{
 
 use mio::net::UdpSocket;
 use proto::{EcnCodepoint, Transmit};
+use tracing::warn;
 
-use super::cmsg;
+use super::{cmsg, RecvMeta, UdpState};
 
 #[cfg(target_os = "freebsd")]
 type IpTosTy = libc::c_uchar;
@@ -28,7 +29,11 @@ impl super::UdpExt for UdpSocket {
             mem::size_of::<libc::sockaddr_in6>()
         );
         assert!(
-            CMSG_LEN >= unsafe { libc::CMSG_SPACE(mem::size_of::<libc::c_int>() as _) as usize }
+            CMSG_LEN
+                >= unsafe {
+                    libc::CMSG_SPACE(mem::size_of::<libc::c_int>() as _) as usize
+                        + libc::CMSG_SPACE(mem::size_of::<u16>() as _) as usize
+                }
         );
         assert!(
             mem::align_of::<libc::cmsghdr>() <= mem::align_of::<cmsg::Aligned<[u8; 0]>>(),
@@ -98,11 +103,28 @@ impl super::UdpExt for UdpSocket {
                 return Err(io::Error::last_os_error());
             }
         }
+        #[cfg(target_os = "linux")]
+        {
+            // Receive buffers are only sized for coalesced messages if offload is supported
+            if gro_segments() > 1 {
+                let on: libc::c_int = 1;
+                // Failure just means datagrams are received individually
+                unsafe {
+                    libc::setsockopt(
+                        self.as_raw_fd(),
+                        libc::SOL_UDP,
+                        libc::UDP_GRO,
+                        &on as *const _ as _,
+                        mem::size_of_val(&on) as _,
+                    );
+                }
+            }
+        }
         Ok(())
     }
 
     #[cfg(not(any(target_os = "macos", target_os = "ios")))]
-    fn send_ext(&self, transmits: &[Transmit]) -> io::Result<usize> {
+    fn send_ext(&self, state: &UdpState, transmits: &[Transmit]) -> io::Result<usize> {
         use crate::udp::BATCH_SIZE;
         let mut msgs: [libc::mmsghdr; BATCH_SIZE] = unsafe { mem::zeroed() };
         let mut iovecs: [libc::iovec; BATCH_SIZE] = unsafe { mem::zeroed() };
@@ -129,6 +151,22 @@ impl super::UdpExt for UdpSocket {
                 if e.kind() == io::ErrorKind::Interrupted {
                     continue;
                 }
+                if e.raw_os_error() == Some(libc::EIO) && transmits[0].segment_size.is_some() {
+                    // Some network interfaces can't offload segmentation, which is only reported
+                    // when sending. Stop producing segmented transmits and send this one's
+                    // datagrams individually, leaving any that still fail to loss recovery.
+                    warn!("disabling segmentation offload: {}", e);
+                    state.disable_gso();
+                    let datagrams = split_segments(&transmits[0]);
+                    let mut remaining = &datagrams[..];
+                    while !remaining.is_empty() {
+                        match self.send_ext(state, remaining) {
+                            Ok(n) => remaining = &remaining[n..],
+                            Err(_) => break,
+                        }
+                    }
+                    return Ok(1);
+                }
                 return Err(e);
             }
             return Ok(n as usize);
@@ -136,7 +174,7 @@ impl super::UdpExt for UdpSocket {
     }
 
     #[cfg(any(target_os = "macos", target_os = "ios"))]
-    fn send_ext(&self, transmits: &[Transmit]) -> io::Result<usize> {
+    fn send_ext(&self, _state: &UdpState, transmits: &[Transmit]) -> io::Result<usize> {
         let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
         let mut iov: libc::iovec = unsafe { mem::zeroed() };
         let mut ctrl = cmsg::Aligned(MaybeUninit::uninit());
@@ -163,21 +201,63 @@ impl super::UdpExt for UdpSocket {
         Ok(sent)
     }
 
-    fn recv_ext(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Option<EcnCodepoint>)> {
-        let mut name = MaybeUninit::<libc::sockaddr_storage>::uninit();
-        let mut iov = libc::iovec {
-            iov_base: buf.as_ptr() as *mut _,
-            iov_len: buf.len(),
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    fn recv_ext(&self, buf: &mut [u8], meta: &mut [RecvMeta]) -> io::Result<usize> {
+        use crate::udp::BATCH_SIZE;
+        let mut names = [MaybeUninit::<libc::sockaddr_storage>::uninit(); BATCH_SIZE];
+        let mut ctrls = [cmsg::Aligned(MaybeUninit::<[u8; CMSG_LEN]>::uninit()); BATCH_SIZE];
+        let mut iovs: [libc::iovec; BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut hdrs: [libc::mmsghdr; BATCH_SIZE] = unsafe { mem::zeroed() };
+        let slot_len = buf.len() / meta.len();
+        let count = meta.len().min(BATCH_SIZE);
+        for (i, slot) in buf.chunks_exact_mut(slot_len).take(count).enumerate() {
+            prepare_recv(
+                slot,
+                &mut names[i],
+                &mut ctrls[i],
+                &mut iovs[i],
+                &mut hdrs[i].msg_hdr,
+            );
+        }
+        let n = loop {
+            let n = unsafe {
+                libc::recvmmsg(
+                    self.as_raw_fd(),
+                    hdrs.as_mut_ptr(),
+                    count as _,
+                    0,
+                    ptr::null_mut(),
+                )
+            };
+            if n == -1 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+            break n as usize;
         };
+        for (meta, (name, hdr)) in meta.iter_mut().zip(names.iter().zip(hdrs.iter())).take(n) {
+            *meta = decode_recv(name, &hdr.msg_hdr, hdr.msg_len as usize);
+        }
+        Ok(n)
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    fn recv_ext(&self, buf: &mut [u8], meta: &mut [RecvMeta]) -> io::Result<usize> {
+        let mut name = MaybeUninit::<libc::sockaddr_storage>::uninit();
         let mut ctrl = cmsg::Aligned(MaybeUninit::<[u8; CMSG_LEN]>::uninit());
+        let mut iov: libc::iovec = unsafe { mem::zeroed() };
         let mut hdr = unsafe { mem::zeroed::<libc::msghdr>() };
-        hdr.msg_name = name.as_mut_ptr() as _;
-        hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
-        hdr.msg_iov = &mut iov;
-        hdr.msg_iovlen = 1;
-        hdr.msg_control = ctrl.0.as_mut_ptr() as _;
-        hdr.msg_controllen = CMSG_LEN as _;
-        hdr.msg_flags = 0;
+        let slot_len = buf.len() / meta.len();
+        prepare_recv(
+            &mut buf[..slot_len],
+            &mut name,
+            &mut ctrl,
+            &mut iov,
+            &mut hdr,
+        );
         let n = loop {
             let n = unsafe { libc::recvmsg(self.as_raw_fd(), &mut hdr, 0) };
             if n == -1 {
@@ -192,39 +272,80 @@ impl super::UdpExt for UdpSocket {
             }
             break n;
         };
-        let name = unsafe { name.assume_init() };
-        let ecn_bits = match unsafe { cmsg::Iter::new(&hdr).next() } {
-            Some(cmsg) => match (cmsg.cmsg_level, cmsg.cmsg_type) {
-                // FreeBSD uses IP_RECVTOS here, and we can be liberal because cmsgs are opt-in.
-                (libc::IPPROTO_IP, libc::IP_TOS) | (libc::IPPROTO_IP, libc::IP_RECVTOS) => unsafe {
-                    cmsg::decode::<u8>(cmsg)
-                },
-                (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => unsafe {
-                    // Temporary hack around broken macos ABI. Remove once upstream fixes it.
-                    // https://bugreport.apple.com/web/?problemID=48761855
-                    if cfg!(target_os = "macos")
-                        && cmsg.cmsg_len as usize
-                            == libc::CMSG_LEN(mem::size_of::<u8>() as _) as usize
-                    {
-                        cmsg::decode::<u8>(cmsg)
-                    } else {
-                        cmsg::decode::<libc::c_int>(cmsg) as u8
-                    }
-                },
-                _ => 0,
+        meta[0] = decode_recv(&name, &hdr, n as usize);
+        Ok(1)
+    }
+}
+
+fn prepare_recv(
+    buf: &mut [u8],
+    name: &mut MaybeUninit<libc::sockaddr_storage>,
+    ctrl: &mut cmsg::Aligned<MaybeUninit<[u8; CMSG_LEN]>>,
+    iov: &mut libc::iovec,
+    hdr: &mut libc::msghdr,
+) {
+    iov.iov_base = buf.as_mut_ptr() as *mut _;
+    iov.iov_len = buf.len();
+    hdr.msg_name = name.as_mut_ptr() as _;
+    hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+    hdr.msg_iov = iov;
+    hdr.msg_iovlen = 1;
+    hdr.msg_control = ctrl.0.as_mut_ptr() as _;
+    hdr.msg_controllen = CMSG_LEN as _;
+    hdr.msg_flags = 0;
+}
+
+fn decode_recv(
+    name: &MaybeUninit<libc::sockaddr_storage>,
+    hdr: &libc::msghdr,
+    len: usize,
+) -> RecvMeta {
+    let name = unsafe { name.assume_init() };
+    let mut ecn_bits = 0;
+    let mut stride = len;
+    for cmsg in unsafe { cmsg::Iter::new(hdr) } {
+        match (cmsg.cmsg_level, cmsg.cmsg_type) {
+            // FreeBSD uses IP_RECVTOS here, and we can be liberal because cmsgs are opt-in.
+            (libc::IPPROTO_IP, libc::IP_TOS) | (libc::IPPROTO_IP, libc::IP_RECVTOS) => unsafe {
+                ecn_bits = cmsg::decode::<u8>(cmsg);
             },
-            None => 0,
-        };
-        let addr = match libc::c_int::from(name.ss_family) {
-            libc::AF_INET => unsafe { SocketAddr::V4(ptr::read(&name as *const _ as _)) },
-            libc::AF_INET6 => unsafe { SocketAddr::V6(ptr::read(&name as *const _ as _)) },
-            _ => unreachable!(),
-        };
-        Ok((n as usize, addr, EcnCodepoint::from_bits(ecn_bits)))
+            (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => unsafe {
+                // Temporary hack around broken macos ABI. Remove once upstream fixes it.
+                // https://bugreport.apple.com/web/?problemID=48761855
+                if cfg!(target_os = "macos")
+                    && cmsg.cmsg_len as usize == libc::CMSG_LEN(mem::size_of::<u8>() as _) as usize
+                {
+                    ecn_bits = cmsg::decode::<u8>(cmsg);
+                } else {
+                    ecn_bits = cmsg::decode::<libc::c_int>(cmsg) as u8;
+                }
+            },
+            #[cfg(target_os = "linux")]
+            (libc::SOL_UDP, libc::UDP_GRO) => unsafe {
+                stride = cmsg::decode::<libc::c_int>(cmsg) as usize;
+            },
+            _ => {}
+        }
+    }
+    let addr = match libc::c_int::from(name.ss_family) {
+        libc::AF_INET => unsafe { SocketAddr::V4(ptr::read(&name as *const _ as _)) },
+        libc::AF_INET6 => unsafe { SocketAddr::V6(ptr::read(&name as *const _ as _)) },
+        _ => unreachable!(),
+    };
+    RecvMeta {
+        addr,
+        // Truncated messages can't be safely processed
+        len: if hdr.msg_flags & libc::MSG_TRUNC != 0 {
+            0
+        } else {
+            len
+        },
+        stride,
+        ecn: EcnCodepoint::from_bits(ecn_bits),
     }
 }
 
-const CMSG_LEN: usize = 24;
+const CMSG_LEN: usize = 88;
 
 fn prepare_msg(
     transmit: &Transmit,
@@ -253,5 +374,87 @@ fn prepare_msg(
     } else {
         encoder.push(libc::IPPROTO_IPV6, libc::IPV6_TCLASS, ecn);
     }
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(segment_size) = transmit.segment_size {
+            encoder.push(libc::SOL_UDP, libc::UDP_SEGMENT, segment_size as u16);
+        }
+    }
     encoder.finish();
 }
+
+/// Split a segmented transmit into one transmit per datagram
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+fn split_segments(transmit: &Transmit) -> Vec<Transmit> {
+    let segment_size = transmit.segment_size.unwrap();
+    transmit
+        .contents
+        .chunks(segment_size)
+        .map(|datagram| Transmit {
+            destination: transmit.destination,
+            ecn: transmit.ecn,
+            contents: datagram.into(),
+            segment_size: None,
+        })
+        .collect()
+}
+
+/// Maximum number of segments the kernel sends or receives as a single message
+#[cfg(target_os = "linux")]
+const UDP_MAX_SEGMENTS: usize = 64;
+
+/// Determine whether the kernel supports UDP segmentation offload
+#[cfg(target_os = "linux")]
+pub fn max_gso_segments() -> usize {
+    const GSO_SIZE: libc::c_int = 1500;
+    if probe_udp_option(libc::UDP_SEGMENT, GSO_SIZE) {
+        UDP_MAX_SEGMENTS
+    } else {
+        1
+    }
+}
+
+/// Determine whether the kernel supports UDP receive offload
+#[cfg(target_os = "linux")]
+pub fn gro_segments() -> usize {
+    if probe_udp_option(libc::UDP_GRO, 1) {
+        UDP_MAX_SEGMENTS
+    } else {
+        1
+    }
+}
+
+/// Check whether a UDP-level socket option can be set
+///
+/// Probes on a throwaway socket, as the options affect all subsequent sends or receives.
+#[cfg(target_os = "linux")]
+fn probe_udp_option(option: libc::c_int, value: libc::c_int) -> bool {
+    let socket = match std::net::UdpSocket::bind("[::]:0")
+        .or_else(|_| std::net::UdpSocket::bind("127.0.0.1:0"))
+    {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_UDP,
+            option,
+            &value as *const _ as _,
+            mem::size_of_val(&value) as _,
+        )
+    };
+    rc != -1
+}
+
+/// Segmentation offload is only supported on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn max_gso_segments() -> usize {
+    1
+}
+
+/// Receive offload is only supported on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn gro_segments() -> usize {
+    1
+}
@@ -14,7 +14,7 @@ use futures::{
     io::{AsyncRead, AsyncWrite},
     ready, FutureExt,
 };
-use proto::{ConnectionError, StreamId};
+use proto::{ConnectionError, ResetError, StreamId};
 
 use crate::{connection::ConnectionRef, VarInt};
 
@@ -149,6 +149,39 @@ where
         conn.wake();
     }
 
+    /// Close the send stream, guaranteeing delivery of the first `reliable_size` bytes written
+    ///
+    /// Like `reset`, but data preceding `reliable_size` continues to be retransmitted until
+    /// acknowledged, and the peer can read it before learning of the reset. Fails if the peer
+    /// doesn't support reliable stream resets or fewer than `reliable_size` bytes were written.
+    ///
+    /// May be called on a stream that was already finished or reset, e.g. to lower an earlier
+    /// reliable size. The reliable size can never be raised by a later reset.
+    pub fn reset_at(&mut self, error_code: VarInt, reliable_size: u64) -> Result<(), ResetError> {
+        let mut conn = self.conn.lock().unwrap();
+        if self.is_0rtt && conn.check_0rtt().is_err() {
+            return Err(ResetError::UnknownStream);
+        }
+        conn.inner
+            .reset_at(self.stream, error_code, reliable_size)?;
+        conn.wake();
+        Ok(())
+    }
+
+    /// Set the priority of the send stream
+    ///
+    /// Every send stream has an initial priority of 0. Buffered data from streams with a higher
+    /// priority is transmitted before data from streams with a lower priority, while streams of
+    /// equal priority take turns.
+    pub fn set_priority(&self, priority: i32) -> Result<(), UnknownStream> {
+        let mut conn = self.conn.lock().unwrap();
+        if self.is_0rtt && conn.check_0rtt().is_err() {
+            return Err(UnknownStream {});
+        }
+        conn.inner.set_priority(self.stream, priority)?;
+        Ok(())
+    }
+
     #[doc(hidden)]
     pub fn id(&self) -> StreamId {
         self.stream
@@ -407,6 +440,11 @@ where
     pub fn is_0rtt(&self) -> bool {
         self.is_0rtt
     }
+
+    #[doc(hidden)]
+    pub fn id(&self) -> StreamId {
+        self.stream
+    }
 }
 
 /// Future produced by `read_to_end`
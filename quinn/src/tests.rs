@@ -84,6 +84,136 @@ async fn close_endpoint() {
     }
 }
 
+#[test]
+fn closed_future() {
+    let _guard = subscribe();
+    let mut runtime = rt_basic();
+    let (endpoint, mut incoming) = runtime.enter(endpoint);
+    runtime.spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        new_conn.connection.close(42u32.into(), b"bye");
+    });
+    runtime.block_on(async move {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .expect("connect");
+        let conn = new_conn.connection;
+        match conn.closed().await {
+            crate::ConnectionError::ApplicationClosed(close) => {
+                assert_eq!(close.error_code, 42u32.into());
+                assert_eq!(&close.reason[..], b"bye");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert!(matches!(
+            conn.close_reason(),
+            Some(crate::ConnectionError::ApplicationClosed(_))
+        ));
+    });
+}
+
+#[test]
+fn endpoint_stats() {
+    let _guard = subscribe();
+    let mut runtime = rt_basic();
+    let (endpoint, mut incoming) = runtime.enter(endpoint);
+    runtime.spawn(async move {
+        let _new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+    });
+    runtime.block_on(async move {
+        endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .expect("connect");
+        let stats = endpoint.stats();
+        // Both sides of the handshake are sent through the same endpoint
+        assert!(stats.datagrams_sent >= 2);
+        assert_eq!(stats.stateless_datagrams_sent, 0);
+    });
+}
+
+#[test]
+fn graceful_shutdown() {
+    let _guard = subscribe();
+    let mut runtime = rt_basic();
+    let (endpoint, mut incoming) = runtime.enter(endpoint);
+    runtime.spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        new_conn.connection.shutting_down().await;
+        new_conn.connection.close(7u32.into(), b"done");
+    });
+    runtime.block_on(async move {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .expect("connect");
+        let start = Instant::now();
+        endpoint
+            .shutdown(Duration::from_secs(10), 0u32.into(), b"")
+            .await;
+        assert!(start.elapsed() < Duration::from_secs(10));
+        match new_conn.connection.close_reason() {
+            Some(crate::ConnectionError::ApplicationClosed(close)) => {
+                assert_eq!(close.error_code, 7u32.into());
+            }
+            x => panic!("unexpected close reason: {:?}", x),
+        }
+        assert!(endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .is_err());
+    });
+}
+
+#[test]
+fn graceful_shutdown_timeout() {
+    let _guard = subscribe();
+    let mut runtime = rt_basic();
+    let (endpoint, mut incoming) = runtime.enter(endpoint);
+    runtime.spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        // Ignore the shutdown signal, holding the connection open
+        new_conn.connection.closed().await;
+    });
+    runtime.block_on(async move {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .expect("connect");
+        endpoint
+            .shutdown(Duration::from_millis(100), 0u32.into(), b"")
+            .await;
+        assert!(matches!(
+            new_conn.connection.close_reason(),
+            Some(crate::ConnectionError::LocallyClosed)
+        ));
+    });
+}
+
 #[test]
 fn local_addr() {
     let socket = UdpSocket::bind("[::1]:0").unwrap();
@@ -181,6 +311,120 @@ async fn accept_after_close() {
     assert!(receiver.connection.open_uni().await.is_err());
 }
 
+#[tokio::test]
+async fn reset_at() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    const MSG: &[u8] = b"reliable";
+    tokio::spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        let mut s = new_conn.connection.open_uni().await.unwrap();
+        s.write_all(MSG).await.unwrap();
+        s.write_all(&[0; 4096]).await.unwrap();
+        s.reset_at(7u32.into(), MSG.len() as u64).unwrap();
+        // Keep the connection alive until the peer is done reading
+        new_conn.connection.closed().await;
+    });
+
+    let mut new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let mut stream = new_conn
+        .uni_streams
+        .next()
+        .await
+        .expect("incoming streams")
+        .expect("missing stream");
+    let mut received = Vec::new();
+    let mut buf = [0; 1024];
+    let error_code = loop {
+        match stream.read(&mut buf).await {
+            Ok(Some(n)) => received.extend_from_slice(&buf[..n]),
+            Ok(None) => panic!("stream finished instead of being reset"),
+            Err(crate::ReadError::Reset(code)) => break code,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    };
+    assert_eq!(error_code, 7u32.into());
+    assert_eq!(received, MSG);
+    new_conn.connection.close(0u32.into(), b"done");
+}
+
+#[test]
+fn recv_coalesced() {
+    use crate::platform::{RecvMeta, UdpExt, UdpState};
+
+    let _guard = subscribe();
+    let state = UdpState::default();
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+    let recv = mio::net::UdpSocket::bind(&addr).unwrap();
+    recv.init_ext().unwrap();
+    let send = mio::net::UdpSocket::bind(&addr).unwrap();
+    send.init_ext().unwrap();
+
+    // Full-sized datagrams followed by a short one, as segmentation offload produces
+    const SEGMENT_SIZE: usize = 1200;
+    let lens = [SEGMENT_SIZE, SEGMENT_SIZE, SEGMENT_SIZE, 100];
+    let mut contents = Vec::new();
+    for (i, &len) in lens.iter().enumerate() {
+        contents.extend(std::iter::repeat(i as u8).take(len));
+    }
+    let destination = recv.local_addr().unwrap();
+    let transmits = if state.max_gso_segments() > 1 {
+        vec![proto::Transmit {
+            destination,
+            ecn: None,
+            contents: contents.into(),
+            segment_size: Some(SEGMENT_SIZE),
+        }]
+    } else {
+        contents
+            .chunks(SEGMENT_SIZE)
+            .map(|x| proto::Transmit {
+                destination,
+                ecn: None,
+                contents: x.into(),
+                segment_size: None,
+            })
+            .collect()
+    };
+    assert_eq!(send.send_ext(&state, &transmits).unwrap(), transmits.len());
+
+    // Slots large enough for the datagrams to be received coalesced, if the platform supports it
+    let mut meta = [RecvMeta::default(); 4];
+    let mut buf = vec![0; meta.len() * 64 * 1024];
+    let slot_len = buf.len() / meta.len();
+    let mut received = Vec::new();
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while received.len() < lens.len() {
+        assert!(std::time::Instant::now() < deadline, "timed out");
+        let n = match recv.recv_ext(&mut buf, &mut meta) {
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+            Err(e) => panic!("receive failed: {}", e),
+        };
+        for (meta, slot) in meta.iter().zip(buf.chunks_exact(slot_len)).take(n) {
+            received.extend(meta.datagrams(slot).map(|x| x.to_vec()));
+        }
+    }
+    assert_eq!(received.len(), lens.len());
+    for (i, (datagram, &len)) in received.iter().zip(lens.iter()).enumerate() {
+        assert_eq!(datagram.len(), len);
+        assert!(datagram.iter().all(|&x| x == i as u8));
+    }
+}
+
 /// Construct an endpoint suitable for connecting to itself
 fn endpoint() -> (Endpoint, Incoming) {
     let mut endpoint = Endpoint::builder();
@@ -291,6 +535,183 @@ async fn zero_rtt() {
     endpoint.wait_idle().await;
 }
 
+/// Generate a self-signed certificate for `name`
+fn self_signed(name: &str) -> (crate::Certificate, crate::PrivateKey) {
+    let cert = rcgen::generate_simple_self_signed(vec![name.into()]).unwrap();
+    let key = crate::PrivateKey::from_der(&cert.serialize_private_key_der()).unwrap();
+    let cert = crate::Certificate::from_der(&cert.serialize_der().unwrap()).unwrap();
+    (cert, key)
+}
+
+/// Construct an endpoint which requires clients to authenticate with a certificate signed by
+/// `client_ca`, and which presents `client_cert` (if any) when connecting to itself
+fn mutual_tls_endpoint(
+    client_ca: crate::Certificate,
+    client_cert: Option<(crate::Certificate, crate::PrivateKey)>,
+) -> (Endpoint, Incoming) {
+    let mut endpoint = Endpoint::builder();
+
+    let (cert, key) = self_signed("localhost");
+    let mut server_config = ServerConfigBuilder::default();
+    server_config
+        .certificate(crate::CertificateChain::from_certs(vec![cert.clone()]), key)
+        .unwrap();
+    server_config.client_auth(vec![client_ca], true).unwrap();
+    endpoint.listen(server_config.build());
+
+    let mut client_config = ClientConfigBuilder::default();
+    client_config.add_certificate_authority(cert).unwrap();
+    if let Some((cert, key)) = client_cert {
+        client_config
+            .client_certificate(crate::CertificateChain::from_certs(vec![cert]), key)
+            .unwrap();
+    }
+    endpoint.default_client_config(client_config.build());
+
+    endpoint
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn mutual_tls() {
+    let _guard = subscribe();
+    let (client_cert, client_key) = self_signed("client");
+    let (endpoint, mut incoming) =
+        mutual_tls_endpoint(client_cert.clone(), Some((client_cert.clone(), client_key)));
+
+    let server = tokio::spawn(async move {
+        let new_conn = incoming.next().await.unwrap().await.expect("accept");
+        let chain = new_conn
+            .connection
+            .authentication_data()
+            .peer_certificates
+            .expect("missing client certificate");
+        let certs = chain.iter().collect::<Vec<_>>();
+        assert_eq!(certs.len(), 1);
+        assert_eq!(&certs[0].0[..], client_cert.as_der());
+        new_conn.connection.close(0u32.into(), b"done");
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    assert!(new_conn
+        .connection
+        .authentication_data()
+        .peer_certificates
+        .is_some());
+    server.await.unwrap();
+    endpoint.wait_idle().await;
+}
+
+#[tokio::test]
+async fn mutual_tls_required() {
+    let _guard = subscribe();
+    let (client_ca, _) = self_signed("client");
+    let (endpoint, mut incoming) = mutual_tls_endpoint(client_ca, None);
+
+    let server = tokio::spawn(async move {
+        assert!(incoming.next().await.unwrap().await.is_err());
+    });
+
+    // The client may consider the handshake complete before the server has checked its
+    // credentials, so only the outcome of the server's side is certain
+    if let Ok(new_conn) = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+    {
+        new_conn.connection.closed().await;
+    }
+    server.await.unwrap();
+    endpoint.wait_idle().await;
+}
+
+#[tokio::test]
+async fn sni_certificates() {
+    let _guard = subscribe();
+    let resolver = Arc::new(crate::SniResolver::new());
+    let (a_cert, a_key) = self_signed("a.example");
+    let (default_cert, default_key) = self_signed("default.example");
+    let (c_cert, c_key) = self_signed("c.example");
+    let chain = |cert: &crate::Certificate| crate::CertificateChain::from_certs(vec![cert.clone()]);
+    resolver.insert("a.example", chain(&a_cert), a_key).unwrap();
+    resolver
+        .set_default(chain(&default_cert), default_key)
+        .unwrap();
+
+    let mut endpoint = Endpoint::builder();
+    let mut server_config = ServerConfigBuilder::default();
+    server_config.certificate_resolver(resolver.clone());
+    endpoint.listen(server_config.build());
+    let mut client_config = ClientConfigBuilder::default();
+    client_config
+        .add_certificate_authority(a_cert.clone())
+        .unwrap()
+        .add_certificate_authority(default_cert.clone())
+        .unwrap()
+        .add_certificate_authority(c_cert.clone())
+        .unwrap();
+    endpoint.default_client_config(client_config.build());
+    let (endpoint, mut incoming) = endpoint
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+
+    tokio::spawn(async move {
+        while let Some(connecting) = incoming.next().await {
+            let name = connecting.server_name().expect("missing server name");
+            let new_conn = match connecting.await {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let mut s = new_conn.connection.open_uni().await.unwrap();
+            s.write_all(name.as_bytes()).await.unwrap();
+            s.finish().await.unwrap();
+        }
+    });
+
+    let connect = |name: &'static str| {
+        let conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), name)
+            .unwrap();
+        async move {
+            let mut new_conn = conn.await.expect("connect");
+            let peer = new_conn
+                .connection
+                .authentication_data()
+                .peer_certificates
+                .unwrap();
+            let seen = new_conn
+                .uni_streams
+                .next()
+                .await
+                .unwrap()
+                .unwrap()
+                .read_to_end(usize::max_value())
+                .await
+                .unwrap();
+            assert_eq!(seen, name.as_bytes());
+            peer.iter().next().unwrap().0.clone()
+        }
+    };
+
+    assert_eq!(connect("a.example").await, a_cert.as_der());
+    assert_eq!(connect("default.example").await, default_cert.as_der());
+    // Certificates can be added while the endpoint is running
+    resolver.insert("c.example", chain(&c_cert), c_key).unwrap();
+    assert_eq!(connect("c.example").await, c_cert.as_der());
+    // Once removed, the name falls back to the default certificate, which isn't valid for it
+    assert!(resolver.remove("a.example"));
+    assert!(endpoint
+        .connect(&endpoint.local_addr().unwrap(), "a.example")
+        .unwrap()
+        .await
+        .is_err());
+}
+
 #[test]
 fn echo_v6() {
     run_echo(
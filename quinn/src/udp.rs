@@ -8,9 +8,9 @@ use futures::ready;
 
 use tokio::io::PollEvented;
 
-use proto::{EcnCodepoint, Transmit};
+use proto::Transmit;
 
-use crate::platform::UdpExt;
+use crate::platform::{RecvMeta, UdpExt, UdpState};
 
 /// Tokio-compatible UDP socket with some useful specializations.
 ///
@@ -32,10 +32,11 @@ impl UdpSocket {
     pub fn poll_send(
         &self,
         cx: &mut Context,
+        state: &UdpState,
         transmits: &[Transmit],
     ) -> Poll<Result<usize, io::Error>> {
         ready!(self.io.poll_write_ready(cx))?;
-        match self.io.get_ref().send_ext(transmits) {
+        match self.io.get_ref().send_ext(state, transmits) {
             Ok(n) => Poll::Ready(Ok(n)),
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                 self.io.clear_write_ready(cx)?;
@@ -45,13 +46,15 @@ impl UdpSocket {
         }
     }
 
+    /// Receive a batch of messages, as described by `UdpExt::recv_ext`
     pub fn poll_recv(
         &self,
         cx: &mut Context,
         buf: &mut [u8],
-    ) -> Poll<io::Result<(usize, SocketAddr, Option<EcnCodepoint>)>> {
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
         ready!(self.io.poll_read_ready(cx, mio::Ready::readable()))?;
-        match self.io.get_ref().recv_ext(buf) {
+        match self.io.get_ref().recv_ext(buf, meta) {
             Ok(n) => Poll::Ready(Ok(n)),
             Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                 self.io.clear_read_ready(cx, mio::Ready::readable())?;
@@ -66,7 +69,7 @@ impl UdpSocket {
     }
 }
 
-/// Number of UDP packets to send at a time
+/// Number of UDP packets to send or receive at a time
 ///
 /// Chosen somewhat arbitrarily; might benefit from additional tuning.
 pub const BATCH_SIZE: usize = 32;